@@ -35,13 +35,17 @@
 
 #include "eekboard/eekboard-context-service.h"
 #include "src/layout.h"
+#include "src/feedback.h"
 #include "src/popover.h"
+#include "src/preview.h"
 #include "src/submission.h"
 
 #define LIBFEEDBACK_USE_UNSTABLE_API
 #include <libfeedback.h>
 
 #define SQUEEKBOARD_APP_ID "sm.puri.squeekboard"
+/// Known to all feedback themes
+#define FALLBACK_EVENT "button-pressed"
 
 typedef struct _EekGtkKeyboardPrivate
 {
@@ -55,8 +59,10 @@ typedef struct _EekGtkKeyboardPrivate
 
     Layout *keyboard; // unowned reference; it's kept in server-context
 
-    GdkEventSequence *sequence; // unowned reference
-    LfbEvent *event;
+    struct squeek_preview *preview; // owned, nullable
+
+    gboolean feedback_ready; // libfeedback initialized
+    struct squeek_feedback *feedback; // owned, nullable
 
     gulong kb_signal;
 } EekGtkKeyboardPrivate;
@@ -84,6 +90,7 @@ static void set_allocation_size(EekGtkKeyboard *gtk_keyboard,
     // This is where size-dependent surfaces would be released
     EekGtkKeyboardPrivate *priv =
         eek_gtk_keyboard_get_instance_private (gtk_keyboard);
+    squeek_layout_fit (layout, width, height);
     priv->render_geometry = eek_render_geometry_from_allocation_size(
         layout, width, height);
 }
@@ -137,6 +144,24 @@ eek_gtk_keyboard_real_size_allocate (GtkWidget     *self,
         size_allocate (self, allocation);
 }
 
+static void trigger_feedback (const char *name, const char *profile);
+
+static void
+on_feedback_ended (LfbEvent *event,
+                   gpointer  unused)
+{
+    // Ends only once
+    g_signal_handlers_disconnect_by_func (event, on_feedback_ended, unused);
+
+    // The theme doesn't know the event
+    if (lfb_event_get_end_reason (event) == LFB_EVENT_END_REASON_NOT_FOUND
+            && g_strcmp0 (lfb_event_get_event (event), FALLBACK_EVENT) != 0) {
+        g_autofree char *profile = lfb_event_get_feedback_profile (event);
+        trigger_feedback (FALLBACK_EVENT, profile);
+    }
+    g_object_unref (event);
+}
+
 static void
 on_event_triggered (LfbEvent      *event,
                     GAsyncResult  *res,
@@ -148,11 +173,41 @@ on_event_triggered (LfbEvent      *event,
     if (!lfb_event_trigger_feedback_finish (event, res, &err)) {
         g_warning ("Failed to trigger feedback for '%s': %s",
                    lfb_event_get_event (event), err->message);
+        // Release the event unless it ended already
+        if (g_signal_handlers_disconnect_by_func (event, on_feedback_ended, NULL) > 0) {
+            g_object_unref (event);
+        }
+    }
+}
+
+/// The event is released when the feedback ends.
+/// Without a profile, the one set in the feedback daemon applies.
+static void
+trigger_feedback (const char *name, const char *profile)
+{
+    LfbEvent *event = lfb_event_new (name);
+    if (profile) {
+        lfb_event_set_feedback_profile (event, profile);
     }
+    g_signal_connect (event, "feedback-ended",
+                      G_CALLBACK (on_feedback_ended), NULL);
+    lfb_event_trigger_feedback_async (event,
+                                      NULL,
+                                      (GAsyncReadyCallback)on_event_triggered,
+                                      NULL);
+}
+
+/// The pointer, as opposed to touch points
+static const guint32 POINTER_ID = 0;
+
+/// Identifies the touch point. Touch sequences are never NULL.
+static guint32 sequence_id(GdkEventSequence *sequence)
+{
+    return GPOINTER_TO_UINT(sequence);
 }
 
 static void depress(EekGtkKeyboard *self,
-                    gdouble x, gdouble y, guint32 time)
+                    gdouble x, gdouble y, guint32 time, guint32 touch_id)
 {
     EekGtkKeyboardPrivate *priv = eek_gtk_keyboard_get_instance_private (self);
     if (!priv->keyboard) {
@@ -160,11 +215,13 @@ static void depress(EekGtkKeyboard *self,
     }
     squeek_layout_depress(priv->keyboard->layout,
                           priv->submission,
-                          x, y, priv->render_geometry.widget_to_layout, time, self);
+                          x, y, priv->render_geometry.widget_to_layout,
+                          priv->render_geometry.allocation_height,
+                          time, touch_id, self);
 }
 
 static void drag(EekGtkKeyboard *self,
-                 gdouble x, gdouble y, guint32 time)
+                 gdouble x, gdouble y, guint32 time, guint32 touch_id)
 {
     EekGtkKeyboardPrivate *priv = eek_gtk_keyboard_get_instance_private (self);
     if (!priv->keyboard) {
@@ -172,18 +229,18 @@ static void drag(EekGtkKeyboard *self,
     }
     squeek_layout_drag(eekboard_context_service_get_keyboard(priv->eekboard_context)->layout,
                        priv->submission,
-                       x, y, priv->render_geometry.widget_to_layout, time,
+                       x, y, priv->render_geometry.widget_to_layout, time, touch_id,
                        priv->popover, priv->state_manager, self);
 }
 
-static void release(EekGtkKeyboard *self, guint32 time)
+static void release(EekGtkKeyboard *self, guint32 time, guint32 touch_id)
 {
     EekGtkKeyboardPrivate *priv = eek_gtk_keyboard_get_instance_private (self);
     if (!priv->keyboard) {
         return;
     }
     squeek_layout_release(eekboard_context_service_get_keyboard(priv->eekboard_context)->layout,
-                          priv->submission, priv->render_geometry.widget_to_layout, time,
+                          priv->submission, priv->render_geometry.widget_to_layout, time, touch_id,
                           priv->popover, priv->state_manager, self);
 }
 
@@ -192,7 +249,7 @@ eek_gtk_keyboard_real_button_press_event (GtkWidget      *self,
                                           GdkEventButton *event)
 {
     if (event->type == GDK_BUTTON_PRESS && event->button == 1) {
-        depress(EEK_GTK_KEYBOARD(self), event->x, event->y, event->time);
+        depress(EEK_GTK_KEYBOARD(self), event->x, event->y, event->time, POINTER_ID);
     }
     return TRUE;
 }
@@ -204,7 +261,7 @@ eek_gtk_keyboard_real_button_release_event (GtkWidget      *self,
 {
     if (event->type == GDK_BUTTON_RELEASE && event->button == 1) {
         // TODO: can the event have different coords than the previous move event?
-        release(EEK_GTK_KEYBOARD(self), event->time);
+        release(EEK_GTK_KEYBOARD(self), event->time, POINTER_ID);
     }
     return TRUE;
 }
@@ -215,7 +272,7 @@ eek_gtk_keyboard_leave_event (GtkWidget      *self,
 {
     if (event->type == GDK_LEAVE_NOTIFY) {
         // TODO: can the event have different coords than the previous move event?
-        release(EEK_GTK_KEYBOARD(self), event->time);
+        release(EEK_GTK_KEYBOARD(self), event->time, POINTER_ID);
     }
     return TRUE;
 }
@@ -226,40 +283,33 @@ eek_gtk_keyboard_real_motion_notify_event (GtkWidget      *self,
                                            GdkEventMotion *event)
 {
     if (event->state & GDK_BUTTON1_MASK) {
-        drag(EEK_GTK_KEYBOARD(self), event->x, event->y, event->time);
+        drag(EEK_GTK_KEYBOARD(self), event->x, event->y, event->time, POINTER_ID);
     }
     return TRUE;
 }
 
-// Only one touch stream at a time allowed. Others will be completely ignored.
+// Each touch point presses and releases its own buttons.
 static gboolean
 handle_touch_event (GtkWidget     *widget,
                     GdkEventTouch *event)
 {
-    EekGtkKeyboard        *self = EEK_GTK_KEYBOARD (widget);
-    EekGtkKeyboardPrivate *priv = eek_gtk_keyboard_get_instance_private (self);
-
-    /* For each new touch, release the previous one and record the new event
-       sequence. */
-    if (event->type == GDK_TOUCH_BEGIN) {
-        release(self, event->time);
-        priv->sequence = event->sequence;
-        depress(self, event->x, event->y, event->time);
-        return TRUE;
-    }
+    EekGtkKeyboard *self = EEK_GTK_KEYBOARD (widget);
+    guint32 id = sequence_id(event->sequence);
 
-    /* Only allow the latest touch point to be dragged. */
-    if (event->type == GDK_TOUCH_UPDATE && event->sequence == priv->sequence) {
-        drag(self, event->x, event->y, event->time);
-    }
-    else if (event->type == GDK_TOUCH_END || event->type == GDK_TOUCH_CANCEL) {
+    switch (event->type) {
+    case GDK_TOUCH_BEGIN:
+        depress(self, event->x, event->y, event->time, id);
+        break;
+    case GDK_TOUCH_UPDATE:
+        drag(self, event->x, event->y, event->time, id);
+        break;
+    case GDK_TOUCH_END:
+    case GDK_TOUCH_CANCEL:
         // TODO: can the event have different coords than the previous update event?
-        /* Only respond to the release of the latest touch point. Previous
-           touches have already been released. */
-        if (event->sequence == priv->sequence) {
-            release(self, event->time);
-            priv->sequence = NULL;
-        }
+        release(self, event->time, id);
+        break;
+    default:
+        break;
     }
     return TRUE;
 }
@@ -320,9 +370,19 @@ eek_gtk_keyboard_dispose (GObject *object)
         priv->keyboard = NULL;
     }
 
-    if (priv->event) {
-        g_clear_object (&priv->event);
+    if (priv->feedback_ready) {
         lfb_uninit ();
+        priv->feedback_ready = FALSE;
+    }
+
+    if (priv->feedback) {
+        squeek_feedback_free(priv->feedback);
+        priv->feedback = NULL;
+    }
+
+    if (priv->preview) {
+        squeek_preview_free(priv->preview);
+        priv->preview = NULL;
     }
 
     G_OBJECT_CLASS (eek_gtk_keyboard_parent_class)->dispose (object);
@@ -360,7 +420,7 @@ eek_gtk_keyboard_init (EekGtkKeyboard *self)
     g_autoptr(GError) err = NULL;
 
     if (lfb_init(SQUEEKBOARD_APP_ID, &err)) {
-        priv->event = lfb_event_new ("button-pressed");
+        priv->feedback_ready = TRUE;
     } else {
         g_warning ("Failed to init libfeedback: %s", err->message);
     }
@@ -419,6 +479,8 @@ eek_gtk_keyboard_new (EekboardContextService *eekservice,
         },
     };
     priv->render_geometry = initial_geometry;
+    priv->preview = squeek_preview_new(GTK_WIDGET(ret));
+    priv->feedback = squeek_feedback_new();
 
     priv->kb_signal = g_signal_connect (eekservice,
                       "notify::keyboard",
@@ -437,21 +499,56 @@ eek_gtk_keyboard_new (EekboardContextService *eekservice,
 
 /**
  * eek_gtk_keyboard_emit_feedback:
+ * @event: the name of the feedback event
+ * @profile: (nullable): the feedback profile, like "full" or "quiet",
+ *   or NULL to follow the feedback daemon
  *
- * Emit button press haptic feedback via libfeedack.
+ * Emit button press feedback via libfeedack.
+ * Events unknown to the feedback theme fall back to "button-pressed".
  */
 void
-eek_gtk_keyboard_emit_feedback (EekGtkKeyboard *self)
+eek_gtk_keyboard_emit_feedback (EekGtkKeyboard *self,
+                                const char     *event,
+                                const char     *profile)
 {
     EekGtkKeyboardPrivate *priv;
 
     g_return_if_fail (EEK_IS_GTK_KEYBOARD (self));
 
     priv = eek_gtk_keyboard_get_instance_private (EEK_GTK_KEYBOARD (self));
-    if (priv->event) {
-        lfb_event_trigger_feedback_async (priv->event,
-                                          NULL,
-                                          (GAsyncReadyCallback)on_event_triggered,
-                                          NULL);
+    if (priv->feedback_ready) {
+        trigger_feedback (event, profile);
     }
 }
+
+/**
+ * eek_gtk_keyboard_get_feedback:
+ *
+ * Returns: (transfer none) (nullable): the feedback settings
+ */
+struct squeek_feedback *
+eek_gtk_keyboard_get_feedback (EekGtkKeyboard *self)
+{
+    EekGtkKeyboardPrivate *priv;
+
+    g_return_val_if_fail (EEK_IS_GTK_KEYBOARD (self), NULL);
+
+    priv = eek_gtk_keyboard_get_instance_private (EEK_GTK_KEYBOARD (self));
+    return priv->feedback;
+}
+
+/**
+ * eek_gtk_keyboard_get_preview:
+ *
+ * Returns: (transfer none) (nullable): the magnified label of the pressed button
+ */
+struct squeek_preview *
+eek_gtk_keyboard_get_preview (EekGtkKeyboard *self)
+{
+    EekGtkKeyboardPrivate *priv;
+
+    g_return_val_if_fail (EEK_IS_GTK_KEYBOARD (self), NULL);
+
+    priv = eek_gtk_keyboard_get_instance_private (EEK_GTK_KEYBOARD (self));
+    return priv->preview;
+}
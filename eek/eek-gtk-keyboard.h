@@ -30,8 +30,10 @@
 
 #include "eek/eek-renderer.h"
 #include "eek/eek-types.h"
+#include "src/feedback.h"
 #include "src/main.h"
 #include "src/popover.h"
+#include "src/preview.h"
 
 struct submission;
 struct squeek_layout_state;
@@ -51,7 +53,9 @@ struct _EekGtkKeyboardClass
 };
 
 GtkWidget *eek_gtk_keyboard_new       (EekboardContextService *eekservice, struct submission *submission, struct squeek_state_manager *state_manager, struct squeek_popover *popover);
-void       eek_gtk_keyboard_emit_feedback (EekGtkKeyboard *self);
+void       eek_gtk_keyboard_emit_feedback (EekGtkKeyboard *self, const char *event, const char *profile);
+struct squeek_feedback *eek_gtk_keyboard_get_feedback (EekGtkKeyboard *self);
+struct squeek_preview *eek_gtk_keyboard_get_preview (EekGtkKeyboard *self);
 
 G_END_DECLS
 #endif  /* EEK_GTK_KEYBOARD_H */
@@ -315,6 +315,22 @@ renderer_init (EekRenderer *self)
     self->css_provider = squeek_load_style();
 }
 
+/// Returns the style class of the arrangement, or NULL
+static const char *
+get_arrangement_class (enum squeek_arrangement_kind kind)
+{
+    switch (kind) {
+    case ARRANGEMENT_KIND_WIDE:
+        return "wide";
+    case ARRANGEMENT_KIND_NARROW:
+        return "narrow";
+    case ARRANGEMENT_KIND_TABLET:
+        return "tablet";
+    default:
+        return NULL;
+    }
+}
+
 EekRenderer *
 eek_renderer_new (Layout  *keyboard,
                   PangoContext *pcontext)
@@ -324,6 +340,7 @@ eek_renderer_new (Layout  *keyboard,
     renderer->pcontext = pcontext;
     g_object_ref (renderer->pcontext);
     const char *purpose_class = "normal";
+    const char *arrangement_class = get_arrangement_class(squeek_layout_get_kind(keyboard->layout));
 
     /* Create a style context for the layout */
     GtkWidgetPath *path = gtk_widget_path_new();
@@ -332,8 +349,8 @@ eek_renderer_new (Layout  *keyboard,
     renderer->view_context = gtk_style_context_new();
     gtk_style_context_set_path(renderer->view_context, path);
     gtk_widget_path_unref(path);
-    if (squeek_layout_get_kind(keyboard->layout) == ARRANGEMENT_KIND_WIDE) {
-        gtk_style_context_add_class(renderer->view_context, "wide");
+    if (arrangement_class) {
+        gtk_style_context_add_class(renderer->view_context, arrangement_class);
     }
     gtk_style_context_add_class(renderer->view_context, (char*)&keyboard->style_name);
     gtk_style_context_add_provider (renderer->view_context,
@@ -343,8 +360,8 @@ eek_renderer_new (Layout  *keyboard,
     /* Create a style context for the buttons */
     path = gtk_widget_path_new();
     gtk_widget_path_append_type(path, view_type());
-    if (squeek_layout_get_kind(keyboard->layout) == ARRANGEMENT_KIND_WIDE) {
-        gtk_widget_path_iter_add_class(path, -1, "wide");
+    if (arrangement_class) {
+        gtk_widget_path_iter_add_class(path, -1, arrangement_class);
     }
     /* Add style classes based on purpose */
     switch (squeek_layout_get_purpose (keyboard->layout)) {
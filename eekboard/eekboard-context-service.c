@@ -126,10 +126,13 @@ settings_get_layout(GSettings *settings, char **type, char **layout)
     g_variant_unref(inputs);
 }
 
-void eekboard_context_service_set_layout(EekboardContextService *context, char *style_name, struct squeek_layout *layout, uint32_t timestamp) {
+void eekboard_context_service_set_layout(EekboardContextService *context, char *style_name, struct squeek_layout *layout, uint32_t keep_view, uint32_t timestamp) {
+    Layout *previous_keyboard = context->keyboard;
+    if (keep_view && previous_keyboard) {
+        squeek_layout_keep_view(layout, previous_keyboard->layout);
+    }
     Layout *keyboard = layout_new(style_name, layout);
     // set as current
-    Layout *previous_keyboard = context->keyboard;
     context->keyboard = keyboard;
     // Update the keymap if necessary.
     // TODO: Update submission on change event
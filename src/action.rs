@@ -46,9 +46,19 @@ pub enum Action {
         /// The key events this symbol submits when submitting text is not possible
         keys: Vec<KeySym>,
     },
+    /// Combine with the next key press, like a dead key or the compose key
+    Dead {
+        keysym: KeySym,
+        /// Shown until the next key gets pressed
+        label: String,
+    },
     /// Erase a position behind the cursor
     Erase,
     ShowPreferences,
+    /// Dock the keyboard to one side, or back to the whole width
+    ToggleOneHanded,
+    /// Hide the keyboard until the next text field asks for it
+    HideKeyboard,
 }
 
 impl Action {
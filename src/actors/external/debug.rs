@@ -3,6 +3,7 @@
  *
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
+use crate::logging;
 use crate::main;
 use crate::state;
 
@@ -29,6 +30,7 @@ impl Manager {
     #[dbus_interface(property, name = "Enabled")]
     fn set_enabled(&mut self, enabled: bool) {
         self.enabled = enabled;
+        logging::set_debug_mode(enabled);
         self.sender
             .send(state::Event::Debug(
                 if enabled { Event::Enable }
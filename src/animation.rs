@@ -4,16 +4,31 @@
 
 /*! Animation details */
 
-use std::time::Duration;
+use gio;
+use std::time::{ Duration, Instant };
 
+use crate::devices;
 use crate::imservice::ContentPurpose;
 use crate::layout::ArrangementKind;
 use crate::outputs::OutputId;
 use crate::panel::PixelSize;
+use crate::popover;
+
+// Traits
+use gio::prelude::SettingsExt;
 
 /// The keyboard should hide after this has elapsed to prevent flickering.
 pub const HIDING_TIMEOUT: Duration = Duration::from_millis(200);
 
+/// Time between steps of sliding the panel
+pub const FRAME_TIME: Duration = Duration::from_millis(16);
+
+/// In milliseconds
+const SETTINGS_KEY_SLIDE_DURATION: &str = "slide-duration";
+const SYSTEM_SCHEMA: &str = "org.gnome.desktop.interface";
+/// False when the user wants reduced motion
+const SYSTEM_KEY_ANIMATIONS: &str = "enable-animations";
+
 /// Description of parameters which influence panel contents
 #[derive(PartialEq, Clone, Debug)]
 pub struct Contents {
@@ -21,6 +36,147 @@ pub struct Contents {
     pub kind: ArrangementKind,
     pub overlay_name: Option<String>,
     pub purpose: ContentPurpose,
+    /// Physical key size, for gestures measured in keys
+    pub key_size: Option<PixelSize>,
+    /// Rows split in halves at the sides of the screen
+    pub split: bool,
+    /// Edges of the panel to keep free of keys
+    pub safe_area: devices::Insets,
+}
+
+impl Contents {
+    /// Whether both are arrangements of the same layout,
+    /// like the wide and the base one
+    pub fn is_same_source(&self, other: &Contents) -> bool {
+        self.name == other.name
+            && self.overlay_name == other.overlay_name
+            && self.purpose == other.purpose
+    }
+
+    /// Whether both load the same layout file,
+    /// no matter how it's sized
+    pub fn is_same_layout(&self, other: &Contents) -> bool {
+        self.name == other.name
+            && self.kind == other.kind
+            && self.overlay_name == other.overlay_name
+            && self.purpose == other.purpose
+    }
+
+    /// Whether loading the other one would give the same layout.
+    /// The key size is left out:
+    /// it changes with every size the compositor gives, like while dragging,
+    /// but only tunes how far gestures go.
+    pub fn is_same_loaded(&self, other: &Contents) -> bool {
+        Contents { key_size: None, ..self.clone() }
+            == Contents { key_size: None, ..other.clone() }
+    }
+}
+
+/// The panel moving between hidden and shown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slide {
+    /// The part of the panel which was shown when the movement started
+    from: f64,
+    /// Whether the panel is going up
+    pub showing: bool,
+    since: Instant,
+}
+
+impl Slide {
+    pub fn hidden(now: Instant) -> Slide {
+        Slide { from: 0.0, showing: false, since: now }
+    }
+
+    /// The part of the panel shown, from 0 to 1.
+    /// The panel moves at the same speed all the way.
+    pub fn get_progress(&self, now: Instant, duration: Duration) -> f64 {
+        let step = match duration.as_secs_f64() {
+            d if d > 0.0 => now.saturating_duration_since(self.since).as_secs_f64() / d,
+            // Just jump
+            _ => 1.0,
+        };
+        match self.showing {
+            true => (self.from + step).min(1.0),
+            false => (self.from - step).max(0.0),
+        }
+    }
+
+    /// Starts moving the other way from where the panel is
+    pub fn turn(&self, now: Instant, duration: Duration) -> Slide {
+        Slide {
+            from: self.get_progress(now, duration),
+            showing: !self.showing,
+            since: now,
+        }
+    }
+
+    /// When the panel started moving
+    pub fn since(&self) -> Instant {
+        self.since
+    }
+
+    /// When the panel stops moving
+    pub fn get_end(&self, duration: Duration) -> Instant {
+        let distance = match self.showing {
+            true => 1.0 - self.from,
+            false => self.from,
+        };
+        self.since + duration.mul_f64(distance)
+    }
+}
+
+/// Reads how long the panel takes to slide in or out.
+/// No sliding when the user prefers reduced motion.
+pub fn load_slide_duration() -> Duration {
+    get_slide_duration(
+        popover::get_settings(SYSTEM_SCHEMA).as_ref(),
+        popover::get_own_settings().as_ref(),
+    )
+}
+
+fn get_slide_duration(
+    system: Option<&gio::Settings>,
+    own: Option<&gio::Settings>,
+) -> Duration {
+    let animations = system
+        .map(|settings| popover::get_boolean(settings, SYSTEM_KEY_ANIMATIONS))
+        .unwrap_or(true);
+    let millis = own
+        .map(|settings| popover::get_int(settings, SETTINGS_KEY_SLIDE_DURATION))
+        .unwrap_or(0);
+    match animations {
+        true => Duration::from_millis(millis.max(0) as u64),
+        false => Duration::from_millis(0),
+    }
+}
+
+/// Calls back with the new duration whenever the settings affecting it change.
+/// The handlers keep the settings alive for as long as the program runs.
+pub fn watch_slide_duration<F>(notify: F)
+    where F: Fn(Duration) + Clone + 'static
+{
+    let system = popover::get_settings(SYSTEM_SCHEMA);
+    let own = popover::get_own_settings();
+    let watched = vec![
+        (system.clone(), SYSTEM_KEY_ANIMATIONS),
+        (own.clone(), SETTINGS_KEY_SLIDE_DURATION),
+    ];
+    for (settings, key) in watched {
+        if let Some(settings) = settings {
+            let system = system.clone();
+            let own = own.clone();
+            let notify = notify.clone();
+            let handler = move |_: &gio::Settings, changed: &str| {
+                if changed == key {
+                    notify(get_slide_duration(system.as_ref(), own.as_ref()));
+                }
+            };
+            #[cfg(feature = "glib_v0_14")]
+            settings.connect_changed(Some(key), handler);
+            #[cfg(not(feature = "glib_v0_14"))]
+            settings.connect_changed(handler);
+        }
+    }
 }
 
 /// The outwardly visible state of visibility
@@ -30,6 +186,8 @@ pub enum Outcome {
         output: OutputId,
         height: PixelSize,
         contents: Contents,
+        /// The part of the panel above the screen edge, from 0 to 1
+        progress: f64,
     },
     Hidden,
 }
@@ -0,0 +1,293 @@
+/*! Dead keys and compose sequences.
+ *
+ * Uses the XKB Compose tables installed on the system,
+ * so that the sequences are the same as on a hardware keyboard,
+ * including the user's own ~/.XCompose.
+ */
+
+use std::env;
+use std::ffi::OsString;
+use std::mem;
+
+use xkbcommon::xkb;
+use xkbcommon::xkb::compose;
+
+use super::{ Composed, Engine, Input };
+use crate::logging;
+
+// Traits
+use crate::logging::Warn;
+
+
+mod c {
+    extern "C" {
+        // from libxkbcommon, not wrapped in the crate
+        pub fn xkb_utf32_to_keysym(ucs: u32) -> u32;
+    }
+}
+
+/// Finds the keysym which a hardware keyboard would send for this text.
+fn text_to_keysym(text: &str) -> Option<xkb::Keysym> {
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => {
+            match unsafe { c::xkb_utf32_to_keysym(c as u32) } {
+                xkb::KEY_NoSymbol => None,
+                keysym => Some(keysym),
+            }
+        },
+        // Sequences of characters don't correspond to a single keysym
+        _ => None,
+    }
+}
+
+/// Like in libc, the first variable which is set wins.
+fn get_locale() -> OsString {
+    ["LC_ALL", "LC_CTYPE", "LANG"].iter()
+        .filter_map(|name| env::var_os(name))
+        .find(|value| !value.is_empty())
+        .unwrap_or_else(|| "C".into())
+}
+
+fn load_table(locale: &OsString) -> Option<compose::Table> {
+    let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+    compose::Table::new_from_locale(
+        &context,
+        locale,
+        compose::COMPILE_NO_FLAGS,
+    )
+        .map_err(|()| format!("{:?}", locale))
+        .or_print(
+            logging::Problem::Warning,
+            "Can't load the compose table for locale",
+        )
+}
+
+/// The table for the current locale, kept after the first use.
+/// Parsing it takes a while, and layouts get switched often.
+#[derive(Default)]
+pub struct TableCache(Option<(OsString, Option<compose::Table>)>);
+
+impl TableCache {
+    /// Loads the table again only if the locale changed.
+    /// Failures are kept too, so that they get reported once.
+    fn get(&mut self) -> Option<&compose::Table> {
+        let locale = get_locale();
+        let loaded = match &self.0 {
+            Some((loaded_locale, _)) => loaded_locale == &locale,
+            None => false,
+        };
+        if !loaded {
+            let table = load_table(&locale);
+            self.0 = Some((locale, table));
+        }
+        self.0.as_ref().and_then(|(_locale, table)| table.as_ref())
+    }
+}
+
+pub struct Compose {
+    state: compose::State,
+    /// What was typed so far. Sequences can't be decomposed,
+    /// so this is shown as is.
+    preedit: String,
+}
+
+impl Compose {
+    /// Uses the compose table for the current locale.
+    pub fn new(tables: &mut TableCache) -> Option<Compose> {
+        tables.get().map(Compose::from_table)
+    }
+
+    fn from_table(table: &compose::Table) -> Compose {
+        Compose {
+            state: compose::State::new(table, compose::STATE_NO_FLAGS),
+            preedit: String::new(),
+        }
+    }
+
+    /// Advances the sequence.
+    /// `shown` is what the user sees as the typed key.
+    fn advance(&mut self, keysym: xkb::Keysym, shown: &str) -> Option<Composed> {
+        self.state.feed(keysym);
+        match self.state.status() {
+            compose::Status::Nothing => None,
+            compose::Status::Composing => {
+                self.preedit.push_str(shown);
+                Some(Composed {
+                    commit: String::new(),
+                    preedit: self.preedit.clone(),
+                })
+            },
+            compose::Status::Composed => {
+                let text = self.state.utf8()
+                    .or_else(|| {
+                        self.state.keysym().map(|keysym| {
+                            xkb::keysym_to_utf8(keysym)
+                                .trim_end_matches('\0')
+                                .into()
+                        })
+                    })
+                    .unwrap_or_default();
+                self.flush();
+                Some(Composed {
+                    commit: text,
+                    preedit: String::new(),
+                })
+            },
+            // The sequence doesn't exist.
+            // Instead of dropping what was typed like X does,
+            // show it to the user, and start over from this key.
+            compose::Status::Cancelled => {
+                let typed = self.flush();
+                let composed = self.advance(keysym, shown)
+                    .unwrap_or(Composed {
+                        commit: shown.into(),
+                        preedit: String::new(),
+                    });
+                Some(Composed {
+                    commit: typed + &composed.commit,
+                    preedit: composed.preedit,
+                })
+            },
+        }
+    }
+}
+
+impl Engine for Compose {
+    fn feed(&mut self, input: Input) -> Option<Composed> {
+        match input {
+            Input::Text(text) => match text_to_keysym(text) {
+                Some(keysym) => self.advance(keysym, text),
+                None => match self.preedit.is_empty() {
+                    true => None,
+                    false => Some(Composed {
+                        commit: self.flush() + text,
+                        preedit: String::new(),
+                    }),
+                },
+            },
+            Input::Dead { keysym, label } => self.advance(keysym, label),
+            // Sequences are too short to bother erasing one key at a time
+            Input::Erase => match self.preedit.is_empty() {
+                true => None,
+                false => {
+                    self.flush();
+                    Some(Composed {
+                        commit: String::new(),
+                        preedit: String::new(),
+                    })
+                },
+            },
+        }
+    }
+
+    fn flush(&mut self) -> String {
+        self.state.reset();
+        mem::take(&mut self.preedit)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TABLE: &str = "
+<dead_acute> <e> : \"é\" eacute
+<dead_acute> <dead_acute> : \"´\" acute
+<Multi_key> <o> <e> : \"œ\" oe
+<dead_circumflex> <Greek_omega> : \"ῶ\"
+";
+
+    fn compose() -> Compose {
+        let context = xkb::Context::new(xkb::CONTEXT_NO_FLAGS);
+        let table = compose::Table::new_from_buffer(
+            &context,
+            TABLE,
+            &OsString::from("C"),
+            compose::FORMAT_TEXT_V1,
+            compose::COMPILE_NO_FLAGS,
+        ).unwrap();
+        Compose::from_table(&table)
+    }
+
+    fn dead(name: &str) -> xkb::Keysym {
+        xkb::keysym_from_name(name, xkb::KEYSYM_NO_FLAGS)
+    }
+
+    fn composed(commit: &str, preedit: &str) -> Option<Composed> {
+        Some(Composed {
+            commit: commit.into(),
+            preedit: preedit.into(),
+        })
+    }
+
+    #[test]
+    fn dead_key() {
+        let mut compose = compose();
+        assert_eq!(
+            compose.feed(Input::Dead { keysym: dead("dead_acute"), label: "´" }),
+            composed("", "´"),
+        );
+        assert_eq!(compose.feed(Input::Text("e")), composed("é", ""));
+    }
+
+    #[test]
+    fn multi_key() {
+        let mut compose = compose();
+        compose.feed(Input::Dead { keysym: dead("Multi_key"), label: "⎄" });
+        assert_eq!(compose.feed(Input::Text("o")), composed("", "⎄o"));
+        assert_eq!(compose.feed(Input::Text("e")), composed("œ", ""));
+    }
+
+    /// Non-Latin text must be matched against legacy keysyms
+    #[test]
+    fn greek() {
+        let mut compose = compose();
+        compose.feed(Input::Dead { keysym: dead("dead_circumflex"), label: "^" });
+        assert_eq!(compose.feed(Input::Text("ω")), composed("ῶ", ""));
+    }
+
+    #[test]
+    fn not_composing() {
+        let mut compose = compose();
+        assert_eq!(compose.feed(Input::Text("e")), None);
+        assert_eq!(compose.feed(Input::Erase), None);
+    }
+
+    #[test]
+    fn cancelled() {
+        let mut compose = compose();
+        compose.feed(Input::Dead { keysym: dead("dead_acute"), label: "´" });
+        assert_eq!(compose.feed(Input::Text("x")), composed("´x", ""));
+        // The next sequence is not affected
+        compose.feed(Input::Dead { keysym: dead("dead_acute"), label: "´" });
+        assert_eq!(compose.feed(Input::Text("e")), composed("é", ""));
+    }
+
+    /// A dead key breaking a sequence starts a new one
+    #[test]
+    fn cancelled_by_dead() {
+        let mut compose = compose();
+        compose.feed(Input::Dead { keysym: dead("dead_acute"), label: "´" });
+        assert_eq!(
+            compose.feed(Input::Dead { keysym: dead("dead_circumflex"), label: "^" }),
+            composed("´", "^"),
+        );
+    }
+
+    #[test]
+    fn erase() {
+        let mut compose = compose();
+        compose.feed(Input::Dead { keysym: dead("dead_acute"), label: "´" });
+        assert_eq!(compose.feed(Input::Erase), composed("", ""));
+        assert_eq!(compose.feed(Input::Text("e")), None);
+    }
+
+    #[test]
+    fn multiple_characters() {
+        let mut compose = compose();
+        assert_eq!(compose.feed(Input::Text("ch")), None);
+        compose.feed(Input::Dead { keysym: dead("dead_acute"), label: "´" });
+        assert_eq!(compose.feed(Input::Text("ch")), composed("´ch", ""));
+    }
+}
@@ -0,0 +1,345 @@
+/*! Hangul syllable composition out of jamo.
+ *
+ * Follows the 2-set (dubeolsik) rules:
+ * keys carry compatibility jamo,
+ * and the role of a consonant (initial or final)
+ * depends on what comes after it.
+ *
+ * Syllables are kept as the sequence of typed jamo,
+ * so that erasing undoes one key press at a time.
+ */
+
+use super::{ Composed, Engine, Input };
+
+
+/// Compatibility jamo for initial consonants, in syllable order
+const INITIALS: &[char] = &[
+    'ㄱ', 'ㄲ', 'ㄴ', 'ㄷ', 'ㄸ', 'ㄹ', 'ㅁ', 'ㅂ', 'ㅃ', 'ㅅ',
+    'ㅆ', 'ㅇ', 'ㅈ', 'ㅉ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ',
+];
+
+/// Compatibility jamo for vowels, in syllable order
+const MEDIALS: &[char] = &[
+    'ㅏ', 'ㅐ', 'ㅑ', 'ㅒ', 'ㅓ', 'ㅔ', 'ㅕ', 'ㅖ', 'ㅗ', 'ㅘ',
+    'ㅙ', 'ㅚ', 'ㅛ', 'ㅜ', 'ㅝ', 'ㅞ', 'ㅟ', 'ㅠ', 'ㅡ', 'ㅢ',
+    'ㅣ',
+];
+
+/// Compatibility jamo for final consonants, in syllable order.
+/// Index 0 means no final.
+const FINALS: &[char] = &[
+    '\0', 'ㄱ', 'ㄲ', 'ㄳ', 'ㄴ', 'ㄵ', 'ㄶ', 'ㄷ', 'ㄹ', 'ㄺ',
+    'ㄻ', 'ㄼ', 'ㄽ', 'ㄾ', 'ㄿ', 'ㅀ', 'ㅁ', 'ㅂ', 'ㅄ', 'ㅅ',
+    'ㅆ', 'ㅇ', 'ㅈ', 'ㅊ', 'ㅋ', 'ㅌ', 'ㅍ', 'ㅎ',
+];
+
+/// Vowels made of two key presses
+const COMPOUND_MEDIALS: &[(char, char, char)] = &[
+    ('ㅗ', 'ㅏ', 'ㅘ'),
+    ('ㅗ', 'ㅐ', 'ㅙ'),
+    ('ㅗ', 'ㅣ', 'ㅚ'),
+    ('ㅜ', 'ㅓ', 'ㅝ'),
+    ('ㅜ', 'ㅔ', 'ㅞ'),
+    ('ㅜ', 'ㅣ', 'ㅟ'),
+    ('ㅡ', 'ㅣ', 'ㅢ'),
+];
+
+/// Final consonant clusters made of two key presses
+const COMPOUND_FINALS: &[(char, char, char)] = &[
+    ('ㄱ', 'ㅅ', 'ㄳ'),
+    ('ㄴ', 'ㅈ', 'ㄵ'),
+    ('ㄴ', 'ㅎ', 'ㄶ'),
+    ('ㄹ', 'ㄱ', 'ㄺ'),
+    ('ㄹ', 'ㅁ', 'ㄻ'),
+    ('ㄹ', 'ㅂ', 'ㄼ'),
+    ('ㄹ', 'ㅅ', 'ㄽ'),
+    ('ㄹ', 'ㅌ', 'ㄾ'),
+    ('ㄹ', 'ㅍ', 'ㄿ'),
+    ('ㄹ', 'ㅎ', 'ㅀ'),
+    ('ㅂ', 'ㅅ', 'ㅄ'),
+];
+
+const SYLLABLE_BASE: u32 = 0xac00;
+
+fn index_of(table: &[char], jamo: char) -> Option<usize> {
+    table.iter().position(|c| *c == jamo)
+}
+
+fn is_consonant(jamo: char) -> bool {
+    index_of(INITIALS, jamo).is_some()
+}
+
+fn is_vowel(jamo: char) -> bool {
+    index_of(MEDIALS, jamo).is_some()
+}
+
+/// Combines up to two jamo typed one after another.
+/// Returns `None` if they don't form a single jamo.
+fn combine(compounds: &[(char, char, char)], jamo: &[char]) -> Option<char> {
+    match jamo {
+        [single] => Some(*single),
+        [first, second] => compounds.iter()
+            .find(|(a, b, _)| a == first && b == second)
+            .map(|(_, _, combined)| *combined),
+        _ => None,
+    }
+}
+
+/// Jamo in the order they were typed
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Syllable {
+    initial: Option<char>,
+    medial: Vec<char>,
+    final_: Vec<char>,
+}
+
+impl Syllable {
+    fn is_empty(&self) -> bool {
+        self.initial.is_none() && self.medial.is_empty()
+    }
+
+    fn to_string(&self) -> String {
+        let medial = combine(COMPOUND_MEDIALS, &self.medial);
+        let final_ = match self.final_.is_empty() {
+            true => Some('\0'),
+            false => combine(COMPOUND_FINALS, &self.final_),
+        };
+        match (self.initial, medial) {
+            (None, None) => String::new(),
+            (Some(initial), None) => initial.to_string(),
+            // A lone vowel can't have a final
+            (None, Some(medial)) => medial.to_string(),
+            (Some(initial), Some(medial)) => {
+                let l = index_of(INITIALS, initial);
+                let v = index_of(MEDIALS, medial);
+                let t = final_.and_then(|f| index_of(FINALS, f));
+                match (l, v, t) {
+                    (Some(l), Some(v), Some(t)) => {
+                        let code = SYLLABLE_BASE + ((l * 21 + v) * 28 + t) as u32;
+                        std::char::from_u32(code)
+                            .map(|c| c.to_string())
+                            .unwrap_or_default()
+                    },
+                    // Can't happen with the rules in `feed_jamo`
+                    _ => String::new(),
+                }
+            },
+        }
+    }
+
+    /// Removes the last typed jamo
+    fn pop(&mut self) {
+        if self.final_.pop().is_none() {
+            if self.medial.pop().is_none() {
+                self.initial = None;
+            }
+        }
+    }
+}
+
+pub struct Hangul {
+    current: Syllable,
+}
+
+impl Hangul {
+    pub fn new() -> Hangul {
+        Hangul { current: Syllable::default() }
+    }
+
+    /// Adds the jamo to the current syllable,
+    /// returning the syllable which got finished, if any.
+    fn feed_jamo(&mut self, jamo: char) -> String {
+        let current = &mut self.current;
+        let vowel = is_vowel(jamo);
+        let joins = match (current.initial, current.medial.len(), current.final_.len()) {
+            (_, 0, _) if current.initial.is_none() => true,
+            // Initial alone takes a vowel
+            (Some(_), 0, _) => vowel,
+            // Vowel takes a second vowel or a final
+            (_, 1, 0) if vowel => {
+                combine(COMPOUND_MEDIALS, &[current.medial[0], jamo]).is_some()
+            },
+            (Some(_), _, 0) => index_of(FINALS, jamo).is_some(),
+            (Some(_), _, 1) if !vowel => {
+                combine(COMPOUND_FINALS, &[current.final_[0], jamo]).is_some()
+            },
+            _ => false,
+        };
+
+        if joins {
+            match (current.initial, current.medial.is_empty(), vowel) {
+                (None, true, false) => current.initial = Some(jamo),
+                (_, _, true) => current.medial.push(jamo),
+                (_, false, false) => current.final_.push(jamo),
+                // Covered by the check above
+                (Some(_), true, false) => {},
+            };
+            return String::new();
+        }
+
+        // A vowel takes over the last consonant of the previous syllable
+        let next = match (vowel, current.final_.pop()) {
+            (true, Some(consonant)) => Syllable {
+                initial: Some(consonant),
+                medial: vec![jamo],
+                final_: Vec::new(),
+            },
+            (true, None) => Syllable {
+                initial: None,
+                medial: vec![jamo],
+                final_: Vec::new(),
+            },
+            (false, final_) => {
+                if let Some(final_) = final_ {
+                    current.final_.push(final_);
+                }
+                Syllable {
+                    initial: Some(jamo),
+                    medial: Vec::new(),
+                    final_: Vec::new(),
+                }
+            },
+        };
+        let finished = current.to_string();
+        self.current = next;
+        finished
+    }
+}
+
+impl Engine for Hangul {
+    fn feed(&mut self, input: Input) -> Option<Composed> {
+        match input {
+            Input::Text(text) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(jamo), None) if is_consonant(jamo) || is_vowel(jamo) => {
+                        let commit = self.feed_jamo(jamo);
+                        Some(Composed {
+                            commit,
+                            preedit: self.current.to_string(),
+                        })
+                    },
+                    _ => None,
+                }
+            },
+            Input::Erase => match self.current.is_empty() {
+                true => None,
+                false => {
+                    self.current.pop();
+                    Some(Composed {
+                        commit: String::new(),
+                        preedit: self.current.to_string(),
+                    })
+                },
+            },
+            Input::Dead { keysym: _, label: _ } => None,
+        }
+    }
+
+    fn flush(&mut self) -> String {
+        let text = self.current.to_string();
+        self.current = Syllable::default();
+        text
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Returns committed text and the final preedit
+    fn type_text(engine: &mut Hangul, text: &str) -> (String, String) {
+        let mut committed = String::new();
+        let mut preedit = String::new();
+        for c in text.chars() {
+            let s = c.to_string();
+            let input = match c {
+                '⌫' => Input::Erase,
+                _ => Input::Text(&s),
+            };
+            match engine.feed(input) {
+                Some(composed) => {
+                    committed.push_str(&composed.commit);
+                    preedit = composed.preedit;
+                },
+                None => {
+                    committed.push_str(&engine.flush());
+                    committed.push_str(&s);
+                    preedit = String::new();
+                },
+            }
+        }
+        (committed, preedit)
+    }
+
+    fn check(input: &str, committed: &str, preedit: &str) {
+        assert_eq!(
+            type_text(&mut Hangul::new(), input),
+            (committed.into(), preedit.into()),
+            "Input: {}", input,
+        );
+    }
+
+    #[test]
+    fn syllable() {
+        check("ㅎ", "", "ㅎ");
+        check("ㅎㅏ", "", "하");
+        check("ㅎㅏㄴ", "", "한");
+    }
+
+    #[test]
+    fn word() {
+        check("ㅎㅏㄴㄱㅡㄹ", "한", "글");
+    }
+
+    /// The final consonant moves to the next syllable
+    #[test]
+    fn final_moves() {
+        check("ㄱㅏㄱㅏ", "가", "가");
+        check("ㅇㅣㄹㄱㅓ", "일", "거");
+    }
+
+    #[test]
+    fn compound_vowel() {
+        check("ㄱㅗㅏ", "", "과");
+        check("ㅇㅡㅣ", "", "의");
+        // Not a compound
+        check("ㄱㅏㅏ", "가", "ㅏ");
+    }
+
+    #[test]
+    fn compound_final() {
+        check("ㄷㅏㄹㄱ", "", "닭");
+        check("ㄷㅏㄹㄱㅇ", "닭", "ㅇ");
+    }
+
+    #[test]
+    fn no_final() {
+        // ㄸ can't be a final
+        check("ㄱㅏㄸ", "가", "ㄸ");
+    }
+
+    #[test]
+    fn lone_consonants() {
+        check("ㄱㄴ", "ㄱ", "ㄴ");
+    }
+
+    #[test]
+    fn erase() {
+        check("ㄷㅏㄹㄱ⌫", "", "달");
+        check("ㄱㅗㅏ⌫", "", "고");
+        check("ㅎㅏ⌫⌫", "", "");
+        check("ㅎㅏㄴㄱ⌫", "한", "");
+    }
+
+    #[test]
+    fn erase_empty() {
+        assert_eq!(Hangul::new().feed(Input::Erase), None);
+    }
+
+    #[test]
+    fn other_text() {
+        check("ㅎㅏㄴ ", "한 ", "");
+        check("ㅎㅏㄴ1", "한1", "");
+    }
+}
@@ -0,0 +1,307 @@
+/*! Composing text out of multiple key presses.
+ *
+ * Some kinds of input need more than one key press
+ * to produce a piece of text: dead keys, Hangul syllables, transliteration.
+ * The text being built is shown as preedit in the text field,
+ * and it's committed when the engine considers it finished,
+ * or when a key which doesn't take part in composition gets pressed.
+ *
+ * This is a library module.
+ * Engines must not have side effects:
+ * sending the text to the application is up to `submission`.
+ */
+
+mod compose;
+mod hangul;
+mod pinyin;
+mod transliteration;
+
+/// What a key press contributes to the composition
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Input<'a> {
+    Text(&'a str),
+    /// A key which only modifies the next one, like a dead key
+    Dead {
+        keysym: u32,
+        /// Shown while waiting for the next key
+        label: &'a str,
+    },
+    Erase,
+}
+
+/// Selects the engine to use with a layout
+#[derive(Debug, Clone, PartialEq)]
+pub enum Kind {
+    /// Dead keys and compose sequences
+    Compose,
+    /// Korean syllables out of jamo
+    Hangul,
+    /// Chinese characters out of pinyin, picked from a dictionary
+    Pinyin,
+    /// Text converted according to the named table of rules
+    Transliteration(String),
+}
+
+impl Kind {
+    /// Whether the engine offers candidates to pick from the strip
+    pub fn offers_candidates(&self) -> bool {
+        match self {
+            Kind::Pinyin => true,
+            _ => false,
+        }
+    }
+}
+
+/// What engines load, kept for the next ones
+#[derive(Default)]
+pub struct Cache {
+    compose: compose::TableCache,
+}
+
+/// Returns `None` if the engine can't work,
+/// in which case keys get submitted without composition.
+pub fn create_engine(kind: Kind, cache: &mut Cache) -> Option<Box<dyn Engine>> {
+    match kind {
+        Kind::Compose => compose::Compose::new(&mut cache.compose)
+            .map(|engine| Box::new(engine) as Box<dyn Engine>),
+        Kind::Hangul => Some(Box::new(hangul::Hangul::new())),
+        Kind::Pinyin => Some(Box::new(pinyin::Pinyin::new(pinyin::load_dictionary()))),
+        Kind::Transliteration(name) => transliteration::load_table(&name)
+            .map(|table| {
+                Box::new(transliteration::Transliteration::new(table))
+                    as Box<dyn Engine>
+            }),
+    }
+}
+
+/// Text resulting from a key press accepted by the engine
+#[derive(Debug, Clone, PartialEq)]
+pub struct Composed {
+    /// Finished text, to be committed. May be empty.
+    pub commit: String,
+    /// Text still being composed. Empty when the composition ended.
+    pub preedit: String,
+}
+
+/// A state machine turning sequences of key presses into text
+pub trait Engine {
+    /// Returns `None` when the input doesn't take part in composition.
+    /// The engine state must not change in that case.
+    fn feed(&mut self, input: Input) -> Option<Composed>;
+    /// Ends the composition, returning the unfinished text as is.
+    fn flush(&mut self) -> String;
+    /// Text which the user can pick to replace the preedit.
+    /// `None` if the engine never offers any.
+    fn candidates(&self) -> Option<Vec<String>> {
+        None
+    }
+    /// Picks one of the candidates.
+    /// Returns `None` if there's no such candidate.
+    fn select(&mut self, _index: usize) -> Option<Composed> {
+        None
+    }
+}
+
+/// What to do with a key press
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// Update the text field, but don't submit the key
+    Compose(Composed),
+    /// Commit the pending text if there's any, then submit the key as usual
+    Pass { commit: Option<String> },
+}
+
+/// Sits between the layout and the submission of text.
+/// Tracks whether there's anything being composed.
+pub struct Composer {
+    engine: Option<Box<dyn Engine>>,
+    /// The engine holds text which was not committed yet
+    pending: bool,
+}
+
+impl Composer {
+    pub fn new(engine: Option<Box<dyn Engine>>) -> Self {
+        Composer {
+            engine,
+            pending: false,
+        }
+    }
+
+    pub fn is_composing(&self) -> bool {
+        self.pending
+    }
+
+    pub fn feed(&mut self, input: Input) -> Step {
+        let composed = self.engine.as_mut()
+            .and_then(|engine| engine.feed(input));
+        match composed {
+            Some(composed) => {
+                self.pending = !composed.preedit.is_empty();
+                Step::Compose(composed)
+            },
+            None => Step::Pass { commit: self.flush() },
+        }
+    }
+
+    /// Ends the composition.
+    /// Returns the text to commit, if there was anything pending.
+    pub fn flush(&mut self) -> Option<String> {
+        match (self.pending, &mut self.engine) {
+            (true, Some(engine)) => {
+                self.pending = false;
+                Some(engine.flush())
+            },
+            _ => None,
+        }
+    }
+
+    /// `None` if the engine doesn't offer candidates at all.
+    pub fn candidates(&self) -> Option<Vec<String>> {
+        self.engine.as_ref().and_then(|engine| engine.candidates())
+    }
+
+    pub fn select(&mut self, index: usize) -> Option<Composed> {
+        let composed = self.engine.as_mut()
+            .and_then(|engine| engine.select(index));
+        if let Some(composed) = &composed {
+            self.pending = !composed.preedit.is_empty();
+        }
+        composed
+    }
+
+    /// Drops the composition without committing,
+    /// e.g. when the text field it was meant for is gone.
+    pub fn reset(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Composes lowercase ASCII words, commits on "."
+    struct Words(String);
+
+    impl Engine for Words {
+        fn feed(&mut self, input: Input) -> Option<Composed> {
+            match input {
+                Input::Text(".") => Some(Composed {
+                    commit: format!("{}.", self.flush()),
+                    preedit: String::new(),
+                }),
+                Input::Text(t) if t.chars().all(|c| c.is_ascii_lowercase()) => {
+                    self.0.push_str(t);
+                    Some(Composed {
+                        commit: String::new(),
+                        preedit: self.0.clone(),
+                    })
+                },
+                Input::Erase if !self.0.is_empty() => {
+                    self.0.pop();
+                    Some(Composed {
+                        commit: String::new(),
+                        preedit: self.0.clone(),
+                    })
+                },
+                _ => None,
+            }
+        }
+        fn flush(&mut self) -> String {
+            std::mem::take(&mut self.0)
+        }
+    }
+
+    fn composer() -> Composer {
+        Composer::new(Some(Box::new(Words(String::new()))))
+    }
+
+    #[test]
+    fn no_engine() {
+        let mut composer = Composer::new(None);
+        assert_eq!(
+            composer.feed(Input::Text("a")),
+            Step::Pass { commit: None },
+        );
+        assert!(!composer.is_composing());
+    }
+
+    #[test]
+    fn preedit() {
+        let mut composer = composer();
+        composer.feed(Input::Text("a"));
+        assert_eq!(
+            composer.feed(Input::Text("b")),
+            Step::Compose(Composed {
+                commit: String::new(),
+                preedit: "ab".into(),
+            }),
+        );
+        assert!(composer.is_composing());
+    }
+
+    #[test]
+    fn engine_commit() {
+        let mut composer = composer();
+        composer.feed(Input::Text("a"));
+        assert_eq!(
+            composer.feed(Input::Text(".")),
+            Step::Compose(Composed {
+                commit: "a.".into(),
+                preedit: String::new(),
+            }),
+        );
+        assert!(!composer.is_composing());
+    }
+
+    /// A key outside of the composition terminates it
+    #[test]
+    fn terminator() {
+        let mut composer = composer();
+        composer.feed(Input::Text("a"));
+        assert_eq!(
+            composer.feed(Input::Text(" ")),
+            Step::Pass { commit: Some("a".into()) },
+        );
+        assert!(!composer.is_composing());
+        // Nothing left to commit
+        assert_eq!(
+            composer.feed(Input::Text(" ")),
+            Step::Pass { commit: None },
+        );
+    }
+
+    #[test]
+    fn erase() {
+        let mut composer = composer();
+        composer.feed(Input::Text("a"));
+        assert_eq!(
+            composer.feed(Input::Erase),
+            Step::Compose(Composed {
+                commit: String::new(),
+                preedit: String::new(),
+            }),
+        );
+        assert!(!composer.is_composing());
+        // Erasing outside of composition goes to the application
+        assert_eq!(
+            composer.feed(Input::Erase),
+            Step::Pass { commit: None },
+        );
+    }
+
+    #[test]
+    fn reset() {
+        let mut composer = composer();
+        composer.feed(Input::Text("a"));
+        composer.reset();
+        assert_eq!(composer.flush(), None);
+        assert_eq!(
+            composer.feed(Input::Text("b")),
+            Step::Compose(Composed {
+                commit: String::new(),
+                preedit: "b".into(),
+            }),
+        );
+    }
+}
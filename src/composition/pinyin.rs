@@ -0,0 +1,286 @@
+/*! Chinese characters out of pinyin.
+ *
+ * Typed letters stay in the preedit,
+ * and the dictionary entries matching them are offered as candidates.
+ * Picking a candidate commits it, and consumes the pinyin it stands for.
+ * The remaining letters stay in the preedit to be picked next.
+ */
+
+use std::collections::HashMap;
+use std::fs;
+use std::mem;
+
+use super::{ Composed, Engine, Input };
+use crate::logging;
+use crate::resources;
+use crate::xdg;
+
+// Traits
+use crate::logging::Warn;
+
+
+/// Maps pinyin without tones to words, most frequent first
+#[derive(Debug, Default)]
+pub struct Dictionary {
+    entries: HashMap<String, Vec<String>>,
+}
+
+impl Dictionary {
+    /// Each line holds the pinyin, followed by words separated by spaces.
+    /// Lines starting with "#" are comments.
+    pub fn parse(text: &str) -> Dictionary {
+        let mut entries: HashMap<String, Vec<String>> = HashMap::new();
+        let lines = text.lines()
+            .enumerate()
+            .map(|(number, line)| (number + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+        for (number, line) in lines {
+            let mut fields = line.split_whitespace();
+            let pinyin = fields.next().unwrap_or_default();
+            let words: Vec<String> = fields.map(String::from).collect();
+            if words.is_empty() || !pinyin.chars().all(|c| c.is_ascii_lowercase()) {
+                log_print!(
+                    logging::Level::Warning,
+                    "Bad pinyin dictionary entry on line {}: {}",
+                    number, line,
+                );
+                continue;
+            }
+            entries.entry(pinyin.into())
+                .or_insert_with(Vec::new)
+                .extend(words);
+        }
+        Dictionary { entries }
+    }
+
+    /// Words matching the beginning of the pinyin,
+    /// together with the length of the pinyin they match.
+    /// Longer matches come first.
+    fn lookup(&self, pinyin: &str) -> Vec<(String, usize)> {
+        let mut found: Vec<(String, usize)> = Vec::new();
+        // Pinyin is ASCII, so every length is a character boundary
+        for length in (1..=pinyin.len()).rev() {
+            if let Some(words) = self.entries.get(&pinyin[..length]) {
+                for word in words {
+                    if !found.iter().any(|(w, _)| w == word) {
+                        found.push((word.clone(), length));
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Loads the dictionary from the user's data directory,
+/// or uses the builtin one.
+pub fn load_dictionary() -> Dictionary {
+    let path = xdg::data_path("squeekboard/pinyin.txt");
+    let text = path.as_ref()
+        .filter(|path| path.exists())
+        .and_then(|path| {
+            fs::read_to_string(path)
+                .map_err(|e| format!("{:?}: {}", path, e))
+                .or_print(
+                    logging::Problem::Warning,
+                    "Can't read the pinyin dictionary",
+                )
+        });
+    match text {
+        Some(text) => Dictionary::parse(&text),
+        None => Dictionary::parse(resources::PINYIN_DICTIONARY),
+    }
+}
+
+pub struct Pinyin {
+    dictionary: Dictionary,
+    /// Letters not converted yet
+    typed: String,
+}
+
+impl Pinyin {
+    pub fn new(dictionary: Dictionary) -> Pinyin {
+        Pinyin {
+            dictionary,
+            typed: String::new(),
+        }
+    }
+
+    fn preedit(&self) -> Composed {
+        Composed {
+            commit: String::new(),
+            preedit: self.typed.clone(),
+        }
+    }
+}
+
+impl Engine for Pinyin {
+    fn feed(&mut self, input: Input) -> Option<Composed> {
+        match input {
+            Input::Text(text) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_lowercase() => {
+                        self.typed.push(c);
+                        Some(self.preedit())
+                    },
+                    // Space picks the best match
+                    (Some(' '), None) if !self.typed.is_empty() => {
+                        self.select(0).or_else(|| Some(Composed {
+                            commit: self.flush(),
+                            preedit: String::new(),
+                        }))
+                    },
+                    _ => None,
+                }
+            },
+            Input::Erase => match self.typed.pop() {
+                Some(_) => Some(self.preedit()),
+                None => None,
+            },
+            Input::Dead { keysym: _, label: _ } => None,
+        }
+    }
+
+    fn flush(&mut self) -> String {
+        mem::take(&mut self.typed)
+    }
+
+    fn candidates(&self) -> Option<Vec<String>> {
+        Some(
+            self.dictionary.lookup(&self.typed)
+                .into_iter()
+                .map(|(word, _length)| word)
+                .collect()
+        )
+    }
+
+    fn select(&mut self, index: usize) -> Option<Composed> {
+        let (word, length) = self.dictionary.lookup(&self.typed)
+            .into_iter()
+            .nth(index)?;
+        self.typed = self.typed.split_off(length);
+        Some(Composed {
+            commit: word,
+            preedit: self.typed.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DICTIONARY: &str = "
+# comment
+ni 你 呢
+hao 好 号
+nihao 你好
+ma 吗
+";
+
+    fn pinyin() -> Pinyin {
+        Pinyin::new(Dictionary::parse(DICTIONARY))
+    }
+
+    fn type_text(engine: &mut Pinyin, text: &str) {
+        for c in text.chars() {
+            engine.feed(Input::Text(&c.to_string()));
+        }
+    }
+
+    fn composed(commit: &str, preedit: &str) -> Option<Composed> {
+        Some(Composed {
+            commit: commit.into(),
+            preedit: preedit.into(),
+        })
+    }
+
+    #[test]
+    fn preedit() {
+        let mut engine = pinyin();
+        type_text(&mut engine, "n");
+        assert_eq!(engine.feed(Input::Text("i")), composed("", "ni"));
+    }
+
+    /// Longer matches come first, then the shorter ones
+    #[test]
+    fn candidates() {
+        let mut engine = pinyin();
+        type_text(&mut engine, "nihao");
+        assert_eq!(
+            engine.candidates(),
+            Some(vec!["你好".into(), "你".into(), "呢".into()]),
+        );
+    }
+
+    #[test]
+    fn no_candidates() {
+        let mut engine = pinyin();
+        assert_eq!(engine.candidates(), Some(vec![]));
+        type_text(&mut engine, "x");
+        assert_eq!(engine.candidates(), Some(vec![]));
+        assert_eq!(engine.select(0), None);
+    }
+
+    /// Picking a shorter match keeps the rest of the pinyin
+    #[test]
+    fn select_part() {
+        let mut engine = pinyin();
+        type_text(&mut engine, "nihao");
+        assert_eq!(engine.select(2), composed("呢", "hao"));
+        assert_eq!(
+            engine.candidates(),
+            Some(vec!["好".into(), "号".into()]),
+        );
+        assert_eq!(engine.select(0), composed("好", ""));
+    }
+
+    #[test]
+    fn space() {
+        let mut engine = pinyin();
+        type_text(&mut engine, "nima");
+        assert_eq!(engine.feed(Input::Text(" ")), composed("你", "ma"));
+        assert_eq!(engine.feed(Input::Text(" ")), composed("吗", ""));
+        // Nothing to pick, the space gets typed
+        assert_eq!(engine.feed(Input::Text(" ")), None);
+    }
+
+    /// Letters without a match get committed as they are
+    #[test]
+    fn space_unknown() {
+        let mut engine = pinyin();
+        type_text(&mut engine, "xx");
+        assert_eq!(engine.feed(Input::Text(" ")), composed("xx", ""));
+    }
+
+    #[test]
+    fn erase() {
+        let mut engine = pinyin();
+        type_text(&mut engine, "ni");
+        assert_eq!(engine.feed(Input::Erase), composed("", "n"));
+        assert_eq!(engine.feed(Input::Erase), composed("", ""));
+        assert_eq!(engine.feed(Input::Erase), None);
+    }
+
+    #[test]
+    fn other_text() {
+        let mut engine = pinyin();
+        type_text(&mut engine, "ni");
+        assert_eq!(engine.feed(Input::Text("N")), None);
+        assert_eq!(engine.flush(), "ni");
+    }
+
+    #[test]
+    fn bad_entries() {
+        let dictionary = Dictionary::parse("ni\nNI 你\nhao 好");
+        assert_eq!(dictionary.entries.len(), 1);
+    }
+
+    /// The shipped dictionary must parse
+    #[test]
+    fn builtin() {
+        let dictionary = Dictionary::parse(resources::PINYIN_DICTIONARY);
+        assert!(!dictionary.lookup("nihao").is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+/*! Text converted on the fly according to a table of rules.
+ *
+ * Similar to m17n input methods:
+ * typed text stays in the preedit, converted,
+ * for as long as a longer rule could still match it.
+ *
+ * Rules are tried in the order they appear in the table,
+ * and the first one matching the beginning of the typed text wins.
+ * Text not matched by any rule passes unchanged.
+ */
+
+use std::fs;
+use std::mem;
+
+use serde::Deserialize;
+
+use super::{ Composed, Engine, Input };
+use crate::logging;
+use crate::resources;
+use crate::xdg;
+
+// Traits
+use crate::logging::Warn;
+
+
+/// The file describing the rules
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Table {
+    /// Pairs of typed text and the text replacing it
+    rules: Vec<(String, String)>,
+}
+
+impl Table {
+    pub fn from_yaml(text: &str) -> Result<Table, serde_yaml::Error> {
+        serde_yaml::from_str(text)
+    }
+
+    /// True if typing more could still match a rule
+    fn is_incomplete(&self, typed: &str) -> bool {
+        self.rules.iter()
+            .any(|(from, _)| from.len() > typed.len() && from.starts_with(typed))
+    }
+
+    /// Converts the beginning of the text.
+    /// Returns the converted text, and the remaining input.
+    fn convert_start<'a>(&self, typed: &'a str) -> (String, &'a str) {
+        let rule = self.rules.iter()
+            .find(|(from, _)| !from.is_empty() && typed.starts_with(from.as_str()));
+        match rule {
+            Some((from, to)) => (to.clone(), &typed[from.len()..]),
+            None => {
+                let length = typed.chars().next()
+                    .map(|c| c.len_utf8())
+                    .unwrap_or(0);
+                (typed[..length].into(), &typed[length..])
+            },
+        }
+    }
+
+    /// Converts the whole text, as if nothing more was going to be typed
+    fn convert(&self, mut typed: &str) -> String {
+        let mut out = String::new();
+        while !typed.is_empty() {
+            let (converted, rest) = self.convert_start(typed);
+            out.push_str(&converted);
+            typed = rest;
+        }
+        out
+    }
+}
+
+/// Finds the table by name,
+/// first in the user's data directory, then among the builtin ones.
+pub fn load_table(name: &str) -> Option<Table> {
+    let path = xdg::data_path(format!("squeekboard/transliteration/{}.yaml", name));
+    let text = path.as_ref()
+        .filter(|path| path.exists())
+        .and_then(|path| {
+            fs::read_to_string(path)
+                .map_err(|e| format!("{:?}: {}", path, e))
+                .or_print(
+                    logging::Problem::Warning,
+                    "Can't read the transliteration table",
+                )
+        });
+    let text = match text {
+        Some(text) => text,
+        None => resources::get_transliteration(name)
+            .ok_or_else(|| name.to_string())
+            .or_print(
+                logging::Problem::Warning,
+                "No such transliteration table",
+            )?
+            .into(),
+    };
+    Table::from_yaml(&text)
+        .map_err(|e| format!("{}: {}", name, e))
+        .or_print(
+            logging::Problem::Warning,
+            "Bad transliteration table",
+        )
+}
+
+pub struct Transliteration {
+    table: Table,
+    /// Text not converted yet, as typed
+    typed: String,
+}
+
+impl Transliteration {
+    pub fn new(table: Table) -> Transliteration {
+        Transliteration {
+            table,
+            typed: String::new(),
+        }
+    }
+
+    /// Converts whatever can't change any more by typing further.
+    fn advance(&mut self) -> Composed {
+        let mut commit = String::new();
+        while !self.typed.is_empty() && !self.table.is_incomplete(&self.typed) {
+            let (converted, rest) = self.table.convert_start(&self.typed);
+            commit.push_str(&converted);
+            self.typed = rest.into();
+        }
+        Composed {
+            commit,
+            preedit: self.table.convert(&self.typed),
+        }
+    }
+}
+
+impl Engine for Transliteration {
+    fn feed(&mut self, input: Input) -> Option<Composed> {
+        match input {
+            Input::Text(text) => {
+                // Text outside of any rules is left to be submitted as usual
+                let takes_part = !self.typed.is_empty()
+                    || self.table.is_incomplete(text)
+                    || self.table.convert(text) != text;
+                if !takes_part {
+                    return None;
+                }
+                self.typed.push_str(text);
+                Some(self.advance())
+            },
+            // Backspace undoes typed text rather than converted text
+            Input::Erase => self.typed.pop().map(|_| Composed {
+                commit: String::new(),
+                preedit: self.table.convert(&self.typed),
+            }),
+            Input::Dead { keysym: _, label: _ } => None,
+        }
+    }
+
+    fn flush(&mut self) -> String {
+        self.table.convert(&mem::take(&mut self.typed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table(rules: &[(&str, &str)]) -> Table {
+        Table {
+            rules: rules.iter()
+                .map(|(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+        }
+    }
+
+    fn cyrillic() -> Transliteration {
+        Transliteration::new(table(&[
+            ("sht", "щ"),
+            ("sh", "ш"),
+            ("s", "с"),
+            ("h", "х"),
+            ("t", "т"),
+            ("a", "а"),
+        ]))
+    }
+
+    fn composed(commit: &str, preedit: &str) -> Option<Composed> {
+        Some(Composed {
+            commit: commit.into(),
+            preedit: preedit.into(),
+        })
+    }
+
+    #[test]
+    fn single() {
+        let mut engine = cyrillic();
+        assert_eq!(engine.feed(Input::Text("a")), composed("а", ""));
+    }
+
+    /// A rule which may still grow stays in the preedit
+    #[test]
+    fn sequence() {
+        let mut engine = cyrillic();
+        assert_eq!(engine.feed(Input::Text("s")), composed("", "с"));
+        assert_eq!(engine.feed(Input::Text("h")), composed("", "ш"));
+        assert_eq!(engine.feed(Input::Text("t")), composed("щ", ""));
+    }
+
+    /// The longer rule doesn't match, so the shorter ones apply
+    #[test]
+    fn fallback() {
+        let mut engine = cyrillic();
+        engine.feed(Input::Text("s"));
+        engine.feed(Input::Text("h"));
+        assert_eq!(engine.feed(Input::Text("a")), composed("ша", ""));
+    }
+
+    /// Rules listed first win
+    #[test]
+    fn precedence() {
+        let mut engine = Transliteration::new(table(&[
+            ("s", "с"),
+            ("sh", "ш"),
+            ("h", "х"),
+        ]));
+        engine.feed(Input::Text("s"));
+        assert_eq!(engine.feed(Input::Text("h")), composed("сх", ""));
+    }
+
+    /// Of rules for the same text, the first one wins
+    #[test]
+    fn duplicate() {
+        let mut engine = Transliteration::new(table(&[
+            ("a", "а"),
+            ("a", "ъ"),
+        ]));
+        assert_eq!(engine.feed(Input::Text("a")), composed("а", ""));
+    }
+
+    #[test]
+    fn erase() {
+        let mut engine = cyrillic();
+        engine.feed(Input::Text("s"));
+        engine.feed(Input::Text("h"));
+        assert_eq!(engine.feed(Input::Erase), composed("", "с"));
+        assert_eq!(engine.feed(Input::Erase), composed("", ""));
+        // Nothing left to erase in the preedit
+        assert_eq!(engine.feed(Input::Erase), None);
+    }
+
+    /// Converted text can't be erased one typed letter at a time
+    #[test]
+    fn erase_committed() {
+        let mut engine = cyrillic();
+        engine.feed(Input::Text("a"));
+        assert_eq!(engine.feed(Input::Erase), None);
+    }
+
+    #[test]
+    fn unmatched() {
+        let mut engine = cyrillic();
+        assert_eq!(engine.feed(Input::Text("1")), None);
+        engine.feed(Input::Text("s"));
+        // Ends the sequence
+        assert_eq!(engine.feed(Input::Text(" ")), composed("с ", ""));
+    }
+
+    #[test]
+    fn flush() {
+        let mut engine = cyrillic();
+        engine.feed(Input::Text("s"));
+        engine.feed(Input::Text("h"));
+        assert_eq!(engine.flush(), "ш");
+        assert_eq!(engine.feed(Input::Erase), None);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Table::from_yaml("rules:\n    - [\"sh\", \"ш\"]\n").unwrap(),
+            table(&[("sh", "ш")]),
+        );
+    }
+
+    /// The shipped tables must parse
+    #[test]
+    fn builtin() {
+        for name in resources::get_transliterations() {
+            let table = Table::from_yaml(
+                resources::get_transliteration(name).unwrap()
+            );
+            assert!(table.is_ok(), "{}: {:?}", name, table);
+        }
+    }
+}
@@ -49,15 +49,20 @@ impl fmt::Display for DataSource {
 fn _get_arrangement_names(name: &str, arrangement: ArrangementKind)
     -> Vec<(ArrangementKind, String)>
 {
-    let name_with_arrangement = match arrangement {    
-        ArrangementKind::Base => name.into(),
-        ArrangementKind::Wide => format!("{}_wide", name),
+    let mut ret = match arrangement {
+        ArrangementKind::Base => Vec::new(),
+        ArrangementKind::Wide => vec![
+            (ArrangementKind::Wide, format!("{}_wide", name)),
+        ],
+        ArrangementKind::Narrow => vec![
+            (ArrangementKind::Narrow, format!("{}_narrow", name)),
+        ],
+        // Tablets have enough space for the wide layouts
+        ArrangementKind::Tablet => vec![
+            (ArrangementKind::Tablet, format!("{}_tablet", name)),
+            (ArrangementKind::Wide, format!("{}_wide", name)),
+        ],
     };
-    
-    let mut ret = Vec::new();
-    if name_with_arrangement != name {
-        ret.push((arrangement, name_with_arrangement));
-    }
     ret.push((ArrangementKind::Base, name.into()));
     ret
 }
@@ -268,6 +273,7 @@ pub fn load_layout(
 mod tests {
     use super::*;
 
+    use crate::composition;
     use crate::logging::ProblemPanic;
 
     #[test]
@@ -278,6 +284,19 @@ mod tests {
         );
     }
     
+    /// Layouts name transliteration tables which exist
+    #[test]
+    fn builtin_transliteration() {
+        let layout = parsing::Layout::from_resource("bg+phonetic")
+            .map(|layout| layout.build(ProblemPanic).0.unwrap())
+            .unwrap();
+        let kind = layout.composition.unwrap();
+        assert_eq!(kind, composition::Kind::Transliteration("bg".into()));
+        assert!(
+            composition::create_engine(kind, &mut Default::default()).is_some()
+        );
+    }
+
     /// First fallback should be to builtin, not to FALLBACK_LAYOUT_NAME
     #[test]
     fn test_fallback_basic_builtin() {
@@ -356,6 +375,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_preferences_order_tablet() {
+        let sources = iter_layout_sources("nb", ArrangementKind::Tablet, ContentPurpose::Normal, None, None);
+
+        assert_eq!(
+            sources.collect::<Vec<_>>(),
+            vec!(
+                (ArrangementKind::Tablet, DataSource::Resource("nb_tablet".into())),
+                (ArrangementKind::Wide, DataSource::Resource("nb_wide".into())),
+                (ArrangementKind::Base, DataSource::Resource("nb".into())),
+                (
+                    ArrangementKind::Tablet,
+                    DataSource::Resource("us_tablet".into())
+                ),
+                (
+                    ArrangementKind::Wide,
+                    DataSource::Resource("us_wide".into())
+                ),
+                (
+                    ArrangementKind::Base,
+                    DataSource::Resource("us".into())
+                ),
+            )
+        );
+    }
+
     #[test]
     fn test_preferences_order_overlay() {
         let sources = iter_layout_sources("nb", ArrangementKind::Base, ContentPurpose::Normal, Some("terminal"), None);
@@ -15,12 +15,15 @@ use xkbcommon::xkb;
 use super::{ Error, LoadError };
 
 use crate::action;
+use crate::composition;
+use crate::fit;
 use crate::keyboard::{
     Key, generate_keymaps, generate_keycodes, KeyCode, FormattingError
 };
 use crate::layout;
 use crate::logging;
 use crate::resources;
+use crate::targeting;
 
 // traits, derives
 use serde::Deserialize;
@@ -39,7 +42,16 @@ pub struct Layout {
     views: HashMap<String, Vec<ButtonIds>>,
     #[serde(default)] 
     buttons: HashMap<String, ButtonMeta>,
-    outlines: HashMap<String, Outline>
+    outlines: HashMap<String, Outline>,
+    /// Builds text out of multiple key presses.
+    #[serde(default)]
+    composition: Option<Composition>,
+    /// Picks buttons by likelihood instead of strict bounds.
+    #[serde(default)]
+    targeting: Option<Targeting>,
+    /// How the layout fills the panel
+    #[serde(default)]
+    fit: Option<Fit>,
 }
 
 #[derive(Debug, Clone, Deserialize, PartialEq, Default)]
@@ -78,6 +90,9 @@ struct ButtonMeta {
     icon: Option<String>,
     /// The name of the outline. If not present, will be "default"
     outline: Option<String>,
+    /// Dragging over the button moves the cursor.
+    /// If not present, only the space bar does that.
+    cursor_trackpad: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Clone)]
@@ -95,9 +110,84 @@ enum Action {
     SetView(String),
     #[serde(rename="show_prefs")]
     ShowPrefs,
+    #[serde(rename="toggle_one_handed")]
+    ToggleOneHanded,
+    #[serde(rename="hide_keyboard")]
+    HideKeyboard,
     /// Remove last character
     #[serde(rename="erase")]
     Erase,
+    /// Combine with the next key using compose sequences.
+    /// Contains the name of the keysym starting the sequence,
+    /// e.g. "dead_acute" or "Multi_key".
+    #[serde(rename="dead")]
+    Dead(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+enum Composition {
+    /// Korean syllables out of jamo
+    #[serde(rename="hangul")]
+    Hangul,
+    /// Chinese characters picked from pinyin candidates
+    #[serde(rename="pinyin")]
+    Pinyin,
+    /// Contains the name of the table of rules
+    #[serde(rename="transliteration")]
+    Transliteration(String),
+}
+
+impl From<Composition> for composition::Kind {
+    fn from(c: Composition) -> Self {
+        match c {
+            Composition::Hangul => composition::Kind::Hangul,
+            Composition::Pinyin => composition::Kind::Pinyin,
+            Composition::Transliteration(name) => {
+                composition::Kind::Transliteration(name)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Targeting {
+    /// How far below the aimed at point fingers land, in layout units
+    #[serde(default)]
+    touch_offset: f64,
+}
+
+impl From<Targeting> for targeting::Model {
+    fn from(t: Targeting) -> Self {
+        targeting::Model { touch_offset: t.touch_offset }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+enum Fit {
+    #[serde(rename="auto")]
+    Auto,
+    #[serde(rename="stretch")]
+    Stretch,
+    #[serde(rename="center")]
+    Center,
+    #[serde(rename="bottom")]
+    Bottom,
+    #[serde(rename="stretch_keys")]
+    StretchKeys,
+}
+
+impl From<Fit> for fit::Mode {
+    fn from(f: Fit) -> Self {
+        match f {
+            Fit::Auto => fit::Mode::Auto,
+            Fit::Stretch => fit::Mode::Stretch,
+            Fit::Center => fit::Mode::Center,
+            Fit::Bottom => fit::Mode::Bottom,
+            Fit::StretchKeys => fit::Mode::StretchKeys,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Deserialize)]
@@ -159,6 +249,7 @@ impl Layout {
             .flat_map(|rows| {
                 rows.iter()
                     .flat_map(|row| row.split_ascii_whitespace())
+                    .filter(|name| *name != SPLIT_MARKER)
             });
         
         let button_names: HashSet<&str>
@@ -175,10 +266,62 @@ impl Layout {
                 )
             )}).collect();
 
+        // Dead keys need compose sequences to do anything
+        let has_dead = button_actions.iter()
+            .any(|(_name, action)| match action {
+                action::Action::Dead { keysym: _, label: _ } => true,
+                _ => false,
+            });
+        let composition = match (self.composition.clone(), has_dead) {
+            (Some(kind), true) => {
+                warning_handler.handle(
+                    logging::Level::Warning,
+                    &format!(
+                        "Dead keys don't work with composition {:?}",
+                        kind,
+                    ),
+                );
+                Some(kind.into())
+            },
+            (Some(kind), false) => Some(kind.into()),
+            (None, true) => Some(composition::Kind::Compose),
+            (None, false) => None,
+        };
+
+        let trackpads: HashSet<&str> = button_actions.iter()
+            .filter(|(name, action)| {
+                self.buttons.get(*name)
+                    .and_then(|meta| meta.cursor_trackpad)
+                    .unwrap_or_else(|| is_space(action))
+            })
+            .map(|(name, _action)| *name)
+            .collect();
+
+        // Moving the cursor needs the arrow keys
+        let cursor_symbols = match trackpads.is_empty() {
+            true => Vec::new(),
+            false => CURSOR_KEYSYMS.iter().map(|name| name.to_string()).collect(),
+        };
+
         let symbolmap: HashMap<String, KeyCode> = generate_keycodes(
-            extract_symbol_names(&button_actions)
+            extract_symbol_names(&button_actions).chain(cursor_symbols)
         );
 
+        let cursor_keys = match trackpads.is_empty() {
+            true => None,
+            false => {
+                let get = |name| symbolmap.get(name)
+                    .expect(&format!("{} missing from symbol map", name))
+                    .clone();
+                Some(layout::CursorKeys {
+                    left: get("Left"),
+                    right: get("Right"),
+                    up: get("Up"),
+                    down: get("Down"),
+                })
+            },
+        };
+
         let button_states = HashMap::<String, Key>::from_iter(
             button_actions.into_iter().map(|(name, action)| {
                 let keycodes = match &action {
@@ -200,6 +343,11 @@ impl Layout {
                             .expect(&format!("BackSpace missing from symbol map"))
                             .clone(),
                     ],
+                    action::Action::Dead { keysym, label: _ } => vec![
+                        symbolmap.get(keysym.0.as_str())
+                            .expect(&format!("{} missing from symbol map", keysym.0))
+                            .clone(),
+                    ],
                     _ => Vec::new(),
                 };
                 (
@@ -222,7 +370,21 @@ impl Layout {
         let views: Vec<_> = self.views.iter()
             .map(|(name, view)| {
                 let rows = view.iter().map(|row| {
-                    let buttons = row.split_ascii_whitespace()
+                    let names: Vec<&str> = row.split_ascii_whitespace()
+                        .collect();
+                    let split = names.iter()
+                        .position(|name| *name == SPLIT_MARKER);
+                    if names.iter().filter(|name| **name == SPLIT_MARKER).count() > 1 {
+                        warning_handler.handle(
+                            logging::Level::Warning,
+                            &format!(
+                                "Row \"{}\" has more than one split point, using the first",
+                                row,
+                            ),
+                        );
+                    }
+                    let buttons = names.into_iter()
+                        .filter(|name| *name != SPLIT_MARKER)
                         .map(|name| {
                             create_button(
                                 &self.buttons,
@@ -231,15 +393,23 @@ impl Layout {
                                 button_states_cache.get(name.into())
                                     .expect("Button state not created")
                                     .clone(),
+                                match trackpads.contains(name) {
+                                    true => cursor_keys.clone(),
+                                    false => None,
+                                },
                                 &mut warning_handler,
                             )
                         });
-                    layout::Row::new(
+                    let row = layout::Row::new(
                         add_offsets(
                             buttons,
                             |button| button.size.width,
                         ).collect()
-                    )
+                    );
+                    match split {
+                        Some(index) => row.with_split(index),
+                        None => row,
+                    }
                 });
                 let rows = add_offsets(rows, |row| row.get_size().height)
                     .collect();
@@ -249,23 +419,7 @@ impl Layout {
                 )
             }).collect();
 
-        // Center views on the same point.
-        let views = {
-            let total_size = layout::View::calculate_super_size(
-                views.iter().map(|(_name, view)| view).collect()
-            );
-
-            HashMap::from_iter(views.into_iter().map(|(name, view)| (
-                name,
-                (
-                    layout::c::Point {
-                        x: (total_size.width - view.get_size().width) / 2.0,
-                        y: (total_size.height - view.get_size().height) / 2.0,
-                    },
-                    view,
-                ),
-            )))
-        };
+        let views = layout::View::center_all(views);
 
         (
             Ok(layout::LayoutParseData {
@@ -281,6 +435,9 @@ impl Layout {
                     bottom: self.margins.bottom,
                     right: self.margins.side,
                 },
+                composition,
+                targeting: self.targeting.clone().map(targeting::Model::from),
+                fit: self.fit.map(fit::Mode::from).unwrap_or_default(),
             }),
             warning_handler,
         )
@@ -385,7 +542,31 @@ fn create_action<H: logging::Handler>(
         SubmitData::Action(
             Action::ShowPrefs
         ) => crate::action::Action::ShowPreferences,
+        SubmitData::Action(
+            Action::ToggleOneHanded
+        ) => crate::action::Action::ToggleOneHanded,
+        SubmitData::Action(
+            Action::HideKeyboard
+        ) => crate::action::Action::HideKeyboard,
         SubmitData::Action(Action::Erase) => action::Action::Erase,
+        SubmitData::Action(Action::Dead(keysym)) => action::Action::Dead {
+            keysym: action::KeySym(
+                match keysym_valid(keysym.as_str()) {
+                    true => keysym.clone(),
+                    false => {
+                        warning_handler.handle(
+                            logging::Level::Warning,
+                            &format!(
+                                "Keysym name invalid: {}",
+                                keysym,
+                            ),
+                        );
+                        "Multi_key".into() // placeholder
+                    },
+                }
+            ),
+            label: symbol_meta.label.clone().unwrap_or_else(|| name.into()),
+        },
         SubmitData::Keysym(keysym) => crate::action::Action::Submit {
             text: None,
             keys: vec!(crate::action::KeySym(
@@ -451,6 +632,7 @@ fn create_button<H: logging::Handler>(
     outlines: &HashMap<String, Outline>,
     name: &str,
     data: Key,
+    cursor_keys: Option<layout::CursorKeys>,
     warning_handler: &mut H,
 ) -> crate::layout::Button {
     let cname = CString::new(name.clone())
@@ -514,6 +696,22 @@ fn create_button<H: logging::Handler>(
         label: label,
         action: data.action,
         keycodes: data.keycodes,
+        cursor_keys,
+    }
+}
+
+/// Separates the left and right halves of a split row
+const SPLIT_MARKER: &str = "||";
+
+/// Sent by trackpad buttons
+const CURSOR_KEYSYMS: [&str; 4] = ["Left", "Right", "Up", "Down"];
+
+fn is_space(action: &action::Action) -> bool {
+    match action {
+        action::Action::Submit { text: Some(text), keys: _ } => {
+            text.as_bytes() == b" "
+        },
+        _ => false,
     }
 }
 
@@ -527,6 +725,7 @@ fn extract_symbol_names<'a>(actions: &'a [(&str, action::Action)])
                     text: _, keys,
                 } => Some(keys.clone()),
                 action::Action::Erase => Some(vec!(action::KeySym("BackSpace".into()))),
+                action::Action::Dead { keysym, label: _ } => Some(vec!(keysym.clone())),
                 _ => None,
             }
         })
@@ -578,11 +777,15 @@ mod tests {
                         modifier: None,
                         label: Some("test".into()),
                         outline: None,
+                        cursor_trackpad: None,
                     }
                 },
                 outlines: hashmap!{
                     "default".into() => Outline { width: 0f64, height: 0f64 }, 
                 },
+                composition: None,
+                targeting: None,
+                fit: None,
             }
         );
     }
@@ -688,6 +891,109 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_layout_hide() {
+        let out = Layout::from_file(path_from_root("tests/layout_hide.yaml"))
+            .unwrap()
+            .build(ProblemPanic).0
+            .unwrap();
+        assert_eq!(
+            out.views["base"].1
+                .get_rows()[0].1
+                .get_buttons()[0].1
+                .action,
+            crate::action::Action::HideKeyboard,
+        );
+    }
+
+    /// Dead keys need a keycode to fall back on, and enable composition
+    #[test]
+    fn test_layout_dead() {
+        let out = Layout::from_file(path_from_root("tests/layout_dead.yaml"))
+            .unwrap()
+            .build(ProblemPanic).0
+            .unwrap();
+        let button = &out.views["base"].1
+            .get_rows()[0].1
+            .get_buttons()[0].1;
+        assert_eq!(
+            button.action,
+            crate::action::Action::Dead {
+                keysym: crate::action::KeySym("dead_acute".into()),
+                label: "´".into(),
+            },
+        );
+        assert_eq!(button.keycodes.len(), 1);
+        assert_eq!(out.composition, Some(composition::Kind::Compose));
+    }
+
+    #[test]
+    fn test_layout_transliteration() {
+        let out = Layout::from_file(path_from_root("tests/layout_transliteration.yaml"))
+            .unwrap()
+            .build(ProblemPanic).0
+            .unwrap();
+        assert_eq!(
+            out.composition,
+            Some(composition::Kind::Transliteration("bg".into())),
+        );
+    }
+
+    #[test]
+    fn test_layout_trackpad() {
+        let out = Layout::from_file(path_from_root("tests/layout_trackpad.yaml"))
+            .unwrap()
+            .build(ProblemPanic).0
+            .unwrap();
+        let buttons = &out.views["base"].1.get_rows()[0].1.get_buttons();
+        let cursor_keys: Vec<bool> = buttons.iter()
+            .map(|(_offset, button)| button.cursor_keys.is_some())
+            .collect();
+        // space, nbsp, narrow, arrows
+        assert_eq!(cursor_keys, vec![true, false, false, true]);
+    }
+
+    #[test]
+    fn test_layout_fit() {
+        let out = Layout::from_file(path_from_root("tests/layout_fit.yaml"))
+            .unwrap()
+            .build(ProblemPanic).0
+            .unwrap();
+        assert_eq!(out.fit, fit::Mode::StretchKeys);
+    }
+
+    #[test]
+    fn test_layout_targeting() {
+        let out = Layout::from_file(path_from_root("tests/layout_targeting.yaml"))
+            .unwrap()
+            .build(ProblemPanic).0
+            .unwrap();
+        assert_eq!(
+            out.targeting,
+            Some(targeting::Model { touch_offset: 2.5 }),
+        );
+    }
+
+    #[test]
+    fn test_layout_split() {
+        let out = Layout::from_file(path_from_root("tests/layout_split.yaml"))
+            .unwrap()
+            .build(ProblemPanic).0
+            .unwrap();
+        let mut layout = layout::Layout::new(
+            out,
+            layout::ArrangementKind::Base,
+            crate::imservice::ContentPurpose::Normal,
+        );
+        layout.shape.split = true;
+        layout.shape.fit(layout::Size { width: 100.0, height: 10.0 });
+        let offsets: Vec<f64> = layout.get_current_view().get_rows()[0].1
+            .get_buttons().iter()
+            .map(|(offset, _button)| *offset)
+            .collect();
+        assert_eq!(offsets, vec![0.0, 10.0, 90.0]);
+    }
+
     #[test]
     fn unicode_keysym() {
         let keysym = xkb::keysym_from_name(
@@ -711,6 +1017,7 @@ mod tests {
                         modifier: None,
                         label: Some("test".into()),
                         outline: None,
+                        cursor_trackpad: None,
                     }
                 },
                 ".",
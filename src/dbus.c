@@ -64,6 +64,17 @@ handle_set_visible(SmPuriOSK0 *object, GDBusMethodInvocation *invocation,
     return TRUE;
 }
 
+static gboolean
+handle_set_output(SmPuriOSK0 *object, GDBusMethodInvocation *invocation,
+                  const gchar *arg_name, gpointer user_data) {
+    DBusHandler *service = user_data;
+
+    squeek_state_send_output_pinned(service->state_manager, arg_name);
+
+    sm_puri_osk0_complete_set_output(object, invocation);
+    return TRUE;
+}
+
 DBusHandler *
 dbus_handler_new (GDBusConnection *connection,
                       const gchar     *object_path,
@@ -77,6 +88,8 @@ dbus_handler_new (GDBusConnection *connection,
     self->dbus_interface = sm_puri_osk0_skeleton_new();
     g_signal_connect(self->dbus_interface, "handle-set-visible",
                      G_CALLBACK(handle_set_visible), self);
+    g_signal_connect(self->dbus_interface, "handle-set-output",
+                     G_CALLBACK(handle_set_output), self);
 
     if (self->connection && self->object_path) {
         GError *error = NULL;
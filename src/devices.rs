@@ -0,0 +1,360 @@
+/* Copyright (C) 2022 Purism SPC
+ * SPDX-License-Identifier: GPL-3.0+
+ */
+
+/*! Adjustments for specific devices.
+ *
+ * Outputs don't describe everything needed to place the keyboard well,
+ * for example rounded corners and notches cutting into the panel.
+ * Some don't even report their size correctly.
+ * Those get described in files in the `squeekboard/devices` data directory,
+ * one device per file, matched by the output's make, model, or name.
+ */
+
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+
+use crate::layout::ArrangementKind;
+use crate::logging;
+use crate::outputs::{ Geometry, Millimeter, OutputState, Size, c };
+use crate::xdg;
+
+// traits, derives
+use serde::Deserialize;
+use std::io::BufReader;
+use crate::logging::Warn;
+
+
+/// Space at the edges of the panel which the layout must not cover,
+/// in scaled pixels
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Insets {
+    pub top: f64,
+    pub bottom: f64,
+    pub left: f64,
+    pub right: f64,
+}
+
+/// Which outputs a device description applies to.
+/// All given values must be equal to those of the output.
+#[derive(Debug, Clone, Deserialize, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Match {
+    make: Option<String>,
+    model: Option<String>,
+    /// Connector name, like "DSI-1"
+    name: Option<String>,
+}
+
+impl Match {
+    fn matches(&self, output: &OutputState) -> bool {
+        fn is_equal(wanted: &Option<String>, value: &Option<String>) -> bool {
+            match wanted {
+                None => true,
+                Some(_) => wanted == value,
+            }
+        }
+        // An empty match would apply to every output, which is surely a mistake
+        let is_empty = self.make.is_none()
+            && self.model.is_none()
+            && self.name.is_none();
+        !is_empty
+            && is_equal(&self.make, &output.make)
+            && is_equal(&self.model, &output.model)
+            && is_equal(&self.name, &output.name)
+    }
+}
+
+/// Dimensions of the screen in its natural orientation, in millimeters
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct PhysicalSize {
+    width: i32,
+    height: i32,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all="lowercase")]
+enum Arrangement {
+    Base,
+    Wide,
+    Narrow,
+    Tablet,
+}
+
+impl From<Arrangement> for ArrangementKind {
+    fn from(arrangement: Arrangement) -> ArrangementKind {
+        match arrangement {
+            Arrangement::Base => ArrangementKind::Base,
+            Arrangement::Wide => ArrangementKind::Wide,
+            Arrangement::Narrow => ArrangementKind::Narrow,
+            Arrangement::Tablet => ArrangementKind::Tablet,
+        }
+    }
+}
+
+/// The contents of a device file
+#[derive(Debug, Clone, Deserialize, PartialEq, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Device {
+    #[serde(rename="match")]
+    matches: Match,
+    /// Keeps the layout away from rounded corners and notches
+    #[serde(default)]
+    pub safe_area: Insets,
+    /// Replaces the size reported by the output
+    #[serde(default)]
+    physical_size: Option<PhysicalSize>,
+    /// Pixels per millimeter.
+    /// Replaces the size reported by the output,
+    /// unless `physical_size` is given.
+    #[serde(default)]
+    density: Option<f64>,
+    /// Used instead of guessing from the shape of the screen
+    #[serde(default)]
+    arrangement: Option<Arrangement>,
+    /// Height of a key in millimeters, unless the user chooses one
+    #[serde(default)]
+    pub key_height: Option<f64>,
+}
+
+impl Device {
+    pub fn get_arrangement(&self) -> Option<ArrangementKind> {
+        self.arrangement.map(ArrangementKind::from)
+    }
+
+    /// The physical size from the file, in millimeters.
+    /// The mode is not rotated, just like the physical size.
+    fn get_physical_size(&self, output: &OutputState) -> Option<(i32, i32)> {
+        match (self.physical_size, self.density, &output.current_mode) {
+            (Some(PhysicalSize { width, height }), _, _) => Some((width, height)),
+            (None, Some(density), Some(mode)) if density > 0.0 => Some((
+                (mode.width as f64 / density).round() as i32,
+                (mode.height as f64 / density).round() as i32,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Logs what the file overrides
+    fn report(&self, output: &OutputState) {
+        if let Some(arrangement) = self.arrangement {
+            log_print!(
+                logging::Level::Info,
+                "Output {:?} {:?}: using arrangement {:?} from the device file",
+                output.make, output.model,
+                arrangement,
+            );
+        }
+        if let Some(key_height) = self.key_height {
+            log_print!(
+                logging::Level::Info,
+                "Output {:?} {:?}: using key height {}mm from the device file",
+                output.make, output.model,
+                key_height,
+            );
+        }
+        if let Some((width, height)) = self.get_physical_size(output) {
+            log_print!(
+                logging::Level::Info,
+                "Output {:?} {:?}: using physical size {}x{}mm from the device file",
+                output.make, output.model,
+                width, height,
+            );
+        }
+    }
+
+    /// Replaces the physical size of the output with the one from the file
+    fn adjust(&self, output: OutputState) -> OutputState {
+        match self.get_physical_size(&output) {
+            Some((width, height)) => {
+                let geometry = Geometry {
+                    phys_size: Size {
+                        width: Some(Millimeter(width)),
+                        height: Some(Millimeter(height)),
+                    },
+                    transform: output.geometry
+                        .map(|geometry| geometry.transform)
+                        .unwrap_or(c::Transform::Normal),
+                };
+                OutputState { geometry: Some(geometry), ..output }
+            },
+            None => output,
+        }
+    }
+
+    fn from_file(path: &Path) -> Result<Device, String> {
+        let infile = BufReader::new(
+            fs::File::open(path).map_err(|e| format!("{:?}: {}", path, e))?
+        );
+        serde_yaml::from_reader(infile)
+            .map_err(|e| format!("{:?}: {}", path, e))
+    }
+}
+
+/// All known devices
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Database(Vec<Device>);
+
+impl Database {
+    pub fn new(devices: Vec<Device>) -> Database {
+        Database(devices)
+    }
+
+    /// Reads all device files.
+    /// The directory can be overridden with `SQUEEKBOARD_DEVICESDIR`.
+    pub fn load() -> Database {
+        let path = env::var_os("SQUEEKBOARD_DEVICESDIR")
+            .map(PathBuf::from)
+            .or_else(|| xdg::data_path("squeekboard/devices"));
+        match path {
+            Some(path) => Self::load_from(&path),
+            None => Default::default(),
+        }
+    }
+
+    fn load_from(path: &Path) -> Database {
+        if !path.exists() {
+            log_print!(
+                logging::Level::Debug,
+                "No device files in {:?}",
+                path,
+            );
+            return Default::default();
+        }
+        let mut files: Vec<PathBuf> = fs::read_dir(path)
+            .map_err(|e| format!("{:?}: {}", path, e))
+            .or_print(logging::Problem::Warning, "Can't list device files")
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().map_or(false, |ext| ext == "yaml"))
+            .collect();
+        // The first matching file wins, so the order must not be random
+        files.sort();
+        Database(
+            files.iter()
+                .filter_map(|path| {
+                    Device::from_file(path)
+                        .or_print(
+                            logging::Problem::Warning,
+                            "Can't load device file",
+                        )
+                })
+                .collect()
+        )
+    }
+
+    /// Returns the description applying to the output, if any
+    pub fn find(&self, output: &OutputState) -> Option<&Device> {
+        self.0.iter().find(|device| device.matches.matches(output))
+    }
+
+    /// Applies the overrides of the physical size.
+    /// The others are looked up with `find` when needed.
+    pub fn adjust(&self, output: OutputState) -> OutputState {
+        match self.find(&output) {
+            Some(device) => device.adjust(output),
+            None => output,
+        }
+    }
+
+    /// Logs the overrides applying to the output
+    pub fn report(&self, output: &OutputState) {
+        if let Some(device) = self.find(output) {
+            device.report(output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn output(make: &str, model: &str) -> OutputState {
+        OutputState {
+            current_mode: None,
+            geometry: None,
+            scale: 1,
+            fractional_scale: None,
+            name: Some("DSI-1".into()),
+            description: None,
+            make: Some(make.into()),
+            model: Some(model.into()),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        let device: Device = serde_yaml::from_str("
+match:
+    make: Purism
+    model: Librem 5
+safe_area:
+    bottom: 8
+").unwrap();
+        assert_eq!(
+            device.safe_area,
+            Insets { top: 0.0, bottom: 8.0, left: 0.0, right: 0.0 },
+        );
+    }
+
+    #[test]
+    fn find() {
+        let db = Database::new(vec![
+            Device {
+                matches: Match {
+                    make: Some("Purism".into()),
+                    model: Some("Librem 5".into()),
+                    name: None,
+                },
+                safe_area: Insets { bottom: 8.0, ..Default::default() },
+                ..Default::default()
+            },
+            Device {
+                matches: Match::default(),
+                safe_area: Insets { top: 1.0, ..Default::default() },
+                ..Default::default()
+            },
+        ]);
+        assert_eq!(
+            db.find(&output("Purism", "Librem 5")).map(|d| d.safe_area.bottom),
+            Some(8.0),
+        );
+        // The empty match doesn't catch everything else
+        assert_eq!(db.find(&output("Purism", "Librem 13")), None);
+    }
+
+    /// Density replaces a missing physical size
+    #[test]
+    fn adjust_density() {
+        use crate::outputs::Mode;
+        let device: Device = serde_yaml::from_str("
+match:
+    model: Virtual-1
+density: 10
+arrangement: wide
+").unwrap();
+        let db = Database::new(vec![device]);
+        let adjusted = db.adjust(OutputState {
+            current_mode: Some(Mode { width: 1280, height: 800 }),
+            ..output("Unknown", "Virtual-1")
+        });
+        let size = adjusted.get_physical_size().unwrap();
+        assert_eq!(
+            (size.width.map(|mm| mm.0), size.height.map(|mm| mm.0)),
+            (Some(128), Some(80)),
+        );
+        assert_eq!(
+            db.find(&adjusted).and_then(Device::get_arrangement),
+            Some(ArrangementKind::Wide),
+        );
+
+        // Other outputs stay as they were
+        let other = db.adjust(output("Purism", "Librem 5"));
+        assert!(other.get_physical_size().is_none());
+    }
+}
@@ -0,0 +1,8 @@
+#pragma once
+
+// Defined in Rust
+/// Picks the feedback for each pressed button
+struct squeek_feedback;
+
+struct squeek_feedback *squeek_feedback_new(void);
+void squeek_feedback_free(struct squeek_feedback *feedback);
@@ -0,0 +1,258 @@
+/*! Haptic and sound feedback on button presses.
+ *
+ * Feedback is given by the feedback daemon,
+ * which turns event names into vibrations, sounds, or LED blinks,
+ * depending on its theme.
+ * Each kind of button sends a different event,
+ * so that themes can tell letters from erasing or switching views.
+ * Themes which don't know an event fall back to "button-pressed".
+ *
+ * The profile selects how much feedback there is,
+ * with the same meaning as in the feedback daemon.
+ * By default, the profile set in the feedback daemon applies.
+ */
+
+use gio;
+use std::ffi::CString;
+use std::ptr;
+
+use crate::action::Action;
+use crate::layout::c::EekGtkKeyboard;
+use crate::popover;
+
+
+const SETTINGS_KEY: &str = "feedback-profile";
+
+pub mod c {
+    use super::*;
+
+    use std::os::raw::c_char;
+
+    extern "C" {
+        #[allow(improper_ctypes)]
+        pub fn eek_gtk_keyboard_emit_feedback(
+            keyboard: EekGtkKeyboard,
+            event: *const c_char,
+            profile: *const c_char,
+        );
+        #[allow(improper_ctypes)]
+        pub fn eek_gtk_keyboard_get_feedback(
+            keyboard: EekGtkKeyboard,
+        ) -> Feedback;
+    }
+
+    /// Owned by the keyboard widget
+    #[repr(transparent)]
+    pub struct Feedback(*mut super::Feedback);
+
+    impl Feedback {
+        pub fn get(&self) -> Option<&super::Feedback> {
+            unsafe { self.0.as_ref() }
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_feedback_new() -> Feedback {
+        Feedback(Box::into_raw(Box::new(super::Feedback::new())))
+    }
+
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_feedback_free(feedback: Feedback) {
+        drop(unsafe { Box::from_raw(feedback.0) });
+    }
+
+    /// Sends events through the keyboard widget
+    pub struct KeyboardSink(pub EekGtkKeyboard);
+
+    impl Sink for KeyboardSink {
+        fn emit(&mut self, event: &str, profile: Profile) {
+            let event = CString::new(event).unwrap();
+            let profile = match profile {
+                Profile::System => None,
+                other => Some(CString::new(other.as_str()).unwrap()),
+            };
+            unsafe {
+                eek_gtk_keyboard_emit_feedback(
+                    self.0,
+                    event.as_ptr(),
+                    profile.as_ref().map_or(ptr::null(), |p| p.as_ptr()),
+                )
+            }
+        }
+    }
+}
+
+/// What the pressed button does, as far as feedback is concerned
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    Letter,
+    Erase,
+    Modifier,
+    ViewSwitch,
+    /// Opens a popup
+    Popup,
+}
+
+impl Role {
+    pub fn from_action(action: &Action) -> Role {
+        match action {
+            Action::Submit { .. } | Action::Dead { .. } => Role::Letter,
+            Action::Erase => Role::Erase,
+            Action::ApplyModifier(_) => Role::Modifier,
+            Action::SetView(_)
+                | Action::LockView { .. }
+                | Action::ToggleOneHanded
+                | Action::HideKeyboard
+            => Role::ViewSwitch,
+            Action::ShowPreferences => Role::Popup,
+        }
+    }
+
+    /// The name of the feedback daemon's event
+    fn get_event(&self) -> &'static str {
+        match self {
+            Role::Letter => "button-pressed",
+            Role::Erase => "keyboard-erase",
+            Role::Modifier => "keyboard-modifier",
+            Role::ViewSwitch => "keyboard-view-switch",
+            Role::Popup => "keyboard-popup",
+        }
+    }
+}
+
+/// How much feedback to give
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Profile {
+    /// Whatever the feedback daemon is set to
+    System,
+    /// Includes sounds, like a click
+    Full,
+    /// Haptic feedback only
+    Quiet,
+    Silent,
+}
+
+impl Profile {
+    fn from_name(name: &str) -> Option<Profile> {
+        match name {
+            "system" => Some(Profile::System),
+            "full" => Some(Profile::Full),
+            "quiet" => Some(Profile::Quiet),
+            "silent" => Some(Profile::Silent),
+            _ => None,
+        }
+    }
+
+    /// The feedback daemon's name, except for `System`
+    fn as_str(&self) -> &'static str {
+        match self {
+            Profile::System => "system",
+            Profile::Full => "full",
+            Profile::Quiet => "quiet",
+            Profile::Silent => "silent",
+        }
+    }
+}
+
+/// Receives feedback events
+pub trait Sink {
+    fn emit(&mut self, event: &str, profile: Profile);
+}
+
+pub struct Feedback {
+    /// `None` if the schema is not installed
+    settings: Option<gio::Settings>,
+}
+
+impl Feedback {
+    fn new() -> Feedback {
+        Feedback {
+            settings: popover::get_own_settings(),
+        }
+    }
+
+    fn get_profile(&self) -> Profile {
+        self.settings.as_ref()
+            .and_then(|settings| popover::get_string(settings, SETTINGS_KEY))
+            .and_then(|name| Profile::from_name(&name))
+            .unwrap_or(Profile::System)
+    }
+
+    pub fn emit<S: Sink>(&self, sink: &mut S, role: Role) {
+        emit(sink, self.get_profile(), role)
+    }
+}
+
+fn emit<S: Sink>(sink: &mut S, profile: Profile, role: Role) {
+    if profile != Profile::Silent {
+        sink.emit(role.get_event(), profile);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::action::Modifier;
+
+    /// Remembers the events instead of giving feedback
+    #[derive(Default)]
+    struct MockSink(Vec<(String, Profile)>);
+
+    impl Sink for MockSink {
+        fn emit(&mut self, event: &str, profile: Profile) {
+            self.0.push((event.into(), profile));
+        }
+    }
+
+    #[test]
+    fn roles() {
+        let mut sink = MockSink::default();
+        let actions = vec![
+            Action::Submit { text: None, keys: Vec::new() },
+            Action::Erase,
+            Action::ApplyModifier(Modifier::Control),
+            Action::SetView("base".into()),
+            Action::ShowPreferences,
+        ];
+        for action in actions {
+            emit(&mut sink, Profile::Quiet, Role::from_action(&action));
+        }
+        assert_eq!(
+            sink.0,
+            vec![
+                ("button-pressed".into(), Profile::Quiet),
+                ("keyboard-erase".into(), Profile::Quiet),
+                ("keyboard-modifier".into(), Profile::Quiet),
+                ("keyboard-view-switch".into(), Profile::Quiet),
+                ("keyboard-popup".into(), Profile::Quiet),
+            ],
+        );
+    }
+
+    #[test]
+    fn profiles() {
+        let mut sink = MockSink::default();
+        emit(&mut sink, Profile::Full, Role::Letter);
+        emit(&mut sink, Profile::Silent, Role::Letter);
+        emit(&mut sink, Profile::System, Role::Letter);
+        assert_eq!(
+            sink.0,
+            vec![
+                ("button-pressed".into(), Profile::Full),
+                ("button-pressed".into(), Profile::System),
+            ],
+        );
+    }
+
+    #[test]
+    fn profile_names() {
+        let profiles = [Profile::System, Profile::Full, Profile::Quiet, Profile::Silent];
+        for profile in &profiles {
+            assert_eq!(Profile::from_name(profile.as_str()), Some(*profile));
+        }
+        assert_eq!(Profile::from_name("loud"), None);
+    }
+}
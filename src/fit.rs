@@ -0,0 +1,129 @@
+/*! Fitting the layout into the space it gets.
+ *
+ * Layouts rarely have the same shape as the panel.
+ * The layout file picks how to deal with that,
+ * and the user may override the choice in gsettings.
+ */
+
+use std::cmp;
+
+use crate::float_ord::FloatOrd;
+use crate::layout::Size;
+use crate::layout::c::{ Bounds, Transformation };
+use crate::popover;
+
+
+/// The mode, or "layout" to use the one from the layout
+const SETTINGS_KEY: &str = "fit";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Stretches up to 5% horizontally, and centers the rest
+    Auto,
+    /// Fills the whole space, distorting the layout
+    Stretch,
+    /// Keeps the shape and centers the layout
+    Center,
+    /// Keeps the shape and places the layout at the bottom,
+    /// centered horizontally
+    Bottom,
+    /// Makes keys wider to fill the width, without widening the gaps.
+    /// The views get changed with `LayoutData::fit`.
+    StretchKeys,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Mode::Auto
+    }
+}
+
+impl Mode {
+    pub fn from_name(name: &str) -> Option<Mode> {
+        match name {
+            "auto" => Some(Mode::Auto),
+            "stretch" => Some(Mode::Stretch),
+            "center" => Some(Mode::Center),
+            "bottom" => Some(Mode::Bottom),
+            "stretch_keys" => Some(Mode::StretchKeys),
+            _ => None,
+        }
+    }
+
+    /// Scales the layout of the given size to the area
+    pub fn get_transformation(&self, area: &Bounds, size: &Size) -> Transformation {
+        let h_scale = area.width / size.width;
+        let v_scale = area.height / size.height;
+        let min_scale = cmp::min(FloatOrd(h_scale), FloatOrd(v_scale)).0;
+        let (scale_x, scale_y) = match self {
+            // Allow up to 5% (and a bit more) horizontal stretching for filling up available space
+            Mode::Auto => (
+                if (h_scale / v_scale) < 1.055 { h_scale } else { v_scale },
+                min_scale,
+            ),
+            Mode::Stretch => (h_scale, v_scale),
+            Mode::Center | Mode::Bottom | Mode::StretchKeys => (min_scale, min_scale),
+        };
+        let free_height = area.height - scale_y * size.height;
+        Transformation {
+            origin_x: area.x + (area.width - (scale_x * size.width)) / 2.0,
+            origin_y: area.y + match self {
+                Mode::Bottom => free_height,
+                _ => free_height / 2.0,
+            },
+            scale_x,
+            scale_y,
+        }
+    }
+}
+
+/// Reads the mode chosen by the user.
+/// `None` leaves the choice to the layout.
+pub fn load() -> Option<Mode> {
+    let settings = popover::get_own_settings()?;
+    popover::get_string(&settings, SETTINGS_KEY)
+        .and_then(|name| Mode::from_name(&name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn area(width: f64, height: f64) -> Bounds {
+        Bounds { x: 0.0, y: 0.0, width, height }
+    }
+
+    /// A square layout in a wide and short area
+    #[test]
+    fn wide() {
+        let size = Size { width: 1.0, height: 1.0 };
+        let area = area(200.0, 100.0);
+
+        let t = Mode::Auto.get_transformation(&area, &size);
+        assert_eq!((t.scale_x, t.scale_y), (100.0, 100.0));
+        assert_eq!((t.origin_x, t.origin_y), (50.0, 0.0));
+
+        let t = Mode::Stretch.get_transformation(&area, &size);
+        assert_eq!((t.scale_x, t.scale_y), (200.0, 100.0));
+        assert_eq!((t.origin_x, t.origin_y), (0.0, 0.0));
+
+        let t = Mode::Center.get_transformation(&area, &size);
+        assert_eq!((t.scale_x, t.scale_y), (100.0, 100.0));
+        assert_eq!((t.origin_x, t.origin_y), (50.0, 0.0));
+    }
+
+    /// A square layout in a narrow and high area
+    #[test]
+    fn high() {
+        let size = Size { width: 1.0, height: 1.0 };
+        let area = area(100.0, 200.0);
+
+        let t = Mode::Center.get_transformation(&area, &size);
+        assert_eq!((t.scale_x, t.scale_y), (100.0, 100.0));
+        assert_eq!((t.origin_x, t.origin_y), (0.0, 50.0));
+
+        let t = Mode::Bottom.get_transformation(&area, &size);
+        assert_eq!((t.scale_x, t.scale_y), (100.0, 100.0));
+        assert_eq!((t.origin_x, t.origin_y), (0.0, 100.0));
+    }
+}
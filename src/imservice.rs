@@ -50,6 +50,12 @@ pub mod c {
         #[allow(improper_ctypes)] // IMService will never be dereferenced in C
         pub fn imservice_connect_listeners(im: InputMethod, imservice: *const IMService);
         pub fn eek_input_method_commit_string(im: InputMethod, text: *const c_char);
+        pub fn eek_input_method_set_preedit_string(
+            im: InputMethod,
+            text: *const c_char,
+            cursor_begin: i32,
+            cursor_end: i32,
+        );
         pub fn eek_input_method_delete_surrounding_text(im: InputMethod, before: u32, after: u32);
         pub fn eek_input_method_commit(im: InputMethod, serial: u32);
     }
@@ -153,9 +159,13 @@ pub mod c {
     {
         let imservice = check_imservice(imservice, im).unwrap();
 
+        let was_active = imservice.current.active;
         imservice.current = imservice.pending.clone();
         imservice.serial += Wrapping(1u32);
         imservice.send_event();
+        if was_active && !imservice.current.active {
+            imservice.notify_deactivated();
+        }
     }
     
     // TODO: this is really untested
@@ -172,6 +182,7 @@ pub mod c {
         imservice.current.active = false;
 
         imservice.send_event();
+        imservice.notify_deactivated();
     }    
 
     // FIXME: destroy and deallocate
@@ -328,6 +339,8 @@ pub struct IMService {
     current: IMProtocolState, // turn current into an idiomatic representation?
     preedit_string: String,
     serial: Wrapping<u32>,
+    /// Called when the text field goes away
+    deactivate_listener: Option<Box<dyn Fn()>>,
 }
 
 pub enum SubmitError {
@@ -349,6 +362,7 @@ impl IMService {
             current: IMProtocolState::default(),
             preedit_string: String::new(),
             serial: Wrapping(0u32),
+            deactivate_listener: None,
         });
         unsafe {
             c::imservice_connect_listeners(
@@ -371,6 +385,58 @@ impl IMService {
         }
     }
 
+    /// Replaces the text being composed.
+    /// The cursor is given as a range of byte offsets into the text,
+    /// or `None` to hide it.
+    pub fn set_preedit_string(
+        &mut self,
+        text: String,
+        cursor: Option<(usize, usize)>,
+    ) -> Result<(), SubmitError> {
+        match self.current.active {
+            true => {
+                let (cursor_begin, cursor_end) = match cursor {
+                    Some((begin, end)) => (begin as i32, end as i32),
+                    None => (-1, -1),
+                };
+                let text_c = CString::new(text.clone())
+                    .or_print(
+                        logging::Problem::Bug,
+                        "Preedit string contains a null byte",
+                    )
+                    .unwrap_or_default();
+                unsafe {
+                    c::eek_input_method_set_preedit_string(
+                        self.im,
+                        text_c.as_ptr(),
+                        cursor_begin, cursor_end,
+                    )
+                }
+                self.preedit_string = text;
+                Ok(())
+            },
+            false => Err(SubmitError::NotActive),
+        }
+    }
+
+    pub fn get_preedit_string(&self) -> &str {
+        &self.preedit_string
+    }
+
+    /// The letters between the cursor and the beginning of the word.
+    /// `None` if the text is not known.
+    pub fn get_word_before_cursor(&self) -> Option<&str> {
+        let text = self.current.surrounding_text.to_str().ok()?;
+        let before = text.get(..self.current.surrounding_cursor as usize)?;
+        let start = before.char_indices()
+            .rev()
+            .take_while(|(_i, c)| c.is_alphabetic())
+            .last()
+            .map(|(i, _c)| i)
+            .unwrap_or(before.len());
+        Some(&before[start..])
+    }
+
     pub fn delete_surrounding_text(
         &self,
         before: u32, after: u32,
@@ -405,6 +471,20 @@ impl IMService {
         self.current.active
     }
 
+    pub fn set_deactivate_listener(&mut self, listener: Box<dyn Fn()>) {
+        self.deactivate_listener = Some(listener);
+    }
+
+    fn notify_deactivated(&self) {
+        if let Some(listener) = &self.deactivate_listener {
+            listener();
+        }
+    }
+
+    pub fn get_content_hint(&self) -> ContentHint {
+        self.current.content_hint
+    }
+
     fn send_event(&self) {
         let state = &self.current;
         let timestamp = Instant::now();
@@ -15,6 +15,8 @@
 enum squeek_arrangement_kind {
     ARRANGEMENT_KIND_BASE = 0,
     ARRANGEMENT_KIND_WIDE = 1,
+    ARRANGEMENT_KIND_NARROW = 2,
+    ARRANGEMENT_KIND_TABLET = 3,
 };
 
 struct squeek_layout_state {
@@ -31,16 +33,19 @@ struct squeek_layout;
 struct transformation squeek_layout_calculate_transformation(
         const struct squeek_layout *layout,
         double allocation_width, double allocation_size);
+void squeek_layout_fit(struct squeek_layout *layout,
+        double allocation_width, double allocation_height);
 
 struct squeek_layout *squeek_load_layout(const char *name, uint32_t type, uint32_t variant_type, const char *overlay_name);
 enum squeek_arrangement_kind squeek_layout_get_kind(const struct squeek_layout *);
 uint32_t squeek_layout_get_purpose(const struct squeek_layout *);
+void squeek_layout_keep_view(struct squeek_layout *layout, const struct squeek_layout *previous);
 void squeek_layout_free(struct squeek_layout*);
 
 void squeek_layout_release(struct squeek_layout *layout,
                            struct submission *submission,
                            struct transformation widget_to_layout,
-                           uint32_t timestamp,
+                           uint32_t timestamp, uint32_t touch_id,
                            struct squeek_popover *popover,
                            struct squeek_state_manager *state,
                            EekGtkKeyboard *ui_keyboard);
@@ -51,12 +56,15 @@ void squeek_layout_depress(struct squeek_layout *layout,
                            struct submission *submission,
                            double x_widget, double y_widget,
                            struct transformation widget_to_layout,
-                           uint32_t timestamp, EekGtkKeyboard *ui_keyboard);
+                           double panel_height,
+                           uint32_t timestamp, uint32_t touch_id,
+                           EekGtkKeyboard *ui_keyboard);
 void squeek_layout_drag(struct squeek_layout *layout,
                         struct submission *submission,
                         double x_widget, double y_widget,
                         struct transformation widget_to_layout,
-                        uint32_t timestamp, struct squeek_popover *popover,
+                        uint32_t timestamp, uint32_t touch_id,
+                        struct squeek_popover *popover,
                         struct squeek_state_manager *state,
                         EekGtkKeyboard *ui_keyboard);
 void squeek_layout_draw_all_changed(struct squeek_layout *layout, EekRenderer* renderer, cairo_t     *cr, struct submission *submission);
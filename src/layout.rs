@@ -17,21 +17,33 @@
  * and let the renderer scale and center it within the widget.
  */
 
-use std::cmp;
 use std::collections::HashMap;
 use std::ffi::CString;
 use std::fmt;
+use std::iter;
+use std::mem;
 use std::vec::Vec;
 
 use crate::action::Action;
 use crate::actors;
+use crate::composition;
+use crate::devices;
 use crate::drawing;
+use crate::feedback;
+use crate::fit;
 use crate::float_ord::FloatOrd;
 use crate::keyboard::{KeyState, KeyCode, PressType};
 use crate::logging;
+use crate::one_handed;
+use crate::panel::PixelSize;
 use crate::popover;
+use crate::preview;
 use crate::receiver;
+use crate::state;
+use crate::state::visibility;
 use crate::submission::{ Submission, SubmitData, Timestamp };
+use crate::swipe;
+use crate::targeting;
 use crate::util::find_max_double;
 
 use crate::imservice::ContentPurpose;
@@ -59,9 +71,9 @@ pub mod c {
 
     extern "C" {
         #[allow(improper_ctypes)]
-        pub fn eek_gtk_keyboard_emit_feedback(
+        pub fn eek_gtk_keyboard_get_preview(
             keyboard: EekGtkKeyboard,
-        );
+        ) -> preview::c::Preview;
     }
 
     /// Defined in eek-types.h
@@ -169,6 +181,23 @@ pub mod c {
 
     // The following defined in Rust. TODO: wrap naked pointers to Rust data inside RefCells to prevent multiple writers
 
+    /// Rearranges the views for the available space,
+    /// splitting rows if needed.
+    /// Call when the allocation changes, not while buttons are held.
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_layout_fit(
+        layout: *mut Layout,
+        allocation_width: f64,
+        allocation_height: f64,
+    ) {
+        let layout = unsafe { &mut *layout };
+        layout.shape.fit(Size {
+            width: allocation_width,
+            height: allocation_height,
+        });
+    }
+
     /// Positions the layout contents within the available space.
     /// The origin of the transformation is the point inside the margins.
     #[no_mangle]
@@ -199,6 +228,15 @@ pub mod c {
         layout.shape.purpose.clone() as u32
     }
 
+    /// Takes over the view from the layout being replaced
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_layout_keep_view(layout: *mut Layout, previous: *const Layout) {
+        let layout = unsafe { &mut *layout };
+        let previous = unsafe { &*previous };
+        layout.keep_view_from(previous);
+    }
+
     #[no_mangle]
     pub extern "C"
     fn squeek_layout_free(layout: *mut Layout) {
@@ -209,7 +247,7 @@ pub mod c {
     pub mod procedures {
         use super::*;
 
-        /// Release pointer in the specified position
+        /// Release the buttons held by the touch point
         #[no_mangle]
         pub extern "C"
         fn squeek_layout_release(
@@ -217,11 +255,13 @@ pub mod c {
             submission: CSubmission,
             widget_to_layout: Transformation,
             time: u32,
+            touch_id: u32,
             popover: actors::popover::c::Actor,
             app_state: receiver::c::State,
             ui_keyboard: EekGtkKeyboard,
         ) {
             let time = Timestamp(time);
+            let touch = TouchId(touch_id);
             let layout = unsafe { &mut *layout };
             let submission = submission.clone_ref();
             let mut submission = submission.borrow_mut();
@@ -233,11 +273,36 @@ pub mod c {
                 keyboard: ui_keyboard,
             };
 
+            if let Some(Dismiss { done: true, .. }) = layout.state.take_dismiss(touch) {
+                // The buttons were let go when the keyboard got hidden
+                return;
+            }
+            if let Some(gesture) = layout.state.take_gesture(touch) {
+                seat::finish_gesture(
+                    layout,
+                    &mut submission,
+                    Some(&ui_backend),
+                    time,
+                    Some((&popover_state, app_state.clone())),
+                    gesture,
+                );
+            }
+            if let Some(trackpad) = layout.state.take_trackpad(touch) {
+                seat::finish_trackpad(
+                    layout,
+                    &mut submission,
+                    Some(&ui_backend),
+                    time,
+                    Some((&popover_state, app_state.clone())),
+                    trackpad,
+                );
+            }
+
             // The list must be copied,
             // because it will be mutated in the loop
             let pressed_buttons
                 = layout.state.active_buttons.clone();
-            for (button, _key_state) in pressed_buttons.iter_pressed() {
+            for button in pressed_buttons.iter_pressed_by(touch) {
                 seat::handle_release_key(
                     layout,
                     &mut submission,
@@ -261,10 +326,16 @@ pub mod c {
             let layout = unsafe { &mut *layout };
             let submission = submission.clone_ref();
             let mut submission = submission.borrow_mut();
+            // An interrupted gesture types only the letter it started on
+            if layout.state.gesture.take().is_some() {
+                submission.finish_gesture(&[], None, None);
+            }
+            layout.state.trackpad = None;
+            layout.state.dismiss = None;
             // The list must be copied,
             // because it will be mutated in the loop
             let pressed_buttons = layout.state.active_buttons.clone();
-            for (button, _key_state) in pressed_buttons.iter_pressed() {
+            for (button, _touch) in pressed_buttons.iter_pressed() {
                 seat::handle_release_key(
                     layout,
                     &mut submission,
@@ -283,9 +354,12 @@ pub mod c {
             submission: CSubmission,
             x_widget: f64, y_widget: f64,
             widget_to_layout: Transformation,
+            panel_height: f64,
             time: u32,
+            touch_id: u32,
             ui_keyboard: EekGtkKeyboard,
         ) {
+            let touch = TouchId(touch_id);
             let layout = unsafe { &mut *layout };
             let submission = submission.clone_ref();
             let mut submission = submission.borrow_mut();
@@ -293,7 +367,19 @@ pub mod c {
                 Point { x: x_widget, y: y_widget }
             );
 
-            let index = layout.find_index_by_position(point);
+            // The view may change, so this must come before finding the button
+            seat::hold_locks(layout, touch);
+            let index = match layout.shape.targeting {
+                Some(_) => {
+                    let index = layout.find_index_by_target(
+                        point.clone(),
+                        submission.get_prediction().as_ref(),
+                    );
+                    layout.log_target(&submission, &point, index);
+                    index
+                },
+                None => layout.find_index_by_position(point.clone()),
+            };
 
             if let Some((row, position_in_row)) = index {
                 let button = ButtonPosition {
@@ -301,23 +387,52 @@ pub mod c {
                     row,
                     position_in_row,
                 };
-                seat::handle_press_key(
+                let trackpad = seat::start_trackpad(
                     layout,
-                    &mut submission,
+                    touch,
+                    &button,
+                    point.clone(),
                     Timestamp(time),
+                );
+                let gesture = !trackpad && seat::start_gesture(
+                    layout,
+                    &mut submission,
+                    touch,
                     &button,
+                    point,
                 );
+                seat::start_dismiss(
+                    layout,
+                    touch,
+                    &button,
+                    Point { x: x_widget, y: y_widget },
+                    panel_height,
+                );
+                if !(trackpad || gesture) {
+                    seat::handle_press_key(
+                        layout,
+                        &mut submission,
+                        Timestamp(time),
+                        touch,
+                        &button,
+                    );
+                }
+                // The letter starting a gesture got typed, so it's shown too
+                if !trackpad {
+                    seat::show_preview(
+                        layout,
+                        &submission,
+                        &UIBackend { widget_to_layout, keyboard: ui_keyboard },
+                        &button,
+                    );
+                }
                 // maybe TODO: draw on the display buffer here
                 drawing::queue_redraw(ui_keyboard);
-                unsafe {
-                    eek_gtk_keyboard_emit_feedback(ui_keyboard);
-                }
+                seat::emit_feedback(layout, ui_keyboard, &button);
             };
         }
 
-        // FIXME: this will work funny
-        // when 2 touch points are on buttons and moving one after another
-        // Solution is to have separate pressed lists for each point
+        /// Moves the touch point, only affecting the buttons it holds
         #[no_mangle]
         pub extern "C"
         fn squeek_layout_drag(
@@ -326,11 +441,13 @@ pub mod c {
             x_widget: f64, y_widget: f64,
             widget_to_layout: Transformation,
             time: u32,
+            touch_id: u32,
             popover: actors::popover::c::Actor,
             app_state: receiver::c::State,
             ui_keyboard: EekGtkKeyboard,
         ) {
             let time = Timestamp(time);
+            let touch = TouchId(touch_id);
             let layout = unsafe { &mut *layout };
             let submission = submission.clone_ref();
             let mut submission = submission.borrow_mut();
@@ -342,13 +459,57 @@ pub mod c {
                 widget_to_layout,
                 keyboard: ui_keyboard,
             };
+
+            let dismissed = seat::move_dismiss(
+                layout,
+                &mut submission,
+                Some(&ui_backend),
+                time,
+                &app_state,
+                touch,
+                &Point { x: x_widget, y: y_widget },
+            );
+            if dismissed {
+                drawing::queue_redraw(ui_keyboard);
+                return;
+            }
+            if seat::is_holding_lock(layout, touch) {
+                // The view changed under the finger holding the lock.
+                // Moving it around shouldn't press buttons of the new view.
+                return;
+            }
             let point = ui_backend.widget_to_layout.forward(
                 Point { x: x_widget, y: y_widget }
             );
 
+            if let Some(gesture) = layout.state.gesture.as_mut()
+                .filter(|gesture| gesture.touch == touch)
+            {
+                // The path gets recognized when the touch ends
+                gesture.path.push(point);
+                return;
+            }
+
+            if seat::is_trackpad(layout, touch) {
+                seat::move_trackpad(
+                    layout,
+                    &mut submission,
+                    &ui_backend.widget_to_layout,
+                    point,
+                    time,
+                );
+                return;
+            }
+
             let pressed_buttons = layout.state.active_buttons.clone();
-            let pressed_buttons = pressed_buttons.iter_pressed();
-            let button_info = layout.find_index_by_position(point);
+            let held = pressed_buttons.iter_pressed_by(touch)
+                .find(|button| button.view == layout.state.current_view)
+                .map(|button| (button.row, button.position_in_row));
+            let pressed_buttons = pressed_buttons.iter_pressed_by(touch);
+            let dismissing = seat::is_dismissing(layout, touch);
+            let button_info = layout.find_index_by_movement(point, held)
+                // Swiping the keyboard away doesn't type the rows below
+                .filter(|(row, _)| !dismissing || *row == 0);
 
             if let Some((row, position_in_row)) = button_info {
                 let current_pos = ButtonPosition {
@@ -357,7 +518,7 @@ pub mod c {
                     position_in_row,
                 };
                 let mut found = false;
-                for (button, _key_state) in pressed_buttons {
+                for button in pressed_buttons {
                     if button == &current_pos {
                         found = true;
                     } else {
@@ -381,15 +542,20 @@ pub mod c {
                         layout,
                         &mut submission,
                         time,
+                        touch,
+                        &button,
+                    );
+                    seat::show_preview(
+                        layout,
+                        &submission,
+                        &ui_backend,
                         &button,
                     );
                     // maybe TODO: draw on the display buffer here
-                    unsafe {
-                        eek_gtk_keyboard_emit_feedback(ui_keyboard);
-                    }
+                    seat::emit_feedback(layout, ui_keyboard, &button);
                 }
             } else {
-                for (button, _key_state) in pressed_buttons {
+                for button in pressed_buttons {
                     seat::handle_release_key(
                         layout,
                         &mut submission,
@@ -457,6 +623,17 @@ pub struct Button {
     pub keycodes: Vec<KeyCode>,
     /// Static description of what the key does when pressed or released
     pub action: Action,
+    /// Present if dragging over the button moves the cursor
+    pub cursor_keys: Option<CursorKeys>,
+}
+
+/// Keys moving the cursor
+#[derive(Clone, Debug, PartialEq)]
+pub struct CursorKeys {
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub up: KeyCode,
+    pub down: KeyCode,
 }
 
 impl Button {
@@ -466,17 +643,54 @@ impl Button {
             width: self.size.width, height: self.size.height,
         }
     }
+
+    /// The label to show magnified while the button is pressed.
+    /// Only buttons typing text have one.
+    fn get_preview_label(&self) -> Option<&str> {
+        match (&self.action, &self.label, &self.cursor_keys) {
+            (
+                Action::Submit { text: Some(_), keys: _ },
+                Label::Text(label),
+                None,
+            ) => label.to_str().ok()
+                .filter(|label| !label.trim().is_empty()),
+            _ => None,
+        }
+    }
+
+    /// The letter submitted by the button, if it submits a single one
+    fn get_letter(&self) -> Option<char> {
+        match &self.action {
+            Action::Submit { text: Some(text), keys: _ } => {
+                let text = text.to_str().ok()?;
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_alphabetic() => Some(c),
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
+    }
 }
 
 /// The representation of a row of buttons
 #[derive(Clone, Debug)]
 pub struct Row {
     /// Buttons together with their offset from the left relative to the row.
-    /// ie. the first button always start at 0.
+    /// ie. the first button always start at 0, unless the row got spread.
     buttons: Vec<(f64, Button)>,
 
     /// Total size of the row
     size: Size,
+
+    /// Index of the first button of the right half when split.
+    /// Rows without halves stay whole in the middle.
+    split: Option<usize>,
+    /// Empty space between the halves
+    gap: f64,
+    /// How much wider the buttons are than declared
+    key_scale: f64,
 }
 
 impl Row {
@@ -499,7 +713,104 @@ impl Row {
             |(_offset, button)| button.size.height,
         );
 
-        Row { buttons, size: Size { width, height } }
+        // Without a declared split point,
+        // the boundary between buttons closest to the middle is used.
+        let split = buttons.iter()
+            .enumerate()
+            .skip(1)
+            .min_by_key(|(_index, (offset, _button))| {
+                FloatOrd((offset - width / 2.0).abs())
+            })
+            .map(|(index, _)| index);
+
+        Row { buttons, size: Size { width, height }, split, gap: 0.0, key_scale: 1.0 }
+    }
+
+    /// Declares where the row splits into halves
+    pub fn with_split(self, index: usize) -> Row {
+        let split = match index {
+            0 => None,
+            i if i >= self.buttons.len() => None,
+            i => Some(i),
+        };
+        Row { split, ..self }
+    }
+
+    fn get_unsplit_width(&self) -> f64 {
+        self.size.width - self.gap
+    }
+
+    /// Places the halves of the row apart, making it as wide as given.
+    /// Rows wider than that don't get split.
+    fn spread_to(self, width: f64) -> Row {
+        let gap = (width - self.get_unsplit_width()).max(0.0);
+        let change = gap - self.gap;
+        let split = self.split;
+        let buttons = self.buttons.into_iter()
+            .enumerate()
+            .map(|(index, (offset, button))| match split {
+                Some(split) if index < split => (offset, button),
+                Some(_) => (offset + change, button),
+                None => (offset + change / 2.0, button),
+            })
+            .collect();
+        Row {
+            buttons,
+            size: Size { width: self.size.width + change, ..self.size },
+            split,
+            gap,
+            key_scale: self.key_scale,
+        }
+    }
+
+    /// Total width of the buttons, without the space between them
+    fn get_keys_width(&self) -> f64 {
+        self.buttons.iter()
+            .map(|(_offset, button)| button.size.width)
+            .sum()
+    }
+
+    /// The factor by which the buttons must be wider than declared
+    /// for the row to take the width, keeping the space between them
+    fn get_key_scale_for(&self, width: f64) -> f64 {
+        let declared_keys_width = self.get_keys_width() / self.key_scale;
+        let space = self.get_unsplit_width() - self.get_keys_width();
+        (width - space) / declared_keys_width
+    }
+
+    /// Makes the buttons wider than declared by the factor,
+    /// keeping the space between them
+    fn stretch_keys(self, key_scale: f64) -> Row {
+        let change = key_scale / self.key_scale;
+        let mut position = match self.buttons.first() {
+            Some((offset, _button)) => *offset,
+            None => 0.0,
+        };
+        let mut buttons = Vec::with_capacity(self.buttons.len());
+        let mut iter = self.buttons.iter().peekable();
+        while let Some((offset, button)) = iter.next() {
+            let space = match iter.peek() {
+                Some((next_offset, _)) => next_offset - offset - button.size.width,
+                None => 0.0,
+            };
+            let width = button.size.width * change;
+            buttons.push((
+                position,
+                Button {
+                    size: Size { width, ..button.size.clone() },
+                    ..button.clone()
+                },
+            ));
+            position += width + space;
+        }
+        // The space around the buttons stays too
+        let width = self.size.width + self.get_keys_width() * (change - 1.0);
+        Row {
+            buttons,
+            size: Size { width, ..self.size },
+            key_scale,
+            ..self
+        }
     }
 
     pub fn get_size(&self) -> Size {
@@ -616,6 +927,32 @@ impl View {
         self.size.clone()
     }
 
+    /// Widens the buttons in all rows by the same factor,
+    /// so that the widest row takes the given width.
+    /// Buttons never get narrower than declared.
+    fn stretch_keys_to(self, width: f64) -> View {
+        let key_scale = self.rows.iter()
+            .filter(|(_offset, row)| !row.buttons.is_empty())
+            .map(|(_offset, row)| FloatOrd(row.get_key_scale_for(width)))
+            .min()
+            .map(|scale| scale.0.max(1.0))
+            .unwrap_or(1.0);
+        View::new(
+            self.rows.into_iter()
+                .map(|(offset, row)| (offset.y, row.stretch_keys(key_scale)))
+                .collect()
+        )
+    }
+
+    /// Spreads all rows to the given width, if they are narrower
+    fn spread_to(self, width: f64) -> View {
+        View::new(
+            self.rows.into_iter()
+                .map(|(offset, row)| (offset.y, row.spread_to(width)))
+                .collect()
+        )
+    }
+
     /// Returns positioned rows, with appropriate x offsets (centered)
     pub fn get_rows(&self) -> &Vec<(c::Point, Row)> {
         &self.rows
@@ -635,6 +972,26 @@ impl View {
             ),
         }
     }
+
+    /// Positions the views so that they are all centered on the same point
+    pub fn center_all(views: Vec<(String, View)>)
+        -> HashMap<String, (c::Point, View)>
+    {
+        let total_size = View::calculate_super_size(
+            views.iter().map(|(_name, view)| view).collect()
+        );
+
+        views.into_iter().map(|(name, view)| (
+            name,
+            (
+                c::Point {
+                    x: (total_size.width - view.size.width) / 2.0,
+                    y: (total_size.height - view.size.height) / 2.0,
+                },
+                view,
+            ),
+        )).collect()
+    }
 }
 
 /// The physical characteristic of layout for the purpose of styling
@@ -642,6 +999,20 @@ impl View {
 pub enum ArrangementKind {
     Base = 0,
     Wide = 1,
+    /// Watches and very small phones
+    Narrow = 2,
+    Tablet = 3,
+}
+
+/// The shape of a layout, as far as the panel size is concerned
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Proportions {
+    /// The most rows in any view
+    pub rows: u32,
+    /// Height divided by width, margins included
+    pub aspect: f64,
+    /// Whether the candidate strip is going to show above the layout
+    pub candidates: bool,
 }
 
 #[derive(Debug, PartialEq)]
@@ -680,8 +1051,13 @@ pub struct ButtonPosition {
     pub position_in_row: usize,
 }
 
+/// Identifies a touch point, or the pointer, for the duration of a press.
+/// Each point owns the buttons it pressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TouchId(pub u32);
+
 #[derive(Clone)]
-pub struct ActiveButtons(HashMap<ButtonPosition, KeyState>);
+pub struct ActiveButtons(HashMap<ButtonPosition, (TouchId, KeyState)>);
 
 enum Presence {
     Missing,
@@ -691,8 +1067,13 @@ enum Presence {
 static RELEASED: KeyState = KeyState { pressed: PressType::Released };
 
 impl ActiveButtons {
-    fn insert(&mut self, button: ButtonPosition, state: KeyState) -> Presence {
-        match self.0.insert(button, state) {
+    fn insert(
+        &mut self,
+        button: ButtonPosition,
+        touch: TouchId,
+        state: KeyState,
+    ) -> Presence {
+        match self.0.insert(button, (touch, state)) {
             Some(_) => Presence::Present,
             None => Presence::Missing,
         }
@@ -700,6 +1081,7 @@ impl ActiveButtons {
     
     pub fn get(&self, button: &ButtonPosition) -> &KeyState {
         self.0.get(button)
+            .map(|(_touch, state)| state)
             .unwrap_or(&RELEASED)
     }
     fn remove(&mut self, button: &ButtonPosition) -> Presence {
@@ -708,8 +1090,18 @@ impl ActiveButtons {
             None => Presence::Missing,
         }
     }
-    fn iter_pressed(&self) -> impl Iterator<Item=(&ButtonPosition, &KeyState)> {
-        self.0.iter().filter(|(_p, s)| s.pressed == PressType::Pressed)
+    fn iter_pressed(&self) -> impl Iterator<Item=(&ButtonPosition, TouchId)> {
+        self.0.iter()
+            .filter(|(_p, (_t, s))| s.pressed == PressType::Pressed)
+            .map(|(p, (t, _s))| (p, *t))
+    }
+    /// Buttons held down by the touch point
+    fn iter_pressed_by(&self, touch: TouchId)
+        -> impl Iterator<Item=&ButtonPosition>
+    {
+        self.iter_pressed()
+            .filter(move |(_p, t)| *t == touch)
+            .map(|(p, _t)| p)
     }
 }
 
@@ -721,18 +1113,138 @@ pub struct LayoutState {
     // clicking any button that emits an action (erase, submit, set modifier)
     // will cause lock buttons to unlatch.
     view_latched: LatchedState,
-    // a Vec would be enough, but who cares, this will be small & fast enough
-    // TODO: turn those into per-input point *_buttons to track dragging.
-    // The renderer doesn't need the list of pressed keys any more,
-    // because it needs to iterate
-    // through all buttons of the current view anyway.
-    // When the list tracks actual location,
-    // it becomes possible to place popovers and other UI accurately.
     /// Buttons not in this list are in their base state:
     /// not pressed.
     /// Latched/locked appearance is derived from current view
     /// and button metadata.
     pub active_buttons: ActiveButtons,
+    /// A locking button held down while another touch point pressed a button,
+    /// like Shift held with one thumb while typing with the other.
+    held_lock: Option<HeldLock>,
+    /// The touch point may be drawing a word across letter keys
+    gesture: Option<Gesture>,
+    /// The touch point may be moving the cursor
+    trackpad: Option<Trackpad>,
+    /// The touch point may be swiping the keyboard away
+    dismiss: Option<Dismiss>,
+}
+
+impl LayoutState {
+    fn take_gesture(&mut self, touch: TouchId) -> Option<Gesture> {
+        match &self.gesture {
+            Some(gesture) if gesture.touch == touch => self.gesture.take(),
+            _ => None,
+        }
+    }
+
+    fn take_trackpad(&mut self, touch: TouchId) -> Option<Trackpad> {
+        match &self.trackpad {
+            Some(trackpad) if trackpad.touch == touch => self.trackpad.take(),
+            _ => None,
+        }
+    }
+
+    fn take_dismiss(&mut self, touch: TouchId) -> Option<Dismiss> {
+        match &self.dismiss {
+            Some(dismiss) if dismiss.touch == touch => self.dismiss.take(),
+            _ => None,
+        }
+    }
+}
+
+/// The view of a held locking button applies only until it's released
+#[derive(Clone, Debug, PartialEq)]
+struct HeldLock {
+    button: ButtonPosition,
+    /// State to restore on release
+    view: String,
+    latched: LatchedState,
+}
+
+/// The path of a touch point which started on a letter.
+/// The letter only gets typed if the path doesn't leave its button.
+#[derive(Clone, Debug, PartialEq)]
+struct Gesture {
+    touch: TouchId,
+    start: ButtonPosition,
+    /// In layout coordinates
+    path: Vec<c::Point>,
+}
+
+/// Holding the button this long turns it into a trackpad
+const TRACKPAD_LONG_PRESS_MS: u32 = 500;
+
+/// The cursor moves by one character per this part of a key
+const TRACKPAD_STEP: f64 = 0.5;
+
+/// A pressed button which moves the cursor when dragged.
+/// The button only gets typed if it was tapped.
+#[derive(Clone, Debug, PartialEq)]
+struct Trackpad {
+    touch: TouchId,
+    button: ButtonPosition,
+    /// Milliseconds
+    pressed_at: u32,
+    /// Where the movement is counted from, in layout coordinates
+    origin: c::Point,
+    /// Steps already taken horizontally and vertically.
+    /// `None` until the button turns into a trackpad.
+    moved: Option<(i32, i32)>,
+}
+
+impl Trackpad {
+    fn is_active(&self, time: u32) -> bool {
+        self.moved.is_some()
+            || time.wrapping_sub(self.pressed_at) >= TRACKPAD_LONG_PRESS_MS
+    }
+
+    /// Returns the steps the cursor should take in each direction,
+    /// positive to the right and down.
+    fn update(&mut self, point: &c::Point, time: u32, step: f64) -> (i32, i32) {
+        let dx = point.x - self.origin.x;
+        let dy = point.y - self.origin.y;
+        let (sent_x, sent_y) = match self.moved {
+            Some(moved) => moved,
+            None => {
+                let horizontal = dx.abs() >= step && dx.abs() > dy.abs();
+                if horizontal || self.is_active(time) {
+                    (0, 0)
+                } else {
+                    return (0, 0);
+                }
+            },
+        };
+        let x = (dx / step).trunc() as i32;
+        let y = (dy / step).trunc() as i32;
+        self.moved = Some((x, y));
+        (x - sent_x, y - sent_y)
+    }
+}
+
+/// Part of the panel height a swipe must travel down to hide the keyboard
+const DISMISS_DISTANCE: f64 = 0.6;
+
+/// A touch point which started on the top row,
+/// and may hide the keyboard by moving down.
+#[derive(Clone, Debug, PartialEq)]
+struct Dismiss {
+    touch: TouchId,
+    /// In widget coordinates
+    origin: c::Point,
+    /// In widget coordinates
+    panel_height: f64,
+    /// The keyboard got hidden, and the rest of the touch does nothing
+    done: bool,
+}
+
+impl Dismiss {
+    /// The swipe must go mostly down, to leave space for typing gestures
+    fn is_reached(&self, point: &c::Point) -> bool {
+        let dx = point.x - self.origin.x;
+        let dy = point.y - self.origin.y;
+        dy >= self.panel_height * DISMISS_DISTANCE
+            && dy > 2.0 * dx.abs()
+    }
 }
 
 /// A builder structure for picking up layout data from storage
@@ -743,6 +1255,9 @@ pub struct LayoutParseData {
     /// xkb keymaps applicable to the contained keys
     pub keymaps: Vec<CString>,
     pub margins: Margins,
+    pub composition: Option<composition::Kind>,
+    pub targeting: Option<targeting::Model>,
+    pub fit: fit::Mode,
 }
 
 /// Static, cacheable information for the layout
@@ -760,6 +1275,37 @@ pub struct LayoutData {
     // Non-UI stuff
     /// xkb keymaps applicable to the contained keys. Unchangeable
     pub keymaps: Vec<CString>,
+    /// How to turn key presses into text
+    pub composition: Option<composition::Kind>,
+    /// The size of a key as seen by the user,
+    /// derived from the physical size of the output.
+    /// `None` if the output doesn't tell.
+    pub key_size: Option<PixelSize>,
+    /// Picks the pressed button by likelihood instead of strict bounds
+    pub targeting: Option<targeting::Model>,
+    /// Rows get split in halves placed at the edges of the available space.
+    /// Applied with `fit`.
+    pub split: bool,
+    /// Docks the layout to one side of the available space
+    pub one_handed: Option<one_handed::Mode>,
+    /// Parts of the panel hidden by the shape of the screen
+    pub safe_area: devices::Insets,
+    /// How the layout takes the available space
+    pub fit: fit::Mode,
+    /// Letter keys may start gesture typing
+    pub gestures: bool,
+    /// What the views were fitted to the last time
+    fitted: Option<FitInputs>,
+}
+
+/// Everything that fitting the views depends on
+#[derive(Clone, Debug, PartialEq)]
+struct FitInputs {
+    available: Size,
+    split: bool,
+    one_handed: Option<one_handed::Mode>,
+    safe_area: devices::Insets,
+    fit: fit::Mode,
 }
 
 #[derive(Debug)]
@@ -805,22 +1351,112 @@ impl LayoutData {
         }
     }
 
+    /// Describes the natural shape, before any fitting
+    pub fn get_proportions(&self) -> Proportions {
+        let size = self.calculate_size();
+        Proportions {
+            rows: self.views.values()
+                .map(|(_offset, view)| view.get_rows().len() as u32)
+                .max()
+                .unwrap_or(0),
+            aspect: size.height / size.width,
+            candidates: self.shows_candidates(),
+        }
+    }
+
+    /// The candidate strip stays for as long as the layout is shown,
+    /// even when empty, so that the keys don't move while typing
+    pub fn shows_candidates(&self) -> bool {
+        self.allows_gestures() || self.composition.as_ref()
+            .map(composition::Kind::offers_candidates)
+            .unwrap_or(false)
+    }
+
+    /// Gestures are turned on, and the layout is for typing plain words
+    fn allows_gestures(&self) -> bool {
+        let words = match self.purpose {
+            ContentPurpose::Normal
+                | ContentPurpose::Alpha
+                | ContentPurpose::Name => true,
+            _ => false,
+        };
+        self.gestures && words && self.composition.is_none()
+    }
+
+    /// Prepares the views for the available size.
+    /// When split, rows get spread so that the keys fill the height,
+    /// with the halves touching the sides.
+    /// Otherwise, keys may get wider, depending on the fit mode.
+    /// Nothing happens if nothing changed since the last time.
+    pub fn fit(&mut self, available: Size) {
+        let inputs = FitInputs {
+            available: available.clone(),
+            split: self.split,
+            one_handed: self.one_handed,
+            safe_area: self.safe_area,
+            fit: self.fit,
+        };
+        if self.fitted.as_ref() == Some(&inputs) {
+            return;
+        }
+        self.fitted = Some(inputs);
+        let area = self.get_area(available);
+        let available = Size { width: area.width, height: area.height };
+        // Neither spreading nor stretching changes the height
+        let scale = available.height / self.calculate_size().height;
+        let full_width = available.width / scale - self.margins.left - self.margins.right;
+        let width = match self.split {
+            true => full_width,
+            // Rows are never narrower than that, so they join back
+            false => 0.0,
+        };
+        let stretch_keys = !self.split && self.fit == fit::Mode::StretchKeys;
+        let views = mem::take(&mut self.views);
+        self.views = View::center_all(
+            views.into_iter()
+                .map(|(name, (_offset, view))| {
+                    let view = match stretch_keys {
+                        true => view.stretch_keys_to(full_width),
+                        // Keys go back to the declared size
+                        false => view.stretch_keys_to(0.0),
+                    };
+                    (name, view.spread_to(width))
+                })
+                .collect()
+        );
+    }
+
+    /// The part of the available space taken by the layout.
+    /// The safe area comes first, then one-handed mode docks within it.
+    fn get_area(&self, available: Size) -> c::Bounds {
+        let insets = &self.safe_area;
+        let safe = Size {
+            width: (available.width - insets.left - insets.right).max(0.0),
+            height: (available.height - insets.top - insets.bottom).max(0.0),
+        };
+        let area = match &self.one_handed {
+            Some(mode) => mode.get_area(safe),
+            None => c::Bounds {
+                x: 0.0,
+                y: 0.0,
+                width: safe.width,
+                height: safe.height,
+            },
+        };
+        c::Bounds {
+            x: area.x + insets.left,
+            y: area.y + insets.top,
+            ..area
+        }
+    }
+
     pub fn calculate_transformation(
         &self,
         available: Size,
     ) -> c::Transformation {
+        let area = self.get_area(available);
         let size = self.calculate_size();
-        let h_scale = available.width / size.width;
-        let v_scale = available.height / size.height;
-        // Allow up to 5% (and a bit more) horizontal stretching for filling up available space
-        let scale_x = if (h_scale / v_scale) < 1.055 { h_scale } else { v_scale };
-        let scale_y = cmp::min(FloatOrd(h_scale), FloatOrd(v_scale)).0;
-        let outside_margins = c::Transformation {
-            origin_x: (available.width - (scale_x * size.width)) / 2.0,
-            origin_y: (available.height - (scale_y * size.height)) / 2.0,
-            scale_x: scale_x,
-            scale_y: scale_y,
-        };
+        let outside_margins = self.fit.get_transformation(&area, &size);
         outside_margins.chain(c::Transformation {
             origin_x: self.margins.left,
             origin_y: self.margins.top,
@@ -842,12 +1478,25 @@ impl Layout {
                 views: data.views,
                 keymaps: data.keymaps,
                 margins: data.margins,
+                composition: data.composition,
                 purpose,
+                key_size: None,
+                targeting: data.targeting,
+                split: false,
+                one_handed: None,
+                safe_area: Default::default(),
+                fit: data.fit,
+                gestures: false,
+                fitted: None,
             },
             state: LayoutState {
                 current_view: "base".to_owned(),
                 view_latched: LatchedState::Not,
                 active_buttons: ActiveButtons(HashMap::new()),
+                held_lock: None,
+                gesture: None,
+                trackpad: None,
+                dismiss: None,
             },
         }
     }
@@ -870,6 +1519,20 @@ impl Layout {
         }
     }
 
+    /// Continues in the view of the previous layout,
+    /// if it's another arrangement of the same one.
+    /// Views missing in this layout are not taken over.
+    pub fn keep_view_from(&mut self, previous: &Layout) {
+        if self.set_view(previous.state.current_view.clone()).is_ok() {
+            self.state.view_latched = match &previous.state.view_latched {
+                LatchedState::FromView(view)
+                    if self.shape.views.contains_key(view)
+                    => LatchedState::FromView(view.clone()),
+                _ => LatchedState::Not,
+            };
+        }
+    }
+
     // Layout is passed around mutably,
     // so better keep the field away from direct access.
     pub fn get_view_latched(&self) -> &LatchedState {
@@ -883,6 +1546,102 @@ impl Layout {
             .map(|(_b, i)| i)
     }
 
+    /// Returns the index of the button most likely aimed at,
+    /// if the layout uses the targeting model.
+    /// Otherwise, the button covering the point.
+    fn find_index_by_target(
+        &self,
+        point: c::Point,
+        prediction: Option<&targeting::Prediction>,
+    ) -> Option<(usize, usize)> {
+        let hit = self.find_index_by_position(point.clone());
+        let model = match (&self.shape.targeting, hit) {
+            (Some(model), Some(_)) => model,
+            _ => return hit,
+        };
+        let mut keys = Vec::new();
+        self.foreach_visible_button(|offset, button, index| {
+            keys.push(targeting::Key {
+                id: index,
+                bounds: c::Bounds {
+                    x: offset.x,
+                    y: offset.y,
+                    width: button.size.width,
+                    height: button.size.height,
+                },
+                letter: button.get_letter(),
+            });
+        });
+        model.choose(&keys, &point, prediction).cloned().or(hit)
+    }
+
+    /// Shows how the targeting model picked the button
+    /// instead of the one covering the point.
+    /// Names of the keys would reveal hidden text,
+    /// so then nothing gets logged.
+    fn log_target(
+        &self,
+        submission: &Submission,
+        point: &c::Point,
+        chosen: Option<(usize, usize)>,
+    ) {
+        if !logging::is_debug_mode() || self.is_text_hidden(submission) {
+            return;
+        }
+        let view = self.get_current_view();
+        let get_name = |index: Option<(usize, usize)>| {
+            index.and_then(|(row, position_in_row)| {
+                view.get_rows().get(row)?.1.get_buttons()
+                    .get(position_in_row)
+                    .map(|(_offset, button)| button.name.clone())
+            })
+        };
+        log_print!(
+            logging::Level::Debug,
+            "Touch at {:?} hit {:?}, chose {:?}",
+            point,
+            get_name(self.find_index_by_position(point.clone())),
+            get_name(chosen),
+        );
+    }
+
+    /// Returns the index of the button under a moving touch.
+    /// With the targeting model, the held button stays chosen
+    /// while the touch remains close to it.
+    /// Letters are not predicted, because the held one got typed already.
+    fn find_index_by_movement(
+        &self,
+        point: c::Point,
+        held: Option<(usize, usize)>,
+    ) -> Option<(usize, usize)> {
+        let holds = match (&self.shape.targeting, held) {
+            (Some(model), Some(held)) => self.get_visible_bounds(held)
+                .map(|bounds| model.holds(&bounds, &point))
+                .unwrap_or(false),
+            _ => false,
+        };
+        match holds {
+            true => held,
+            false => self.find_index_by_target(point, None),
+        }
+    }
+
+    /// The bounds of the button within the current view
+    fn get_visible_bounds(&self, index: (usize, usize)) -> Option<c::Bounds> {
+        let mut found = None;
+        self.foreach_visible_button(|offset, button, i| {
+            if i == index {
+                found = Some(c::Bounds {
+                    x: offset.x,
+                    y: offset.y,
+                    width: button.size.width,
+                    height: button.size.height,
+                });
+            }
+        });
+        found
+    }
+
     /// Returns index within current view too.
     pub fn foreach_visible_button<F>(&self, mut f: F)
         where F: FnMut(c::Point, &Button, (usize, usize))
@@ -900,6 +1659,105 @@ impl Layout {
         }
     }
 
+    /// Letter keys of the current view, with their centers
+    fn get_letter_keys(&self) -> Vec<swipe::Key> {
+        let mut keys = Vec::new();
+        self.foreach_visible_button(|offset, button, _index| {
+            if let Some(letter) = button.get_letter() {
+                keys.push(swipe::Key {
+                    letter,
+                    center: offset + c::Point {
+                        x: button.size.width / 2.0,
+                        y: button.size.height / 2.0,
+                    },
+                });
+            }
+        });
+        keys
+    }
+
+    /// Letters make up most of the text buttons
+    fn is_letter_view(&self) -> bool {
+        let mut letters = 0;
+        let mut texts = 0;
+        self.foreach_visible_button(|_offset, button, _index| {
+            if let Action::Submit { text: Some(_), keys: _ } = button.action {
+                texts += 1;
+            }
+            if button.get_letter().is_some() {
+                letters += 1;
+            }
+        });
+        letters * 2 > texts
+    }
+
+    /// Whether the text field hides what's typed,
+    /// either because of its purpose or because of its hints.
+    /// Nothing may show the typed text then.
+    fn is_text_hidden(&self, submission: &Submission) -> bool {
+        match self.shape.purpose {
+            ContentPurpose::Password | ContentPurpose::Pin => true,
+            _ => submission.is_hinted_hidden(),
+        }
+    }
+
+    /// Gestures type words from a word list,
+    /// so they only make sense with plain letters,
+    /// and must not leak what's typed into hidden fields.
+    /// Without gestures, letters get typed directly, bypassing the preedit.
+    fn may_start_gesture(
+        &self,
+        submission: &Submission,
+        button: &ButtonPosition,
+    ) -> bool {
+        if !self.shape.allows_gestures() || self.is_text_hidden(submission) {
+            return false;
+        }
+        let is_letter = self.shape.get_button(button)
+            .and_then(Button::get_letter)
+            .is_some();
+        is_letter && self.is_letter_view()
+    }
+
+    /// Marks the button as pressed by the touch point.
+    /// Returns false if it was pressed already,
+    /// possibly by another touch point.
+    fn press_button(&mut self, touch: TouchId, button: &ButtonPosition) -> bool {
+        match self.state.active_buttons.get(button) {
+            KeyState { pressed: PressType::Pressed } => false,
+            KeyState { pressed: PressType::Released } => {
+                self.state.active_buttons.insert(
+                    button.clone(),
+                    touch,
+                    KeyState { pressed: PressType::Pressed },
+                );
+                true
+            },
+        }
+    }
+
+    /// Applies the view change caused by the released button.
+    fn release_button(&mut self, button_pos: &ButtonPosition, action: &Action) {
+        match self.state.held_lock.clone() {
+            // The lock was only held, so nothing gets latched or locked
+            Some(HeldLock { button, view, latched }) if &button == button_pos => {
+                try_set_view(self, &view);
+                self.state.view_latched = latched;
+                self.state.held_lock = None;
+            },
+            // The view stays as long as the lock is held
+            Some(_) => {},
+            None => self.apply_view_transition(action),
+        }
+
+        if let Presence::Missing = self.state.active_buttons.remove(&button_pos) {
+            log_print!(
+                logging::Level::Bug,
+                "No button to remove from pressed list: {:?}", button_pos
+            );
+        }
+    }
+
     fn apply_view_transition(
         &mut self,
         action: &Action,
@@ -1103,30 +1961,421 @@ mod seat {
                 &button.keycodes,
                 time,
             ),
+            Action::Dead { keysym, label } => submission.handle_press(
+                button_pos.into(),
+                SubmitData::Dead { keysym: &keysym, label: &label },
+                &button.keycodes,
+                time,
+            ),
             _ => {},
         };
     }
     
-    pub fn handle_press_key(
-        layout: &mut Layout,
-        submission: &mut Submission,
-        time: Timestamp,
+    /// A locking button held by another touch point
+    /// takes effect when a new touch starts,
+    /// and lasts only until it's released.
+    pub fn hold_locks(layout: &mut Layout, touch: TouchId) {
+        if layout.state.held_lock.is_some() {
+            return;
+        }
+        let lock = layout.state.active_buttons.iter_pressed()
+            .filter(|(_button, t)| *t != touch)
+            .map(|(button, _t)| button)
+            .find(|button| match layout.shape.get_button(button) {
+                Some(Button { action: Action::LockView { .. }, .. }) => true,
+                _ => false,
+            })
+            .cloned();
+        if let Some(button) = lock {
+            let action = layout.shape.get_button(&button).unwrap()
+                .action.clone();
+            layout.state.held_lock = Some(HeldLock {
+                button,
+                view: layout.state.current_view.clone(),
+                latched: layout.state.view_latched.clone(),
+            });
+            layout.apply_view_transition(&action);
+        }
+    }
+
+    /// Starts tracking the touch point, if the button may begin a gesture.
+    /// The letter shows up right away, but stays replaceable
+    /// until the gesture ends.
+    /// Returns false if the button should be pressed as usual.
+    pub fn start_gesture(
+        layout: &mut Layout,
+        submission: &mut Submission,
+        touch: TouchId,
+        button_pos: &ButtonPosition,
+        point: c::Point,
+    ) -> bool {
+        if layout.state.gesture.is_some()
+            || !submission.accepts_gestures()
+            || !layout.may_start_gesture(submission, button_pos)
+        {
+            return false;
+        }
+        let letter = match layout.shape.get_button(button_pos)
+            .and_then(Button::get_letter)
+        {
+            Some(letter) => letter,
+            None => return false,
+        };
+        if layout.state.active_buttons.get(button_pos).pressed == PressType::Pressed {
+            log_print!(
+                logging::Level::Debug,
+                "Button {:?} was already pressed", button_pos,
+            );
+        } else if submission.start_gesture(&letter.to_string()) {
+            layout.press_button(touch, button_pos);
+            layout.state.gesture = Some(Gesture {
+                touch,
+                start: button_pos.clone(),
+                path: vec![point],
+            });
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Types the word drawn by the gesture,
+    /// or keeps the starting letter if the gesture was just a tap.
+    pub fn finish_gesture(
+        layout: &mut Layout,
+        submission: &mut Submission,
+        ui: Option<&UIBackend>,
+        time: Timestamp,
+        manager: Option<(&actors::popover::State, receiver::State)>,
+        gesture: Gesture,
+    ) {
+        let start = (gesture.start.row, gesture.start.position_in_row);
+        // Gaps between buttons don't count as leaving
+        let left_start = gesture.start.view == layout.state.current_view
+            && gesture.path.iter()
+                .filter_map(|point| {
+                    layout.find_index_by_movement(point.clone(), Some(start))
+                })
+                .any(|index| index != start);
+        let end_letter = gesture.path.last()
+            .filter(|_| left_start)
+            .and_then(|point| {
+                layout.find_index_by_movement(point.clone(), Some(start))
+            })
+            .map(|(row, position_in_row)| ButtonPosition {
+                view: layout.state.current_view.clone(),
+                row,
+                position_in_row,
+            })
+            .and_then(|button| layout.shape.get_button(&button))
+            .and_then(Button::get_letter)
+            .map(|letter| letter.to_string());
+        submission.finish_gesture(
+            &layout.get_letter_keys(),
+            match left_start {
+                true => Some(gesture.path.as_slice()),
+                false => None,
+            },
+            end_letter.as_deref(),
+        );
+        handle_release_key(layout, submission, ui, time, manager, &gesture.start);
+    }
+
+    /// Presses the button without typing it, if it can move the cursor.
+    /// Returns false if the button should be pressed as usual.
+    pub fn start_trackpad(
+        layout: &mut Layout,
+        touch: TouchId,
+        button_pos: &ButtonPosition,
+        point: c::Point,
+        time: Timestamp,
+    ) -> bool {
+        let moves_cursor = layout.shape.get_button(button_pos)
+            .map(|button| button.cursor_keys.is_some())
+            .unwrap_or(false);
+        if layout.state.trackpad.is_some() || !moves_cursor {
+            return false;
+        }
+        if layout.press_button(touch, button_pos) {
+            layout.state.trackpad = Some(Trackpad {
+                touch,
+                button: button_pos.clone(),
+                pressed_at: time.0,
+                origin: point,
+                moved: None,
+            });
+        } else {
+            log_print!(
+                logging::Level::Debug,
+                "Button {:?} was already pressed", button_pos,
+            );
+        }
+        true
+    }
+
+    pub fn is_trackpad(layout: &Layout, touch: TouchId) -> bool {
+        match &layout.state.trackpad {
+            Some(trackpad) => trackpad.touch == touch,
+            None => false,
+        }
+    }
+
+    /// Distance in layout coordinates moving the cursor by one step
+    fn get_trackpad_step(
+        shape: &LayoutData,
+        button: &Button,
+        widget_to_layout: &c::Transformation,
+    ) -> f64 {
+        let key_size = match shape.key_size {
+            Some(size) => {
+                size.as_scaled_ceiling() as f64 / widget_to_layout.scale_x
+            },
+            // Buttons are usually about as tall as keys are meant to be wide
+            None => button.size.height,
+        };
+        key_size * TRACKPAD_STEP
+    }
+
+    /// Sends arrow keys according to the movement of the touch point
+    pub fn move_trackpad(
+        layout: &mut Layout,
+        submission: &mut Submission,
+        widget_to_layout: &c::Transformation,
+        point: c::Point,
+        time: Timestamp,
+    ) {
+        let trackpad = match &mut layout.state.trackpad {
+            Some(trackpad) => trackpad,
+            None => return,
+        };
+        let button = match layout.shape.get_button(&trackpad.button) {
+            Some(button) => button,
+            None => return,
+        };
+        let keys = match &button.cursor_keys {
+            Some(keys) => keys,
+            None => return,
+        };
+        let step = get_trackpad_step(&layout.shape, button, widget_to_layout);
+        let (x, y) = trackpad.update(&point, time.0, step);
+
+        let horizontal = match x < 0 {
+            true => &keys.left,
+            false => &keys.right,
+        };
+        let vertical = match y < 0 {
+            true => &keys.up,
+            false => &keys.down,
+        };
+        let presses = iter::repeat(horizontal).take(x.unsigned_abs() as usize)
+            .chain(iter::repeat(vertical).take(y.unsigned_abs() as usize));
+        for keycode in presses {
+            submission.handle_press(
+                (&trackpad.button).into(),
+                SubmitData::Keycodes,
+                &vec![keycode.clone()],
+                time,
+            );
+            submission.handle_release((&trackpad.button).into(), time);
+        }
+    }
+
+    /// Types the button if it was only tapped
+    pub fn finish_trackpad(
+        layout: &mut Layout,
+        submission: &mut Submission,
+        ui: Option<&UIBackend>,
+        time: Timestamp,
+        manager: Option<(&actors::popover::State, receiver::State)>,
+        trackpad: Trackpad,
+    ) {
+        if !trackpad.is_active(time.0) {
+            handle_press_key_cleaner(&layout.shape, submission, time, &trackpad.button);
+        }
+        handle_release_key(layout, submission, ui, time, manager, &trackpad.button);
+    }
+
+    /// Starts watching for a swipe down, if the touch started on the top row
+    pub fn start_dismiss(
+        layout: &mut Layout,
+        touch: TouchId,
+        button_pos: &ButtonPosition,
+        point: c::Point,
+        panel_height: f64,
+    ) {
+        if layout.state.dismiss.is_none() && button_pos.row == 0 {
+            layout.state.dismiss = Some(Dismiss {
+                touch,
+                origin: point,
+                panel_height,
+                done: false,
+            });
+        }
+    }
+
+    /// The touch point may still hide the keyboard
+    pub fn is_dismissing(layout: &Layout, touch: TouchId) -> bool {
+        match &layout.state.dismiss {
+            Some(dismiss) => dismiss.touch == touch && !dismiss.done,
+            None => false,
+        }
+    }
+
+    /// Once the touch point moved down far enough,
+    /// lets go of its buttons without applying their actions.
+    /// The button it started on was already typed.
+    /// Returns true if it just happened.
+    pub fn reach_dismiss(
+        layout: &mut Layout,
+        submission: &mut Submission,
+        time: Timestamp,
+        touch: TouchId,
+        point: &c::Point,
+    ) -> bool {
+        match &mut layout.state.dismiss {
+            Some(dismiss) if dismiss.touch == touch && !dismiss.done => {
+                if !dismiss.is_reached(point) {
+                    return false;
+                }
+                dismiss.done = true;
+            },
+            _ => return false,
+        };
+        layout.state.take_gesture(touch);
+        layout.state.take_trackpad(touch);
+        let pressed_buttons = layout.state.active_buttons.clone();
+        for button in pressed_buttons.iter_pressed_by(touch) {
+            // Keys must not stay down
+            submission.handle_release(button.into(), time);
+            layout.state.active_buttons.remove(button);
+        }
+        true
+    }
+
+    /// Hides the keyboard once the touch point moved down far enough.
+    /// Returns true if the touch point must not affect buttons any more.
+    pub fn move_dismiss(
+        layout: &mut Layout,
+        submission: &mut Submission,
+        ui: Option<&UIBackend>,
+        time: Timestamp,
+        app_state: &receiver::State,
+        touch: TouchId,
+        point: &c::Point,
+    ) -> bool {
+        let done = layout.state.dismiss.as_ref()
+            .map_or(false, |dismiss| dismiss.touch == touch && dismiss.done);
+        if done {
+            return true;
+        }
+        let pressed_buttons: Vec<ButtonPosition>
+            = layout.state.active_buttons.iter_pressed_by(touch)
+                .cloned()
+                .collect();
+        if !reach_dismiss(layout, submission, time, touch, point) {
+            return false;
+        }
+        if let Some(ui) = ui {
+            let preview = unsafe { c::eek_gtk_keyboard_get_preview(ui.keyboard) };
+            if let Some(preview) = preview.get() {
+                for button in pressed_buttons.iter() {
+                    preview.hide(button);
+                }
+            }
+        }
+        // The letter of a gesture is only in the preedit
+        submission.cancel_gesture();
+        app_state.send(state::Event::Visibility(visibility::Event::ForceHidden))
+            .or_print(logging::Problem::Bug, "Can't send to state manager");
+        true
+    }
+
+    pub fn is_holding_lock(layout: &Layout, touch: TouchId) -> bool {
+        match &layout.state.held_lock {
+            Some(HeldLock { button, .. }) => {
+                layout.state.active_buttons.iter_pressed_by(touch)
+                    .any(|b| b == button)
+            },
+            None => false,
+        }
+    }
+
+    pub fn handle_press_key(
+        layout: &mut Layout,
+        submission: &mut Submission,
+        time: Timestamp,
+        touch: TouchId,
         button_pos: &ButtonPosition,
     ) {
-        // Send messages
-        handle_press_key_cleaner(&layout.shape, submission, time, button_pos);
-    
         // Update state
-        let find = layout.state.active_buttons.get(button_pos);
-        if let KeyState { pressed: PressType::Pressed } = find {
+        if !layout.press_button(touch, button_pos) {
+            // Another touch point is holding the button already
             log_print!(
-                logging::Level::Bug,
+                logging::Level::Debug,
                 "Button {:?} was already pressed", button_pos,
             );
-        } else {
-            layout.state.active_buttons.insert(
-                button_pos.clone(),
-                KeyState { pressed: PressType::Pressed },
+            return;
+        }
+
+        // Send messages
+        handle_press_key_cleaner(&layout.shape, submission, time, button_pos);
+    }
+
+    /// Lets the user feel what kind of button got pressed
+    pub fn emit_feedback(
+        layout: &Layout,
+        keyboard: c::EekGtkKeyboard,
+        button_pos: &ButtonPosition,
+    ) {
+        let button = match layout.shape.get_button(button_pos) {
+            Some(button) => button,
+            None => return,
+        };
+        let feedback = unsafe { feedback::c::eek_gtk_keyboard_get_feedback(keyboard) };
+        if let Some(feedback) = feedback.get() {
+            feedback.emit(
+                &mut feedback::c::KeyboardSink(keyboard),
+                feedback::Role::from_action(&button.action),
+            );
+        }
+    }
+
+    /// Shows the pressed button magnified above the finger,
+    /// unless the text must stay hidden.
+    pub fn show_preview(
+        layout: &Layout,
+        submission: &Submission,
+        ui: &UIBackend,
+        button_pos: &ButtonPosition,
+    ) {
+        if layout.is_text_hidden(submission) {
+            return;
+        }
+        let view_offset = match layout.shape.views.get(&button_pos.view) {
+            Some((offset, _view)) => offset,
+            None => return,
+        };
+        let (position, button) = match layout.shape.find_button_place(button_pos) {
+            Some(place) => place,
+            None => return,
+        };
+        let label = match button.get_preview_label() {
+            Some(label) => label,
+            None => return,
+        };
+        let position = view_offset + position;
+        let bounds = c::Bounds {
+            x: position.x,
+            y: position.y,
+            width: button.size.width,
+            height: button.size.height,
+        };
+        let preview = unsafe { c::eek_gtk_keyboard_get_preview(ui.keyboard) };
+        if let Some(preview) = preview.get() {
+            preview.show(
+                button_pos,
+                label,
+                ui.widget_to_layout.reverse_bounds(bounds),
             );
         }
     }
@@ -1150,6 +2399,7 @@ mod seat {
         match action.clone() {
             Action::Submit { text: _, keys: _ }
                 | Action::Erase
+                | Action::Dead { keysym: _, label: _ }
             => {
                 submission.handle_release(button_pos.into(), time);
             },
@@ -1187,6 +2437,11 @@ mod seat {
                     }
                 }
             },
+            Action::HideKeyboard => if let Some((_manager, app_state)) = manager {
+                app_state
+                    .send(state::Event::Visibility(visibility::Event::ForceHidden))
+                    .or_print(logging::Problem::Bug, "Can't send to state manager");
+            },
             // Other keys are handled in view switcher before.
             _ => {}
         };
@@ -1220,14 +2475,22 @@ mod seat {
             button_pos,
         );
         
+        if let Some(ui) = ui {
+            let preview = unsafe { c::eek_gtk_keyboard_get_preview(ui.keyboard) };
+            if let Some(preview) = preview.get() {
+                preview.hide(button_pos);
+            }
+        }
+
         // Apply state changes
-        layout.apply_view_transition(&action);
-        
-        if let Presence::Missing = layout.state.active_buttons.remove(&button_pos) {
-            log_print!(
-                logging::Level::Bug,
-                "No button to remove from pressed list: {:?}", button_pos
-            );
+        layout.release_button(button_pos, &action);
+
+        if let Action::ToggleOneHanded = action {
+            layout.shape.one_handed = one_handed::toggle(layout.shape.one_handed);
+            // The transformation gets recalculated on allocation
+            if let Some(ui) = ui {
+                one_handed::refresh(ui.keyboard);
+            }
         }
     }
 }
@@ -1237,6 +2500,7 @@ mod test {
     use super::*;
 
     use std::ffi::CString;
+    use crate::vkeyboard::c::ZwpVirtualKeyboardV1;
 
     pub fn make_button(
         name: String,
@@ -1248,9 +2512,42 @@ mod test {
             label: Label::Text(CString::new(name).unwrap()),
             action: Action::SetView("default".into()),
             keycodes: Vec::new(),
+            cursor_keys: None,
         }
     }
 
+    /// A base layout without margins, and with all the extras turned off
+    fn make_layout_data(
+        views: HashMap<String, (c::Point, View)>,
+    ) -> LayoutData {
+        LayoutData {
+            keymaps: Vec::new(),
+            composition: None,
+            key_size: None,
+            targeting: None,
+            split: false,
+            one_handed: None,
+            safe_area: Default::default(),
+            fit: Default::default(),
+            gestures: false,
+            kind: ArrangementKind::Base,
+            margins: Margins {
+                top: 0.0,
+                left: 0.0,
+                right: 0.0,
+                bottom: 0.0,
+            },
+            views,
+            purpose: ContentPurpose::Normal,
+            fitted: None,
+        }
+    }
+
+    /// Not connected to any text field
+    fn make_submission() -> Submission {
+        Submission::new(ZwpVirtualKeyboardV1::null(), None)
+    }
+
     #[test]
     fn latch_lock_unlock() {
         let action = Action::LockView {
@@ -1317,25 +2614,18 @@ mod test {
                 current_view: "base".into(),
                 view_latched: LatchedState::Not,
                 active_buttons: ActiveButtons(HashMap::new()),
+                held_lock: None,
+                gesture: None,
+                trackpad: None,
+                dismiss: None,
             },
-            shape: LayoutData {
-                keymaps: Vec::new(),
-                kind: ArrangementKind::Base,
-                margins: Margins {
-                    top: 0.0,
-                    left: 0.0,
-                    right: 0.0,
-                    bottom: 0.0,
-                },
-                views: hashmap! {
-                    // Both can use the same structure.
-                    // Switching doesn't depend on the view shape
-                    // as long as the switching button is present.
-                    "base".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
-                    "locked".into() => (c::Point { x: 0.0, y: 0.0 }, view),
-                },
-                purpose: ContentPurpose::Normal,
-            },
+            shape: make_layout_data(hashmap! {
+                // Both can use the same structure.
+                // Switching doesn't depend on the view shape
+                // as long as the switching button is present.
+                "base".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
+                "locked".into() => (c::Point { x: 0.0, y: 0.0 }, view),
+            }),
         };
 
         // Basic cycle
@@ -1397,26 +2687,19 @@ mod test {
                 current_view: "base".into(),
                 view_latched: LatchedState::Not,
                 active_buttons: ActiveButtons(HashMap::new()),
+                held_lock: None,
+                gesture: None,
+                trackpad: None,
+                dismiss: None,
             },
-            shape: LayoutData {
-                keymaps: Vec::new(),
-                kind: ArrangementKind::Base,
-                margins: Margins {
-                    top: 0.0,
-                    left: 0.0,
-                    right: 0.0,
-                    bottom: 0.0,
-                },
-                views: hashmap! {
-                    // Both can use the same structure.
-                    // Switching doesn't depend on the view shape
-                    // as long as the switching button is present.
-                    "base".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
-                    "locked".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
-                    "unlocked".into() => (c::Point { x: 0.0, y: 0.0 }, view),
-                },
-                purpose: ContentPurpose::Normal,
-            },
+            shape: make_layout_data(hashmap! {
+                // Both can use the same structure.
+                // Switching doesn't depend on the view shape
+                // as long as the switching button is present.
+                "base".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
+                "locked".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
+                "unlocked".into() => (c::Point { x: 0.0, y: 0.0 }, view),
+            }),
         };
 
         layout.apply_view_transition(&switch);
@@ -1468,26 +2751,19 @@ mod test {
                 current_view: "base".into(),
                 view_latched: LatchedState::Not,
                 active_buttons: ActiveButtons(HashMap::new()),
+                held_lock: None,
+                gesture: None,
+                trackpad: None,
+                dismiss: None,
             },
-            shape: LayoutData {
-                keymaps: Vec::new(),
-                kind: ArrangementKind::Base,
-                margins: Margins {
-                    top: 0.0,
-                    left: 0.0,
-                    right: 0.0,
-                    bottom: 0.0,
-                },
-                views: hashmap! {
-                    // All can use the same structure.
-                    // Switching doesn't depend on the view shape
-                    // as long as the switching button is present.
-                    "base".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
-                    "locked".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
-                    "ĄĘ".into() => (c::Point { x: 0.0, y: 0.0 }, view),
-                },
-                purpose: ContentPurpose::Normal,
-            },
+            shape: make_layout_data(hashmap! {
+                // All can use the same structure.
+                // Switching doesn't depend on the view shape
+                // as long as the switching button is present.
+                "base".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
+                "locked".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
+                "ĄĘ".into() => (c::Point { x: 0.0, y: 0.0 }, view),
+            }),
         };
 
         // Latch twice, then Ąto-unlatch across 2 levels
@@ -1502,6 +2778,443 @@ mod test {
         assert_eq!(&layout.state.current_view, "base");
     }
 
+    /// A layout with a Shift-like button and a text-like button
+    fn make_lock_layout() -> Layout {
+        let view = View::new(vec![(
+            0.0,
+            Row::new(vec![
+                (
+                    0.0,
+                    Button {
+                        action: Action::LockView {
+                            lock: "locked".into(),
+                            unlock: "base".into(),
+                            latches: true,
+                            looks_locked_from: vec![],
+                        },
+                        ..make_button("switch".into())
+                    },
+                ),
+                (
+                    1.0,
+                    Button {
+                        action: Action::Erase,
+                        ..make_button("submit".into())
+                    },
+                ),
+            ]),
+        )]);
+
+        Layout {
+            state: LayoutState {
+                current_view: "base".into(),
+                view_latched: LatchedState::Not,
+                active_buttons: ActiveButtons(HashMap::new()),
+                held_lock: None,
+                gesture: None,
+                trackpad: None,
+                dismiss: None,
+            },
+            shape: make_layout_data(hashmap! {
+                "base".into() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
+                "locked".into() => (c::Point { x: 0.0, y: 0.0 }, view),
+            }),
+        }
+    }
+
+    /// Rotating keeps the latched view
+    #[test]
+    fn keep_view() {
+        let mut previous = make_lock_layout();
+        previous.apply_view_transition(&Action::LockView {
+            lock: "locked".into(),
+            unlock: "base".into(),
+            latches: true,
+            looks_locked_from: vec![],
+        });
+        assert_eq!(
+            previous.get_view_latched(),
+            &LatchedState::FromView("base".into()),
+        );
+
+        let mut layout = make_lock_layout();
+        layout.shape.kind = ArrangementKind::Wide;
+        layout.keep_view_from(&previous);
+        assert_eq!(&layout.state.current_view, "locked");
+        assert_eq!(layout.get_view_latched(), previous.get_view_latched());
+
+        // The view may be missing in the other arrangement
+        let mut layout = make_lock_layout();
+        layout.shape.views.remove("locked");
+        layout.keep_view_from(&previous);
+        assert_eq!(&layout.state.current_view, "base");
+        assert_eq!(layout.get_view_latched(), &LatchedState::Not);
+    }
+
+    fn position(view: &str, position_in_row: usize) -> ButtonPosition {
+        ButtonPosition {
+            view: view.into(),
+            row: 0,
+            position_in_row,
+        }
+    }
+
+    fn release(layout: &mut Layout, button: &ButtonPosition) {
+        let action = layout.shape.get_button(button).unwrap().action.clone();
+        layout.release_button(button, &action);
+    }
+
+    /// Each touch point owns the buttons it pressed
+    #[test]
+    fn touch_owns_buttons() {
+        let mut layout = make_lock_layout();
+        assert!(layout.press_button(TouchId(1), &position("base", 0)));
+        assert!(layout.press_button(TouchId(2), &position("base", 1)));
+        assert_eq!(
+            layout.state.active_buttons.iter_pressed_by(TouchId(2))
+                .collect::<Vec<_>>(),
+            vec![&position("base", 1)],
+        );
+        // A button held by another touch point can't be pressed again
+        assert!(!layout.press_button(TouchId(3), &position("base", 1)));
+        assert_eq!(
+            layout.state.active_buttons.iter_pressed_by(TouchId(3)).count(),
+            0,
+        );
+    }
+
+    /// Holding a lock while typing with another finger doesn't latch it
+    #[test]
+    fn hold_lock() {
+        let mut layout = make_lock_layout();
+        layout.press_button(TouchId(1), &position("base", 0));
+        assert_eq!(&layout.state.current_view, "base");
+
+        seat::hold_locks(&mut layout, TouchId(2));
+        assert_eq!(&layout.state.current_view, "locked");
+        assert!(seat::is_holding_lock(&layout, TouchId(1)));
+        assert!(!seat::is_holding_lock(&layout, TouchId(2)));
+        layout.press_button(TouchId(2), &position("locked", 1));
+        release(&mut layout, &position("locked", 1));
+        // Still held
+        assert_eq!(&layout.state.current_view, "locked");
+
+        release(&mut layout, &position("base", 0));
+        assert_eq!(&layout.state.current_view, "base");
+        assert_eq!(layout.state.view_latched, LatchedState::Not);
+        assert_eq!(layout.state.held_lock, None);
+    }
+
+    /// Tapping the lock alone behaves as before
+    #[test]
+    fn tap_lock() {
+        let mut layout = make_lock_layout();
+        layout.press_button(TouchId(1), &position("base", 0));
+        release(&mut layout, &position("base", 0));
+        assert_eq!(&layout.state.current_view, "locked");
+        assert_eq!(
+            layout.state.view_latched,
+            LatchedState::FromView("base".into()),
+        );
+        // A new touch doesn't find any held lock
+        seat::hold_locks(&mut layout, TouchId(2));
+        assert_eq!(layout.state.held_lock, None);
+    }
+
+    fn make_letter_layout(purpose: ContentPurpose) -> Layout {
+        let letter = |text: &str| Button {
+            size: Size { width: 10.0, height: 20.0 },
+            action: Action::Submit {
+                text: Some(CString::new(text).unwrap()),
+                keys: Vec::new(),
+            },
+            ..make_button(text.into())
+        };
+        let view = View::new(vec![(
+            0.0,
+            Row::new(vec![
+                (0.0, letter("a")),
+                (10.0, letter("b")),
+                (20.0, letter(",")),
+                (30.0, Button { action: Action::Erase, ..letter("erase") }),
+            ]),
+        )]);
+        let symbols = View::new(vec![(
+            0.0,
+            Row::new(vec![
+                (0.0, letter("a")),
+                (10.0, letter("1")),
+                (20.0, letter("2")),
+            ]),
+        )]);
+        let mut layout = make_lock_layout();
+        layout.shape.purpose = purpose;
+        layout.shape.gestures = true;
+        layout.shape.views = hashmap! {
+            "base".into() => (c::Point { x: 0.0, y: 0.0 }, view),
+            "symbols".into() => (c::Point { x: 0.0, y: 0.0 }, symbols),
+        };
+        layout
+    }
+
+    #[test]
+    fn gesture_letters() {
+        let layout = make_letter_layout(ContentPurpose::Normal);
+        let submission = make_submission();
+        assert!(layout.may_start_gesture(&submission, &position("base", 0)));
+        // Not a letter
+        assert!(!layout.may_start_gesture(&submission, &position("base", 2)));
+        assert!(!layout.may_start_gesture(&submission, &position("base", 3)));
+        assert_eq!(
+            layout.get_letter_keys(),
+            vec![
+                swipe::Key { letter: 'a', center: c::Point { x: 5.0, y: 10.0 } },
+                swipe::Key { letter: 'b', center: c::Point { x: 15.0, y: 10.0 } },
+            ],
+        );
+    }
+
+    /// Gestures are off unless turned on
+    #[test]
+    fn gesture_disabled() {
+        let mut layout = make_letter_layout(ContentPurpose::Normal);
+        layout.shape.gestures = false;
+        let submission = make_submission();
+        assert!(!layout.may_start_gesture(&submission, &position("base", 0)));
+    }
+
+    /// The strip stays for gestures, but not where gestures can't start
+    #[test]
+    fn gesture_strip() {
+        let mut layout = make_letter_layout(ContentPurpose::Normal);
+        assert!(layout.shape.shows_candidates());
+        layout.shape.gestures = false;
+        assert!(!layout.shape.shows_candidates());
+        for purpose in &[ContentPurpose::Pin, ContentPurpose::Number] {
+            let layout = make_letter_layout(*purpose);
+            assert!(!layout.shape.shows_candidates());
+        }
+    }
+
+    /// Letters among mostly other buttons don't start gestures
+    #[test]
+    fn gesture_symbols() {
+        let mut layout = make_letter_layout(ContentPurpose::Normal);
+        layout.state.current_view = "symbols".into();
+        let submission = make_submission();
+        assert!(!layout.may_start_gesture(&submission, &position("symbols", 0)));
+    }
+
+    #[test]
+    fn preview_label() {
+        let text = |label: &str| Button {
+            label: Label::Text(CString::new(label).unwrap()),
+            action: Action::Submit {
+                text: Some(CString::new("x").unwrap()),
+                keys: Vec::new(),
+            },
+            ..make_button("x".into())
+        };
+        assert_eq!(text("a").get_preview_label(), Some("a"));
+        // Nothing to show
+        assert_eq!(text(" ").get_preview_label(), None);
+        assert_eq!(
+            Button {
+                label: Label::IconName(CString::new("icon").unwrap()),
+                ..text("a")
+            }.get_preview_label(),
+            None,
+        );
+        // Doesn't type
+        assert_eq!(make_button("a".into()).get_preview_label(), None);
+    }
+
+    #[test]
+    fn targeting() {
+        let mut layout = make_letter_layout(ContentPurpose::Normal);
+        let point = c::Point { x: 9.0, y: 10.0 };
+        let words = swipe::WordList::parse("b\n");
+        let prediction = targeting::Prediction::from_words(&words, "");
+        // Strict bounds without the model
+        assert_eq!(
+            layout.find_index_by_target(point.clone(), prediction.as_ref()),
+            Some((0, 0)),
+        );
+        layout.shape.targeting = Some(targeting::Model { touch_offset: 0.0 });
+        assert_eq!(
+            layout.find_index_by_target(point.clone(), None),
+            Some((0, 0)),
+        );
+        assert_eq!(
+            layout.find_index_by_target(point, prediction.as_ref()),
+            Some((0, 1)),
+        );
+        // Outside of the view
+        assert_eq!(
+            layout.find_index_by_target(
+                c::Point { x: 9.0, y: 30.0 },
+                prediction.as_ref(),
+            ),
+            None,
+        );
+    }
+
+    /// A moving touch stays on the pressed button near its edge
+    #[test]
+    fn targeting_movement() {
+        let mut layout = make_letter_layout(ContentPurpose::Normal);
+        let point = c::Point { x: 11.0, y: 10.0 };
+        // Strict bounds without the model
+        assert_eq!(
+            layout.find_index_by_movement(point.clone(), Some((0, 0))),
+            Some((0, 1)),
+        );
+        layout.shape.targeting = Some(targeting::Model { touch_offset: 0.0 });
+        assert_eq!(
+            layout.find_index_by_movement(point.clone(), Some((0, 0))),
+            Some((0, 0)),
+        );
+        assert_eq!(layout.find_index_by_movement(point, None), Some((0, 1)));
+        // Too far
+        assert_eq!(
+            layout.find_index_by_movement(c::Point { x: 14.0, y: 10.0 }, Some((0, 0))),
+            Some((0, 1)),
+        );
+    }
+
+    #[test]
+    fn gesture_hidden_text() {
+        let submission = make_submission();
+        for purpose in &[ContentPurpose::Password, ContentPurpose::Pin] {
+            let layout = make_letter_layout(*purpose);
+            assert!(!layout.may_start_gesture(&submission, &position("base", 0)));
+        }
+    }
+
+    fn make_trackpad() -> Trackpad {
+        Trackpad {
+            touch: TouchId(0),
+            button: position("base", 0),
+            pressed_at: 1000,
+            origin: c::Point { x: 10.0, y: 10.0 },
+            moved: None,
+        }
+    }
+
+    /// A horizontal drag turns the button into a trackpad
+    #[test]
+    fn trackpad_drag() {
+        let mut trackpad = make_trackpad();
+        // Too little
+        assert_eq!(trackpad.update(&c::Point { x: 11.0, y: 10.0 }, 1010, 2.0), (0, 0));
+        assert!(!trackpad.is_active(1010));
+        assert_eq!(trackpad.update(&c::Point { x: 14.5, y: 10.0 }, 1020, 2.0), (2, 0));
+        assert_eq!(trackpad.update(&c::Point { x: 9.0, y: 10.0 }, 1030, 2.0), (-2, 0));
+        // Once active, vertical movement moves the cursor too
+        assert_eq!(trackpad.update(&c::Point { x: 9.0, y: 14.0 }, 1040, 2.0), (0, 2));
+        assert!(trackpad.is_active(1040));
+    }
+
+    /// Vertical movement alone is not enough to start
+    #[test]
+    fn trackpad_vertical() {
+        let mut trackpad = make_trackpad();
+        assert_eq!(trackpad.update(&c::Point { x: 10.0, y: 20.0 }, 1010, 2.0), (0, 0));
+        assert!(!trackpad.is_active(1010));
+    }
+
+    #[test]
+    fn trackpad_long_press() {
+        let mut trackpad = make_trackpad();
+        assert!(trackpad.is_active(1000 + TRACKPAD_LONG_PRESS_MS));
+        assert_eq!(trackpad.update(&c::Point { x: 10.0, y: 14.0 }, 1600, 2.0), (0, 2));
+    }
+
+    fn make_dismiss() -> Dismiss {
+        Dismiss {
+            touch: TouchId(1),
+            origin: c::Point { x: 50.0, y: 10.0 },
+            panel_height: 100.0,
+            done: false,
+        }
+    }
+
+    /// The swipe must go far enough down
+    #[test]
+    fn dismiss_distance() {
+        let dismiss = make_dismiss();
+        assert!(!dismiss.is_reached(&c::Point { x: 50.0, y: 60.0 }));
+        assert!(dismiss.is_reached(&c::Point { x: 50.0, y: 70.0 }));
+        assert!(dismiss.is_reached(&c::Point { x: 60.0, y: 90.0 }));
+        // Up is never enough
+        assert!(!dismiss.is_reached(&c::Point { x: 50.0, y: -90.0 }));
+    }
+
+    /// Sideways paths are left for typing gestures
+    #[test]
+    fn dismiss_sideways() {
+        let dismiss = make_dismiss();
+        assert!(!dismiss.is_reached(&c::Point { x: 0.0, y: 80.0 }));
+    }
+
+    /// Only the top row starts a swipe
+    #[test]
+    fn dismiss_top_row() {
+        let mut layout = make_letter_layout(ContentPurpose::Normal);
+        let point = c::Point { x: 0.0, y: 0.0 };
+        let lower = ButtonPosition { row: 1, ..position("base", 0) };
+        seat::start_dismiss(&mut layout, TouchId(1), &lower, point.clone(), 100.0);
+        assert_eq!(layout.state.dismiss, None);
+
+        seat::start_dismiss(&mut layout, TouchId(1), &position("base", 0), point.clone(), 100.0);
+        assert_eq!(
+            layout.state.take_dismiss(TouchId(1)).map(|d| d.touch),
+            Some(TouchId(1)),
+        );
+    }
+
+    /// The key swiped away from gets let go when the keyboard hides
+    #[test]
+    fn dismiss_typed() {
+        let mut layout = make_letter_layout(ContentPurpose::Normal);
+        layout.state.current_view = "symbols".into();
+        let mut submission = make_submission();
+        let button = position("symbols", 1);
+        let touch = TouchId(1);
+        seat::start_dismiss(&mut layout, touch, &button, c::Point { x: 15.0, y: 10.0 }, 100.0);
+        assert!(layout.press_button(touch, &button));
+        assert_eq!(layout.state.active_buttons.iter_pressed_by(touch).count(), 1);
+
+        assert!(!seat::reach_dismiss(&mut layout, &mut submission, Timestamp(1), touch, &c::Point { x: 15.0, y: 40.0 }));
+        assert!(seat::is_dismissing(&layout, touch));
+
+        assert!(seat::reach_dismiss(&mut layout, &mut submission, Timestamp(2), touch, &c::Point { x: 15.0, y: 80.0 }));
+        assert!(!seat::is_dismissing(&layout, touch));
+        assert_eq!(layout.state.active_buttons.iter_pressed_by(touch).count(), 0);
+        assert!(layout.state.take_dismiss(touch).unwrap().done);
+    }
+
+    /// The button swiped away from doesn't apply its action
+    #[test]
+    fn dismiss_lock() {
+        let mut layout = make_lock_layout();
+        let mut submission = make_submission();
+        let touch = TouchId(1);
+        seat::start_dismiss(&mut layout, touch, &position("base", 0), c::Point { x: 0.0, y: 0.0 }, 100.0);
+        assert!(layout.press_button(touch, &position("base", 0)));
+        assert!(seat::reach_dismiss(&mut layout, &mut submission, Timestamp(1), touch, &c::Point { x: 0.0, y: 90.0 }));
+        assert_eq!(&layout.state.current_view, "base");
+        assert_eq!(layout.state.view_latched, LatchedState::Not);
+    }
+
+    #[test]
+    fn gesture_composition() {
+        let mut layout = make_letter_layout(ContentPurpose::Normal);
+        layout.shape.composition = Some(composition::Kind::Hangul);
+        let submission = make_submission();
+        assert!(!layout.may_start_gesture(&submission, &position("base", 0)));
+    }
+
     #[test]
     fn check_centering() {
         //    A B
@@ -1573,8 +3286,6 @@ mod test {
             ),
         ]);
         let layout = LayoutData {
-            keymaps: Vec::new(),
-            kind: ArrangementKind::Base,
             // Lots of bottom margin
             margins: Margins {
                 top: 0.0,
@@ -1582,10 +3293,9 @@ mod test {
                 right: 0.0,
                 bottom: 1.0,
             },
-            views: hashmap! {
+            ..make_layout_data(hashmap! {
                 String::new() => (c::Point { x: 0.0, y: 0.0 }, view),
-            },
-            purpose: ContentPurpose::Normal,
+            })
         };
         assert_eq!(
             layout.calculate_inner_size(),
@@ -1622,20 +3332,9 @@ mod test {
                 )]),
             ),
         ]);
-        let layout = LayoutData {
-            keymaps: Vec::new(),
-            kind: ArrangementKind::Base,
-            margins: Margins {
-                top: 0.0,
-                left: 0.0,
-                right: 0.0,
-                bottom: 0.0,
-            },
-            views: hashmap! {
-                String::new() => (c::Point { x: 0.0, y: 0.0 }, view),
-            },
-            purpose: ContentPurpose::Normal,
-        };
+        let layout = make_layout_data(hashmap! {
+            String::new() => (c::Point { x: 0.0, y: 0.0 }, view),
+        });
         let transformation = layout.calculate_transformation(
             Size { width: 100.0, height: 100.0 }
         );
@@ -1657,4 +3356,237 @@ mod test {
         assert_eq!(transformation.scale_x, 100.0);
         assert_eq!(transformation.scale_y, 100.0);
     }
+
+    fn get_offsets(layout: &LayoutData, row: usize) -> Vec<f64> {
+        let (_, view) = layout.views.get("").unwrap();
+        let (position, row) = &view.get_rows()[row];
+        row.get_buttons().iter()
+            .map(|(offset, _button)| position.x + offset)
+            .collect()
+    }
+
+    #[test]
+    fn split_rows() {
+        let button = Button {
+            size: Size { width: 1.0, height: 1.0 },
+            ..make_button("foo".into())
+        };
+        let wide = Button {
+            size: Size { width: 2.0, height: 1.0 },
+            ..button.clone()
+        };
+        let view = View::new(vec![
+            (
+                0.0,
+                Row::new(
+                    (0..4).map(|i| (i as f64, button.clone())).collect()
+                ),
+            ),
+            (1.0, Row::new(vec![(0.0, wide)])),
+        ]);
+        let mut layout = LayoutData {
+            split: true,
+            ..make_layout_data(hashmap! {
+                String::new() => (c::Point { x: 0.0, y: 0.0 }, view.clone()),
+            })
+        };
+        let available = Size { width: 10.0, height: 2.0 };
+        layout.fit(available.clone());
+        // Halves touch the edges
+        assert_eq!(get_offsets(&layout, 0), vec![0.0, 1.0, 8.0, 9.0]);
+        // Single buttons stay in the middle
+        assert_eq!(get_offsets(&layout, 1), vec![4.0]);
+        let transformation = layout.calculate_transformation(available.clone());
+        assert_eq!(transformation.scale_x, 1.0);
+        assert_eq!(transformation.origin_x, 0.0);
+
+        // Nothing changed, so the views are not touched
+        layout.views.get_mut("").unwrap().1 = view;
+        layout.fit(available.clone());
+        assert_eq!(get_offsets(&layout, 0), vec![0.0, 1.0, 2.0, 3.0]);
+
+        layout.split = false;
+        layout.fit(available);
+        assert_eq!(get_offsets(&layout, 0), vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(get_offsets(&layout, 1), vec![1.0]);
+    }
+
+    #[test]
+    fn split_declared() {
+        let button = Button {
+            size: Size { width: 1.0, height: 1.0 },
+            ..make_button("foo".into())
+        };
+        let row = Row::new(
+            (0..4).map(|i| (i as f64, button.clone())).collect()
+        ).with_split(1);
+        let row = row.spread_to(6.0);
+        assert_eq!(
+            row.get_buttons().iter().map(|(offset, _)| *offset).collect::<Vec<_>>(),
+            vec![0.0, 3.0, 4.0, 5.0],
+        );
+        assert_eq!(row.get_size().width, 6.0);
+    }
+
+    #[test]
+    fn one_handed() {
+        let view = View::new(vec![
+            (
+                0.0,
+                Row::new(vec![(
+                    0.0,
+                    Button {
+                        size: Size { width: 1.0, height: 1.0 },
+                        ..make_button("foo".into())
+                    },
+                )]),
+            ),
+        ]);
+        let layout = LayoutData {
+            one_handed: Some(one_handed::Mode {
+                side: one_handed::Side::Right,
+                width: 0.5,
+            }),
+            ..make_layout_data(hashmap! {
+                String::new() => (c::Point { x: 0.0, y: 0.0 }, view),
+            })
+        };
+        let transformation = layout.calculate_transformation(
+            Size { width: 100.0, height: 50.0 }
+        );
+        assert_eq!(transformation.origin_x, 50.0);
+        assert_eq!(transformation.scale_x, 50.0);
+        assert_eq!(transformation.scale_y, 50.0);
+
+        // Touches in the empty part don't press anything
+        let (_offset, view) = layout.views.get("").unwrap();
+        let to_layout = |x: f64, y: f64| c::Point {
+            x: (x - transformation.origin_x) / transformation.scale_x,
+            y: (y - transformation.origin_y) / transformation.scale_y,
+        };
+        assert!(view.find_button_by_position(to_layout(25.0, 25.0)).is_none());
+        assert!(view.find_button_by_position(to_layout(75.0, 25.0)).is_some());
+    }
+
+    #[test]
+    fn safe_area() {
+        let view = View::new(vec![
+            (
+                0.0,
+                Row::new(vec![(
+                    0.0,
+                    Button {
+                        size: Size { width: 1.0, height: 1.0 },
+                        ..make_button("foo".into())
+                    },
+                )]),
+            ),
+        ]);
+        let layout = LayoutData {
+            // Rounded corners at the bottom
+            safe_area: devices::Insets {
+                top: 0.0,
+                bottom: 10.0,
+                left: 5.0,
+                right: 5.0,
+            },
+            ..make_layout_data(hashmap! {
+                String::new() => (c::Point { x: 0.0, y: 0.0 }, view),
+            })
+        };
+        let transformation = layout.calculate_transformation(
+            Size { width: 60.0, height: 60.0 }
+        );
+        assert_eq!(transformation.scale_x, 50.0);
+        assert_eq!(transformation.scale_y, 50.0);
+        assert_eq!(transformation.origin_x, 5.0);
+        assert_eq!(transformation.origin_y, 0.0);
+    }
+
+    /// Points on the layout land where the fit mode places them,
+    /// and find their way back
+    #[test]
+    fn fit_modes() {
+        let view = View::new(vec![
+            (
+                0.0,
+                Row::new(vec![(
+                    0.0,
+                    Button {
+                        size: Size { width: 1.0, height: 1.0 },
+                        ..make_button("foo".into())
+                    },
+                )]),
+            ),
+        ]);
+        let mut layout = LayoutData {
+            fit: fit::Mode::Bottom,
+            ..make_layout_data(hashmap! {
+                String::new() => (c::Point { x: 0.0, y: 0.0 }, view),
+            })
+        };
+        let available = Size { width: 100.0, height: 200.0 };
+        let to_panel = |t: &c::Transformation, p: &c::Point| c::Point {
+            x: t.origin_x + p.x * t.scale_x,
+            y: t.origin_y + p.y * t.scale_y,
+        };
+        let to_layout = |t: &c::Transformation, p: &c::Point| c::Point {
+            x: (p.x - t.origin_x) / t.scale_x,
+            y: (p.y - t.origin_y) / t.scale_y,
+        };
+        // The bottom right corner of the button
+        let corner = c::Point { x: 1.0, y: 1.0 };
+
+        let transformation = layout.calculate_transformation(available.clone());
+        let on_panel = to_panel(&transformation, &corner);
+        assert_eq!(on_panel, c::Point { x: 100.0, y: 200.0 });
+        assert_eq!(to_layout(&transformation, &on_panel), corner);
+        // The top of the panel stays empty
+        assert_eq!(transformation.origin_y, 100.0);
+
+        layout.fit = fit::Mode::Stretch;
+        let transformation = layout.calculate_transformation(available);
+        let on_panel = to_panel(&transformation, &corner);
+        assert_eq!(on_panel, c::Point { x: 100.0, y: 200.0 });
+        assert_eq!(to_layout(&transformation, &on_panel), corner);
+        assert_eq!(
+            to_panel(&transformation, &c::Point { x: 0.0, y: 0.0 }),
+            c::Point { x: 0.0, y: 0.0 },
+        );
+    }
+
+    /// Keys get wider, but the gaps between them stay
+    #[test]
+    fn fit_stretch_keys() {
+        let button = Button {
+            size: Size { width: 1.0, height: 1.0 },
+            ..make_button("foo".into())
+        };
+        let view = View::new(vec![
+            (
+                0.0,
+                Row::new(vec![(0.0, button.clone()), (2.0, button.clone())]),
+            ),
+        ]);
+        let mut layout = LayoutData {
+            fit: fit::Mode::StretchKeys,
+            ..make_layout_data(hashmap! {
+                String::new() => (c::Point { x: 0.0, y: 0.0 }, view),
+            })
+        };
+        let available = Size { width: 5.0, height: 1.0 };
+        layout.fit(available.clone());
+        assert_eq!(get_offsets(&layout, 0), vec![0.0, 3.0]);
+        let (_, view) = layout.views.get("").unwrap();
+        let (_, row) = &view.get_rows()[0];
+        assert_eq!(row.get_buttons()[1].1.size.width, 2.0);
+        let transformation = layout.calculate_transformation(available);
+        assert_eq!(transformation.scale_x, 1.0);
+        assert_eq!(transformation.origin_x, 0.0);
+
+        // Going back to the declared size
+        layout.fit = fit::Mode::Center;
+        layout.fit(Size { width: 5.0, height: 1.0 });
+        assert_eq!(get_offsets(&layout, 0), vec![0.0, 2.0]);
+    }
 }
@@ -25,23 +25,32 @@ mod logging;
 mod action;
 pub mod actors;
 mod animation;
+mod composition;
 pub mod data;
+mod devices;
 mod drawing;
 mod event_loop;
+mod feedback;
+mod fit;
 pub mod float_ord;
 pub mod imservice;
 mod keyboard;
 mod layout;
 mod locale;
 mod main;
+mod one_handed;
 mod outputs;
 mod panel;
 mod popover;
+mod preview;
 mod receiver;
 pub mod resources;
 mod state;
 mod style;
 mod submission;
+mod suggestions;
+mod swipe;
+mod targeting;
 pub mod tests;
 pub mod util;
 mod vkeyboard;
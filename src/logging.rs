@@ -35,6 +35,7 @@
  * */
 
 use std::fmt::Display;
+use std::sync::atomic::{ AtomicBool, Ordering };
 
 /// Levels are not in order.
 pub enum Level {
@@ -120,6 +121,18 @@ pub fn print(level: Level, message: &str) {
     Print{}.handle(level, message)
 }
 
+/// Enabled by the user, through the debug D-Bus interface
+static DEBUG_MODE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_debug_mode(enabled: bool) {
+    DEBUG_MODE.store(enabled, Ordering::Relaxed);
+}
+
+/// For messages too frequent to print at all times
+pub fn is_debug_mode() -> bool {
+    DEBUG_MODE.load(Ordering::Relaxed)
+}
+
 /// Sugar for logging errors in results.
 pub trait Warn where Self: Sized {
     type Value;
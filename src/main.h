@@ -27,7 +27,7 @@ struct rsobjects {
     struct squeek_popover *popover;
 };
 
-void register_ui_loop_handler(struct receiver *receiver, struct panel_manager *panel, struct squeek_popover *popover, EekboardContextService *hint_manager, DBusHandler *dbus_handler);
+void register_ui_loop_handler(struct receiver *receiver, struct squeek_state_manager *state_manager, struct panel_manager *panel, struct squeek_popover *popover, EekboardContextService *hint_manager, DBusHandler *dbus_handler);
 
 struct rsobjects squeek_init(void);
 
@@ -36,3 +36,5 @@ void squeek_state_send_force_hidden(struct squeek_state_manager *state);
 
 void squeek_state_send_keyboard_present(struct squeek_state_manager *state, uint32_t keyboard_present);
 void squeek_state_send_layout_set(struct squeek_state_manager *state, char *name, char *layout, uint32_t timestamp);
+void squeek_state_send_height_dragged(struct squeek_state_manager *state, uint32_t height, uint32_t done);
+void squeek_state_send_output_pinned(struct squeek_state_manager *state, const char *name);
@@ -7,9 +7,14 @@ use crate::actors;
 use crate::actors::external::debug;
 use crate::animation;
 use crate::data::loading;
+use crate::devices;
 use crate::event_loop;
+use crate::fit;
+use crate::one_handed;
+use crate::outputs;
 use crate::panel;
 use crate::state;
+use crate::swipe;
 use glib::{Continue, MainContext, PRIORITY_DEFAULT, Receiver};
 
 
@@ -88,7 +93,7 @@ mod c {
         #[allow(improper_ctypes)]
         fn init_wayland(wayland: *mut Wayland);
         #[allow(improper_ctypes)]
-        fn eekboard_context_service_set_layout(service: HintManager, name: *const c_char, layout: *const layout::Layout, timestamp: u32);
+        fn eekboard_context_service_set_layout(service: HintManager, name: *const c_char, layout: *const layout::Layout, keep_view: u32, timestamp: u32);
         // This should probably only get called from the gtk main loop,
         // given that dbus handler is using glib.
         fn dbus_handler_set_visible(dbus: *const DBusHandler, visible: u8);
@@ -105,10 +110,23 @@ mod c {
         // Set up channels
         let (sender, receiver) = MainContext::channel(PRIORITY_DEFAULT);
         let now = Instant::now();
-        let state_manager = driver::Threaded::new(sender, state::Application::new(now));
+        let state_manager = driver::Threaded::new(
+            sender,
+            state::Application::new(now)
+                .with_height_preference(panel::HeightPreference::load())
+                .with_pinned_output(outputs::load_pinned())
+                .with_devices(devices::Database::load())
+                .with_slide_duration(animation::load_slide_duration()),
+        );
 
         debug::init(state_manager.clone());
 
+        let state_sender = state_manager.clone();
+        animation::watch_slide_duration(move |duration| {
+            state_sender.send(state::Event::SlideDurationChanged(duration))
+                .or_print(logging::Problem::Warning, "Can't send to state manager");
+        });
+
         let outputs = Outputs::new(state_manager.clone());
         let mut wayland = Box::new(Wayland::new(outputs));
         let wayland_raw = &mut *wayland as *mut _;
@@ -128,8 +146,11 @@ mod c {
         #[cfg(feature = "zbus_v1_5")]
         crate::actors::external::screensaver::init(popover.clone_ref());
         
+        let submission = Wrapped::new(submission);
+        crate::submission::connect_deactivate(&submission.clone_ref());
+
         RsObjects {
-            submission: Wrapped::new(submission),
+            submission,
             state_manager: Wrapped::new(state_manager),
             receiver: Wrapped::new(receiver),
             wayland: Box::into_raw(wayland),
@@ -142,6 +163,7 @@ mod c {
     pub extern "C"
     fn register_ui_loop_handler(
         receiver: Wrapped<Receiver<Commands>>,
+        state_manager: Wrapped<EventLoop>,
         panel_manager: panel::c::PanelManager,
         popover: actors::popover::c::Actor,
         hint_manager: HintManager,
@@ -150,7 +172,10 @@ mod c {
         let receiver = unsafe { receiver.unwrap() };
         let receiver = Rc::try_unwrap(receiver).expect("References still present");
         let receiver = receiver.into_inner();
-        let panel_manager = Wrapped::new(panel::Manager::new(panel_manager));
+        let state_manager = state_manager.clone_ref().borrow().clone();
+        let panel_manager = Wrapped::new(
+            panel::Manager::new(panel_manager, state_manager.clone()),
+        );
         let ctx = MainContext::default();
         let _acqu = ctx.acquire();
         receiver.attach(
@@ -158,6 +183,7 @@ mod c {
             move |msg| {
                 main_loop_handle_message(
                     msg,
+                    &state_manager,
                     panel_manager.clone(),
                     &popover.clone_ref(),
                     hint_manager,
@@ -176,6 +202,7 @@ mod c {
     /// and doesn't lend itself to testing other than integration.
     fn main_loop_handle_message(
         msg: Commands,
+        state_manager: &EventLoop,
         panel_manager: Wrapped<panel::Manager>,
         popover: &actors::popover::Destination,
         hint_manager: HintManager,
@@ -185,21 +212,43 @@ mod c {
             panel::Manager::update(panel_manager, visibility);
         }
 
+        if let Some(preference) = msg.height_preference {
+            preference.store();
+        }
+
         if let Some(visible) = msg.dbus_visible_set {
             if dbus_handler != std::ptr::null() {
                 unsafe { dbus_handler_set_visible(dbus_handler, visible as u8) };
             }
         }
         
-        if let Some(commands::SetLayout { description }) = msg.layout_selection {
+        if let Some(commands::SetLayout { description, keep_view }) = msg.layout_selection {
             let animation::Contents {
                 name,
                 kind,
                 overlay_name,
                 purpose,
-            } = description;
+                key_size,
+                split,
+                safe_area,
+            } = description.clone();
             popover.send(popover::Event::Overlay(overlay_name.clone()));
-            let layout = loading::load_layout(&name, kind, purpose, &overlay_name);
+            let mut layout = loading::load_layout(&name, kind, purpose, &overlay_name);
+            // Gestures keep the candidate strip, which takes up height
+            layout.shape.gestures = swipe::load_enabled();
+            state_manager
+                .send(state::Event::LayoutLoaded {
+                    contents: description,
+                    proportions: layout.shape.get_proportions(),
+                })
+                .or_print(logging::Problem::Warning, "Can't send to state manager");
+            layout.shape.key_size = key_size;
+            layout.shape.split = split;
+            layout.shape.safe_area = safe_area;
+            layout.shape.one_handed = one_handed::load();
+            if let Some(mode) = fit::load() {
+                layout.shape.fit = mode;
+            }
             let layout = Box::into_raw(Box::new(layout));
             // CSS can't express "+" in the class
             let name = overlay_name.unwrap_or(name).replace('+', "_");
@@ -209,7 +258,7 @@ mod c {
             unsafe {
                 // Take out the pointer to a temp variable so that it outlives the set_layout call.
                 let name = name.as_ptr();
-                eekboard_context_service_set_layout(hint_manager, name, layout, 0);
+                eekboard_context_service_set_layout(hint_manager, name, layout, keep_view as u32, 0);
             }
         }
     }
@@ -254,6 +303,39 @@ mod c {
             .or_warn(&mut logging::Print, logging::Problem::Warning, "Can't send to state manager");
     }
     
+    /// The height is in scaled pixels
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_state_send_height_dragged(
+        sender: Wrapped<EventLoop>,
+        height: u32,
+        done: u32,
+    ) {
+        let sender = sender.clone_ref();
+        let sender = sender.borrow();
+        sender.send(Event::HeightDragged { height, done: done != 0 })
+            .or_warn(&mut logging::Print, logging::Problem::Warning, "Can't send to state manager");
+    }
+
+    /// An empty or null name lets Squeekboard choose the output
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_state_send_output_pinned(
+        sender: Wrapped<EventLoop>,
+        name: *const c_char,
+    ) {
+        let sender = sender.clone_ref();
+        let sender = sender.borrow();
+        let name = util::c::as_str(&name)
+            .or_print(logging::Problem::Warning, "Received invalid output name")
+            .flatten()
+            .filter(|name| !name.is_empty())
+            .map(String::from);
+        outputs::store_pinned(name.as_deref());
+        sender.send(Event::OutputPinned(name))
+            .or_warn(&mut logging::Print, logging::Problem::Warning, "Can't send to state manager");
+    }
+
     #[no_mangle]
     pub extern "C"
     fn squeek_state_send_layout_set(
@@ -288,6 +370,9 @@ pub mod commands {
     #[derive(Clone, Debug)]
     pub struct SetLayout {
         pub description: animation::Contents,
+        /// Only the arrangement or the size changed,
+        /// so the user stays in the same view
+        pub keep_view: bool,
     }
 }
 
@@ -298,4 +383,6 @@ pub struct Commands {
     pub panel_visibility: Option<panel::Command>,
     pub dbus_visible_set: Option<bool>,
     pub layout_selection: Option<commands::SetLayout>,
+    /// Store the panel size chosen by the user
+    pub height_preference: Option<panel::HeightPreference>,
 }
@@ -0,0 +1,161 @@
+/*! Docking the keyboard to one side, for typing with one hand.
+ *
+ * The layout shrinks to a part of the panel width,
+ * and the rest of the panel stays empty.
+ * The mode is kept in gsettings, so that it survives restarts.
+ */
+
+use gio;
+use gtk;
+
+use crate::layout::Size;
+use crate::layout::c::{ Bounds, EekGtkKeyboard };
+use crate::logging;
+use crate::popover;
+
+// Traits
+use gio::prelude::SettingsExt;
+use glib::translate::FromGlibPtrNone;
+use gtk::prelude::*;
+use crate::logging::Warn;
+
+
+/// The side, or "none"
+const SETTINGS_KEY_SIDE: &str = "one-handed";
+const SETTINGS_KEY_WIDTH: &str = "one-handed-width";
+
+/// Used when the schema is not installed
+const DEFAULT_WIDTH: f64 = 0.75;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    fn from_name(name: &str) -> Option<Side> {
+        match name {
+            "left" => Some(Side::Left),
+            "right" => Some(Side::Right),
+            _ => None,
+        }
+    }
+
+    fn as_str(side: Option<Side>) -> &'static str {
+        match side {
+            Some(Side::Left) => "left",
+            Some(Side::Right) => "right",
+            None => "none",
+        }
+    }
+
+    /// The toggle goes through: off, right, left, and off again.
+    fn next(side: Option<Side>) -> Option<Side> {
+        match side {
+            None => Some(Side::Right),
+            Some(Side::Right) => Some(Side::Left),
+            Some(Side::Left) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mode {
+    pub side: Side,
+    /// The part of the panel width taken by the layout
+    pub width: f64,
+}
+
+impl Mode {
+    /// Returns the part of the available space where the layout goes
+    pub fn get_area(&self, available: Size) -> Bounds {
+        let width = available.width * self.width;
+        Bounds {
+            x: match self.side {
+                Side::Left => 0.0,
+                Side::Right => available.width - width,
+            },
+            y: 0.0,
+            width,
+            height: available.height,
+        }
+    }
+}
+
+fn get_width(settings: &gio::Settings) -> f64 {
+    popover::get_double(settings, SETTINGS_KEY_WIDTH)
+}
+
+fn get_side(settings: &gio::Settings) -> Option<Side> {
+    popover::get_string(settings, SETTINGS_KEY_SIDE)
+        .and_then(|name| Side::from_name(&name))
+}
+
+/// Reads the stored mode. `None` means the whole width is used.
+pub fn load() -> Option<Mode> {
+    let settings = popover::get_own_settings()?;
+    get_side(&settings).map(|side| Mode {
+        side,
+        width: get_width(&settings),
+    })
+}
+
+/// Switches to the next mode, and stores it
+pub fn toggle(current: Option<Mode>) -> Option<Mode> {
+    let settings = popover::get_own_settings();
+    let side = Side::next(current.map(|mode| mode.side));
+    if let Some(settings) = &settings {
+        settings.set_string(SETTINGS_KEY_SIDE, Side::as_str(side))
+            .or_print(
+                logging::Problem::Warning,
+                "Can't store the one-handed mode",
+            );
+    }
+    side.map(|side| Mode {
+        side,
+        width: settings.as_ref()
+            .map(get_width)
+            .unwrap_or(DEFAULT_WIDTH),
+    })
+}
+
+/// Makes the keyboard take on the changed mode.
+/// The layout gets placed again on the next allocation.
+pub fn refresh(keyboard: EekGtkKeyboard) {
+    unsafe { gtk::set_initialized() };
+    let widget = unsafe { gtk::Widget::from_glib_none(keyboard.0) };
+    widget.queue_resize();
+    widget.queue_draw();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cycle() {
+        assert_eq!(Side::next(None), Some(Side::Right));
+        assert_eq!(Side::next(Some(Side::Right)), Some(Side::Left));
+        assert_eq!(Side::next(Some(Side::Left)), None);
+    }
+
+    #[test]
+    fn names() {
+        for side in &[None, Some(Side::Left), Some(Side::Right)] {
+            assert_eq!(Side::from_name(Side::as_str(*side)), *side);
+        }
+    }
+
+    #[test]
+    fn area() {
+        let available = Size { width: 100.0, height: 10.0 };
+        let mode = Mode { side: Side::Right, width: 0.75 };
+        assert_eq!(
+            mode.get_area(available.clone()),
+            Bounds { x: 25.0, y: 0.0, width: 75.0, height: 10.0 },
+        );
+        let mode = Mode { side: Side::Left, ..mode };
+        assert_eq!(mode.get_area(available).x, 0.0);
+    }
+}
@@ -15,4 +15,5 @@ void squeek_outputs_register(struct squeek_outputs*, struct wl_output *output, u
 struct wl_output *squeek_outputs_try_unregister(struct squeek_outputs*, uint32_t id);
 struct squeek_output_handle squeek_outputs_get_current(struct squeek_outputs*);
 int32_t squeek_outputs_get_perceptual_width(struct squeek_outputs*, struct wl_output *output);
+void squeek_outputs_set_preferred_scale(struct squeek_outputs*, struct wl_output *output, uint32_t scale);
 #endif
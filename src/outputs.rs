@@ -8,11 +8,21 @@ use std::ops;
 use std::vec::Vec;
 use crate::logging;
 use crate::main;
-use crate::util::DivCeil;
+use crate::popover;
+use crate::util::{ DivCeil, Rational };
 
 // traits
+use gio::prelude::SettingsExt;
 use crate::logging::Warn;
 
+
+/// Name or description of the output to show the keyboard on
+const SETTINGS_KEY_PINNED: &str = "output";
+
+/// Connector names of panels built into the device.
+/// Those are the ones likely to have a touch screen.
+const BUILTIN_CONNECTORS: &[&str] = &["DSI-", "eDP-", "LVDS-"];
+
 /// Gathers stuff defined in C or called by C
 pub mod c {
     use super::*;
@@ -70,6 +80,17 @@ pub mod c {
             WlOutput,
             i32, // factor
         ),
+        // Since version 4
+        name: extern fn(
+            T, // data
+            WlOutput,
+            *const c_char, // name
+        ),
+        description: extern fn(
+            T, // data
+            WlOutput,
+            *const c_char, // description
+        ),
     }
     
     bitflags!{
@@ -135,9 +156,11 @@ pub mod c {
         _x: i32, _y: i32,
         phys_width: i32, phys_height: i32,
         _subpixel: i32,
-        _make: *const c_char, _model: *const c_char,
+        make: *const c_char, model: *const c_char,
         transform: i32,
     ) {
+        let make = as_string(make, "wl_output.make");
+        let model = as_string(model, "wl_output.model");
         let transform = Transform::from_u32(transform as u32)
             .or_print(
                 logging::Problem::Warning,
@@ -163,6 +186,8 @@ pub mod c {
                     },
                     transform,
                 });
+                state.make = make;
+                state.model = model;
             },
             None => log_print!(
                 logging::Level::Warning,
@@ -217,7 +242,7 @@ pub mod c {
                 output.current = output.pending.clone();
                 Some(Event {
                     output: OutputId(wl_output),
-                    change: ChangeType::Altered(output.current),
+                    change: ChangeType::Altered(output.current.clone()),
                 })
             },
             None => {
@@ -253,6 +278,58 @@ pub mod c {
         };
     }
 
+    extern fn outputs_handle_name(
+        outputs: COutputs,
+        wl_output: WlOutput,
+        name: *const c_char,
+    ) {
+        let name = as_string(name, "wl_output.name");
+        let outputs = outputs.clone_ref();
+        let mut collection = outputs.borrow_mut();
+        let output_state: Option<&mut OutputState>
+            = collection
+                .find_output_mut(wl_output)
+                .map(|o| &mut o.pending);
+        match output_state {
+            Some(state) => { state.name = name; }
+            None => log_print!(
+                logging::Level::Warning,
+                "Got name on unknown output",
+            ),
+        };
+    }
+
+    extern fn outputs_handle_description(
+        outputs: COutputs,
+        wl_output: WlOutput,
+        description: *const c_char,
+    ) {
+        let description = as_string(description, "wl_output.description");
+        let outputs = outputs.clone_ref();
+        let mut collection = outputs.borrow_mut();
+        let output_state: Option<&mut OutputState>
+            = collection
+                .find_output_mut(wl_output)
+                .map(|o| &mut o.pending);
+        match output_state {
+            Some(state) => { state.description = description; }
+            None => log_print!(
+                logging::Level::Warning,
+                "Got description on unknown output",
+            ),
+        };
+    }
+
+    fn as_string(value: *const c_char, what: &str) -> Option<String> {
+        crate::util::c::as_str(&value)
+            .or_print(
+                logging::Problem::Warning,
+                &format!("Received invalid {}", what),
+            )
+            .flatten()
+            .map(String::from)
+    }
+
     // End callbacks
 
     #[no_mangle]
@@ -282,11 +359,54 @@ pub mod c {
                 mode: outputs_handle_mode,
                 done: outputs_handle_done,
                 scale: outputs_handle_scale,
+                name: outputs_handle_name,
+                description: outputs_handle_description,
             } as *const WlOutputListener<COutputs>,
             raw_collection,
         )};
     }
 
+    /// The scale is in 120ths.
+    /// The compositor sends it for a surface, so it's known only for the outputs which had the panel on them.
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_outputs_set_preferred_scale(
+        raw_collection: COutputs,
+        wl_output: WlOutput,
+        scale: u32,
+    ) {
+        let collection = raw_collection.clone_ref();
+        let mut collection = collection.borrow_mut();
+        let event = match collection.find_output_mut(wl_output) {
+            Some(output) => {
+                let scale = Some(Scale {
+                    numerator: scale,
+                    denominator: FRACTIONAL_SCALE_BASE,
+                });
+                output.pending.fractional_scale = scale;
+                if output.current.fractional_scale != scale {
+                    output.current.fractional_scale = scale;
+                    Some(Event {
+                        output: OutputId(wl_output),
+                        change: ChangeType::Altered(output.current.clone()),
+                    })
+                } else {
+                    None
+                }
+            },
+            None => {
+                log_print!(
+                    logging::Level::Warning,
+                    "Got preferred scale on unknown output",
+                );
+                None
+            },
+        };
+        if let Some(event) = event {
+            collection.send_event(event);
+        }
+    }
+
     /// This will try to unregister the output, if the id matches a registered one.
     #[no_mangle]
     pub extern "C"
@@ -307,7 +427,7 @@ pub mod c {
 
 
 /// Generic size
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Size<Unit> {
     pub width: Unit,
     pub height: Unit,
@@ -339,6 +459,13 @@ impl ops::Mul<i32> for Millimeter {
     }
 }
 
+/// The ratio of pixels to scaled pixels.
+/// Fractional scales come in 120ths, like in the fractional-scale protocol.
+pub type Scale = Rational<u32>;
+
+/// Denominator of fractional scales
+const FRACTIONAL_SCALE_BASE: u32 = 120;
+
 /// All geometry parameters
 #[derive(Clone, Copy, Debug)]
 pub struct Geometry {
@@ -346,11 +473,23 @@ pub struct Geometry {
     pub phys_size: Size<Option<Millimeter>>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct OutputState {
     pub current_mode: Option<Mode>,
     pub geometry: Option<Geometry>,
+    /// Integer scale from wl_output
     pub scale: i32,
+    /// Preferred by the compositor for surfaces on this output.
+    /// Known only after the panel appears there.
+    pub fractional_scale: Option<Scale>,
+    /// The connector, like "DSI-1". Stays the same across connections.
+    pub name: Option<String>,
+    /// Human readable, may include make and model
+    pub description: Option<String>,
+    /// Manufacturer of the screen, or "Unknown"
+    pub make: Option<String>,
+    /// Model of the screen, or "Unknown"
+    pub model: Option<String>,
 }
 
 impl OutputState {
@@ -365,9 +504,37 @@ impl OutputState {
             current_mode: None,
             geometry: None,
             scale: 1,
+            fractional_scale: None,
+            name: None,
+            description: None,
+            make: None,
+            model: None,
         }
     }
 
+    /// The scale that the panel gets drawn with
+    pub fn get_scale(&self) -> Scale {
+        self.fractional_scale
+            .unwrap_or((self.scale as u32).into())
+    }
+
+    /// Whether the output is the built-in panel.
+    /// That's a guess for whether it's a touch screen,
+    /// because Wayland doesn't tell which output the touch input belongs to.
+    pub fn is_builtin(&self) -> bool {
+        self.name.as_ref()
+            .map(|name| BUILTIN_CONNECTORS.iter()
+                .any(|prefix| name.starts_with(prefix))
+            )
+            .unwrap_or(false)
+    }
+
+    /// Whether the name or the description is equal to the given one
+    pub fn is_called(&self, name: &str) -> bool {
+        self.name.as_deref() == Some(name)
+            || self.description.as_deref() == Some(name)
+    }
+
     fn transform_size<T>(
         width: T,
         height: T,
@@ -396,7 +563,7 @@ impl OutputState {
             OutputState {
                 current_mode: Some(Mode { width, height } ),
                 geometry: Some(Geometry { transform, .. } ),
-                scale: _,
+                ..
             } => Some(Self::transform_size(*width as u32, *height as u32, *transform)),
             OutputState {
                 current_mode: Some(Mode { width, height } ),
@@ -486,15 +653,33 @@ impl Outputs {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum ChangeType {
     /// Added or changed
     Altered(OutputState),
     Removed,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Event {
     pub output: OutputId,
     pub change: ChangeType,
 }
+
+/// Reads the name or description of the output chosen by the user
+pub fn load_pinned() -> Option<String> {
+    let settings = popover::get_own_settings()?;
+    popover::get_string(&settings, SETTINGS_KEY_PINNED)
+        .filter(|name| !name.is_empty())
+}
+
+/// Remembers the choice of the output across restarts
+pub fn store_pinned(name: Option<&str>) {
+    if let Some(settings) = popover::get_own_settings() {
+        settings.set_string(SETTINGS_KEY_PINNED, name.unwrap_or(""))
+            .or_print(
+                logging::Problem::Warning,
+                "Can't store the output choice",
+            );
+    }
+}
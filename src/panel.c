@@ -1,6 +1,7 @@
 #include "eekboard/eekboard-context-service.h"
 #include "wayland.h"
 #include "panel.h"
+#include "suggestions.h"
 
 
 // Called from rust
@@ -11,6 +12,8 @@ panel_manager_hide(struct panel_manager *self)
     if (self->window) {
         gtk_widget_hide (GTK_WIDGET (self->window));
     }
+    // The surface goes away when hidden
+    g_clear_pointer (&self->fractional_scale, wp_fractional_scale_v1_destroy);
 }
 
 static void
@@ -41,15 +44,101 @@ on_surface_configure(struct squeek_panel_manager *self, PhoshLayerSurface *surfa
     squeek_panel_manager_configured(self, width, height);
 }
 
+static void
+on_preferred_scale (void *data, struct wp_fractional_scale_v1 *fractional_scale, uint32_t scale)
+{
+    struct panel_manager *self = data;
+    (void)fractional_scale;
+    if (self->current_output) {
+        squeek_outputs_set_preferred_scale(squeek_wayland->outputs, self->current_output, scale);
+    }
+}
+
+static const struct wp_fractional_scale_v1_listener fractional_scale_listener = {
+    .preferred_scale = on_preferred_scale,
+};
+
+/// Listens for the fractional scale once the surface exists.
+/// The scale only sizes the panel:
+/// GTK draws at the integer scale,
+/// and the compositor scales it down to the configured size.
+static void
+on_panel_configured (struct panel_manager *self, PhoshLayerSurface *surface)
+{
+    struct wl_surface *wl_surface = phosh_layer_surface_get_wl_surface (surface);
+    if (!wl_surface) {
+        return;
+    }
+    if (!self->fractional_scale && squeek_wayland->fractional_scale_manager) {
+        self->fractional_scale = wp_fractional_scale_manager_v1_get_fractional_scale (
+            squeek_wayland->fractional_scale_manager, wl_surface);
+        wp_fractional_scale_v1_add_listener (self->fractional_scale,
+            &fractional_scale_listener, self);
+    }
+}
+
+/// Height of the strip at the top of the panel which resizes it.
+/// Same as HANDLE_HEIGHT in panel.rs
+#define HANDLE_HEIGHT 12
+
+static void
+send_handle_height (struct panel_manager *self, GtkGestureDrag *gesture, gboolean done)
+{
+    gdouble offset_x, offset_y;
+    if (!gtk_gesture_drag_get_offset (gesture, &offset_x, &offset_y)) {
+        return;
+    }
+    // The panel grows upwards, following the finger.
+    // Event coordinates are relative to the current top edge.
+    gint height = gtk_widget_get_allocated_height (GTK_WIDGET (self->window))
+        - (gint)offset_y;
+    squeek_state_send_height_dragged (self->state_manager, MAX (height, 0), done);
+}
+
+static void
+on_handle_drag_update (GtkGestureDrag *gesture, gdouble offset_x, gdouble offset_y, struct panel_manager *self)
+{
+    (void)offset_x;
+    (void)offset_y;
+    send_handle_height (self, gesture, FALSE);
+}
+
+static void
+on_handle_drag_end (GtkGestureDrag *gesture, gdouble offset_x, gdouble offset_y, struct panel_manager *self)
+{
+    (void)offset_x;
+    (void)offset_y;
+    send_handle_height (self, gesture, TRUE);
+}
+
+static GtkWidget *
+make_handle (struct panel_manager *self)
+{
+    GtkWidget *handle = gtk_event_box_new ();
+    gtk_widget_set_size_request (handle, -1, HANDLE_HEIGHT);
+    gtk_style_context_add_class (gtk_widget_get_style_context (handle), "handle");
+
+    self->handle_drag = gtk_gesture_drag_new (handle);
+    g_signal_connect (self->handle_drag, "drag-update",
+                      G_CALLBACK (on_handle_drag_update), self);
+    g_signal_connect (self->handle_drag, "drag-end",
+                      G_CALLBACK (on_handle_drag_end), self);
+    return handle;
+}
+
 static void
 make_widget (struct panel_manager *self)
 {
     if (self->widget) {
         g_error("Widget already present");
     }
-    self->widget = eek_gtk_keyboard_new (self->state, self->submission, self->state_manager, self->popover);
+    GtkWidget *keyboard = eek_gtk_keyboard_new (self->state, self->submission, self->state_manager, self->popover);
+    gtk_widget_set_has_tooltip (keyboard, TRUE);
 
-    gtk_widget_set_has_tooltip (self->widget, TRUE);
+    self->widget = gtk_box_new (GTK_ORIENTATION_VERTICAL, 0);
+    gtk_box_pack_start (GTK_BOX(self->widget), make_handle (self), FALSE, FALSE, 0);
+    gtk_box_pack_start (GTK_BOX(self->widget), squeek_suggestions_new (self->submission), FALSE, FALSE, 0);
+    gtk_box_pack_start (GTK_BOX(self->widget), keyboard, TRUE, TRUE, 0);
     gtk_container_add (GTK_CONTAINER(self->window), self->widget);
     gtk_widget_show_all(self->widget);
 }
@@ -65,6 +154,18 @@ panel_manager_resize (struct panel_manager *self, uint32_t height)
     phosh_layer_surface_wl_surface_commit(self->window);
 }
 
+// Called from rust
+/// Moves the panel partly below the bottom edge of the screen
+void
+panel_manager_slide (struct panel_manager *self, uint32_t hidden)
+{
+    self->hidden = hidden;
+    if (self->window) {
+        phosh_layer_surface_set_margins(self->window, 0, 0, -(gint)hidden, 0);
+        phosh_layer_surface_wl_surface_commit(self->window);
+    }
+}
+
 // Called from rust
 /// Creates a new panel widget
 void
@@ -82,12 +183,14 @@ panel_manager_request_widget (struct panel_manager *self, struct wl_output *outp
             "layer", ZWLR_LAYER_SHELL_V1_LAYER_TOP,
             "kbd-interactivity", FALSE,
             "exclusive-zone", height,
+            "margin-bottom", -(gint)self->hidden,
             "namespace", "osk",
             NULL
         );
         g_object_connect (self->window,
             "swapped-signal::destroy", G_CALLBACK(on_destroy), self,
             "swapped-signal::configured", G_CALLBACK(on_surface_configure), mgr,
+            "swapped-signal::configured", G_CALLBACK(on_panel_configured), self,
             NULL);
         // The properties below are just to make hacking easier.
         // The way we use layer-shell overrides some,
@@ -103,6 +206,8 @@ panel_manager_request_widget (struct panel_manager *self, struct wl_output *outp
         panel_manager_resize(self, height);
     }
 
+    self->current_output = output;
+
     if (!self->widget) {
         make_widget(self);
     }
@@ -117,7 +222,10 @@ struct panel_manager panel_manager_new(EekboardContextService *state, struct sub
         .submission = submission,
         .window = NULL,
         .widget = NULL,
+        .handle_drag = NULL,
+        .fractional_scale = NULL,
         .current_output = NULL,
+        .hidden = 0,
         .state_manager = state_manager,
         .popover = popover,
     };
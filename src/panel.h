@@ -17,6 +17,13 @@ struct panel_manager {
     // https://gitlab.gnome.org/World/Phosh/squeekboard/-/issues/343
     PhoshLayerSurface *window;
     GtkWidget *widget;
+    /// Resizes the panel by dragging its top edge
+    GtkGesture *handle_drag;
+    /// Exists only while the surface is shown
+    struct wp_fractional_scale_v1 *fractional_scale;
+
+    /// Height of the part below the screen edge while sliding
+    uint32_t hidden;
 
     // Those should be held in Rust
     struct wl_output *current_output;
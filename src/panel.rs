@@ -10,11 +10,10 @@
  * It still relies on a callback from Wayland to accept the panel size,
  * which makes this code somewhat prone to mistakes.
  *
- * An alternative to the callback would be
- * to send a message all the way to `state::State`
- * every time the allocated size changes.
- * That would allow for a more holistic view
- * of interactions of different pieces of state.
+ * The allocated size is also sent all the way to `state::State`,
+ * which picks the layout to fit it.
+ * The requested height doesn't depend on the allocated one,
+ * so that the two can't keep chasing each other.
  * 
  * However, `state::State` already has the potential to become a ball of mud,
  * tightly coupling different functionality and making it difficult to see independent units.
@@ -28,10 +27,28 @@
  * will have to be taken into account later.
  */
 
+use gio;
+
 use crate::logging;
-use crate::outputs::OutputId;
+use crate::main::EventLoop;
+use crate::outputs::{ OutputId, Scale };
+use crate::popover;
+use crate::state;
 use crate::util::c::Wrapped;
 
+// Traits
+use gio::prelude::SettingsExt;
+use crate::logging::Warn;
+
+
+/// In millimeters, 0 for automatic
+const SETTINGS_KEY_KEY_HEIGHT: &str = "key-height";
+const SETTINGS_KEY_MAX_HEIGHT: &str = "max-height";
+
+/// Height of the handle above the keyboard, in scaled pixels.
+/// Same as HANDLE_HEIGHT in panel.c
+pub const HANDLE_HEIGHT: u32 = 12;
+
 
 pub mod c {
     use super::*;
@@ -56,6 +73,7 @@ pub mod c {
         );
         pub fn panel_manager_resize(service: PanelManager, height: u32);
         pub fn panel_manager_hide(service: PanelManager);
+        pub fn panel_manager_slide(service: PanelManager, hidden: u32);
     }
 
     #[no_mangle]
@@ -76,7 +94,15 @@ pub mod c {
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub struct PixelSize {
     pub pixels: u32,
-    pub scale_factor: u32,
+    /// May be fractional
+    pub scale_factor: Scale,
+}
+
+/// The part of the panel below the screen edge, in scaled pixels.
+/// Slows down near the top.
+fn get_hidden_height(height: u32, progress: f64) -> u32 {
+    let shown = 1.0 - (1.0 - progress.max(0.0).min(1.0)).powi(2);
+    (height as f64 * (1.0 - shown)).round() as u32
 }
 
 fn div_ceil(a: u32, b: u32) -> u32 {
@@ -86,18 +112,73 @@ fn div_ceil(a: u32, b: u32) -> u32 {
 
 impl PixelSize {
     pub fn as_scaled_floor(&self) -> u32 {
-        self.pixels / self.scale_factor
+        self.pixels * self.scale_factor.denominator / self.scale_factor.numerator
     }
 
     pub fn as_scaled_ceiling(&self) -> u32 {
-        div_ceil(self.pixels, self.scale_factor)
+        div_ceil(
+            self.pixels * self.scale_factor.denominator,
+            self.scale_factor.numerator,
+        )
+    }
+}
+
+/// The user's choice of the panel size
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeightPreference {
+    /// Height of a key in millimeters.
+    /// `None` uses a size comfortable on the Librem 5.
+    pub key_height: Option<f64>,
+    /// The biggest part of the screen height the panel may take
+    pub max_fraction: f64,
+}
+
+impl Default for HeightPreference {
+    fn default() -> Self {
+        Self {
+            key_height: None,
+            max_fraction: 0.5,
+        }
+    }
+}
+
+impl HeightPreference {
+    /// Reads the preference from gsettings, or uses the default
+    pub fn load() -> Self {
+        let settings = match popover::get_own_settings() {
+            Some(settings) => settings,
+            None => return Self::default(),
+        };
+        let key_height = popover::get_double(&settings, SETTINGS_KEY_KEY_HEIGHT);
+        let max_fraction = popover::get_double(&settings, SETTINGS_KEY_MAX_HEIGHT);
+        Self {
+            key_height: match key_height {
+                h if h > 0.0 => Some(h),
+                _ => None,
+            },
+            max_fraction,
+        }
+    }
+
+    /// Remembers the key height across restarts
+    pub fn store(&self) {
+        if let Some(settings) = popover::get_own_settings() {
+            settings.set_double(
+                SETTINGS_KEY_KEY_HEIGHT,
+                self.key_height.unwrap_or(0.0),
+            ).or_print(
+                logging::Problem::Warning,
+                "Can't store the key height",
+            );
+        }
     }
 }
 
+/// The size of the panel surface, in scaled pixels
 #[derive(Clone, Debug, PartialEq)]
-struct Size {
-    width: u32,
-    height: u32,
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
 }
 
 /// This state requests the Wayland layer shell protocol synchronization:
@@ -136,6 +217,8 @@ pub enum Command {
     Show {
         output: OutputId,
         height: PixelSize,
+        /// The part of the panel above the screen edge, from 0 to 1
+        progress: f64,
     },
     Hide,
 }
@@ -143,7 +226,11 @@ pub enum Command {
 /// Tries to contain all the panel sizing duties.
 pub struct Manager {
     panel: c::PanelManager,
+    /// Receives the allocated size
+    state_manager: EventLoop,
     state: State,
+    /// Last hidden height sent to the panel while sliding
+    hidden: Option<u32>,
     // This should be part of State, if it ever actually gets unhardcoded.
     // It's here because State doesn't need to become more complex
     // until this becomes properly used.
@@ -151,15 +238,15 @@ pub struct Manager {
 }
 
 impl Manager {
-    pub fn new(panel: c::PanelManager) -> Self {
+    pub fn new(panel: c::PanelManager, state_manager: EventLoop) -> Self {
         Self {
             panel,
+            state_manager,
             state: State::Hidden,
+            hidden: None,
             debug: false,
         }
     }
-    // TODO: mabe send the allocated size back to state::State,
-    // to perform layout adjustments
     fn set_configured(&mut self, size: Size) {
         if self.debug {
             eprintln!("Panel received configure {:?}", &size);
@@ -170,6 +257,12 @@ impl Manager {
         if self.debug {
             eprintln!("Panel now {:?}", &self.state);
         }
+
+        if let Some((output, size)) = self.state.get_allocated() {
+            self.state_manager
+                .send(state::Event::PanelConfigured { output, size })
+                .or_print(logging::Problem::Warning, "Can't send to state manager");
+        }
     }
 
     pub fn update(mgr: Wrapped<Manager>, cmd: Command) {
@@ -182,6 +275,18 @@ impl Manager {
             eprintln!("Panel received {:?}", &cmd);
         }
         
+        // Comes first, so that a new panel appears in the right place
+        match &cmd {
+            Command::Show { height, progress, .. } => {
+                let hidden = get_hidden_height(height.as_scaled_ceiling(), *progress);
+                if mgr.hidden != Some(hidden) {
+                    unsafe { c::panel_manager_slide(mgr.panel, hidden) };
+                    (*mgr).hidden = Some(hidden);
+                }
+            },
+            Command::Hide => { (*mgr).hidden = None; },
+        }
+
         let (state, updates) = mgr.state.clone().update(cmd);
         (*mgr).state = state;
         
@@ -207,6 +312,15 @@ impl Manager {
 }
 
 impl State {
+    /// The size given by the compositor, if any
+    fn get_allocated(&self) -> Option<(OutputId, Size)> {
+        match self {
+            State::SizeAllocated { output, allocated, .. }
+                => Some((*output, allocated.clone())),
+            _ => None,
+        }
+    }
+
     fn configure(self, size: Size) -> Self {
          match self {
             State::Hidden => {
@@ -241,7 +355,7 @@ impl State {
             (Command::Hide, State::SizeRequested{..}) => (
                 State::Hidden, vec![Update::Hide],
             ),
-            (Command::Show{output, height}, State::Hidden) => {
+            (Command::Show{output, height, ..}, State::Hidden) => {
                 let height = height.as_scaled_ceiling();
                 (
                     State::SizeRequested{output, height},
@@ -249,7 +363,7 @@ impl State {
                 )
             },
             (
-                Command::Show{output, height},
+                Command::Show{output, height, ..},
                 State::SizeRequested{output: req_output, height: req_height},
             ) => {
                 let height = height.as_scaled_ceiling();
@@ -283,7 +397,7 @@ impl State {
                 )}
             },
             (
-                Command::Show{output, height},
+                Command::Show{output, height, ..},
                 State::SizeAllocated{output: alloc_output, allocated, wanted_height},
             ) => {
                 let height = height.as_scaled_ceiling();
@@ -326,7 +440,8 @@ mod tests {
         // Initial show
         let (state, cmds) = state.update(Command::Show {
             output,
-            height: PixelSize { pixels: 100, scale_factor: 1 },
+            height: PixelSize { pixels: 100, scale_factor: 1.into() },
+            progress: 1.0,
         });
         assert_eq!(
             cmds,
@@ -337,7 +452,8 @@ mod tests {
         // but another show comes before first can be confirmed
         let (state, cmds) = dbg!(state).update(Command::Show {
             output,
-            height: PixelSize { pixels: 50, scale_factor: 1 },
+            height: PixelSize { pixels: 50, scale_factor: 1.into() },
+            progress: 1.0,
         });
         assert_eq!(
             cmds,
@@ -380,4 +496,70 @@ mod tests {
             },
         );
     }
+
+    /// The state follows the allocated size,
+    /// but keeps asking for the same height.
+    /// That must not cause another request.
+    #[test]
+    fn configured_smaller() {
+        #[allow(non_upper_case_globals)]
+        const output: OutputId = OutputId(WlOutput::dummy());
+        let wanted = PixelSize { pixels: 100, scale_factor: 1.into() };
+
+        let (state, cmds) = State::Hidden.update(Command::Show {
+            output,
+            height: wanted,
+            progress: 1.0,
+        });
+        assert_eq!(
+            cmds,
+            vec![Update::RequestWidget { output, height: 100 }],
+        );
+        assert_eq!(state.get_allocated(), None);
+
+        let state = state.configure(Size { width: 360, height: 80 });
+        assert_eq!(
+            state.get_allocated(),
+            Some((output, Size { width: 360, height: 80 })),
+        );
+
+        // The state picked a layout for the allocated size, and shows again
+        let (state, cmds) = state.update(Command::Show {
+            output,
+            height: wanted,
+            progress: 1.0,
+        });
+        assert_eq!(cmds, Vec::new(), "{:?}", state);
+        assert_eq!(
+            state.get_allocated(),
+            Some((output, Size { width: 360, height: 80 })),
+        );
+
+        // The preference changed
+        let (state, cmds) = state.update(Command::Show {
+            output,
+            height: PixelSize { pixels: 120, scale_factor: 1.into() },
+            progress: 1.0,
+        });
+        assert_eq!(cmds, vec![Update::Resize { height: 120 }]);
+        // Until the new size arrives, the old one is not reported
+        assert_eq!(state.get_allocated(), None);
+    }
+
+    #[test]
+    fn slide() {
+        assert_eq!(get_hidden_height(100, 0.0), 100);
+        assert_eq!(get_hidden_height(100, 0.5), 25);
+        assert_eq!(get_hidden_height(100, 1.0), 0);
+    }
+
+    #[test]
+    fn fractional_scale() {
+        let size = PixelSize {
+            pixels: 100,
+            scale_factor: Scale { numerator: 180, denominator: 120 },
+        };
+        assert_eq!(size.as_scaled_floor(), 66);
+        assert_eq!(size.as_scaled_ceiling(), 67);
+    }
 }
\ No newline at end of file
@@ -105,7 +105,7 @@ mod variants {
     }
 }
 
-fn get_settings(schema_name: &str) -> Option<gio::Settings> {
+pub fn get_settings(schema_name: &str) -> Option<gio::Settings> {
     let mut error_handler = logging::Print{};
 
     #[cfg(feature = "glib_v0_14")]
@@ -129,6 +129,46 @@ fn get_settings(schema_name: &str) -> Option<gio::Settings> {
         .map(|_sschema| gio::Settings::new(schema_name))
 }
 
+/// Squeekboard's own settings
+pub const SETTINGS_SCHEMA: &str = "sm.puri.Squeekboard";
+
+/// Squeekboard's own settings, if the schema is installed
+pub fn get_own_settings() -> Option<gio::Settings> {
+    get_settings(SETTINGS_SCHEMA)
+}
+
+pub fn get_boolean(settings: &gio::Settings, key: &str) -> bool {
+    #[cfg(feature = "glib_v0_14")]
+    let value = settings.boolean(key);
+    #[cfg(not(feature = "glib_v0_14"))]
+    let value = settings.get_boolean(key);
+    value
+}
+
+pub fn get_int(settings: &gio::Settings, key: &str) -> i32 {
+    #[cfg(feature = "glib_v0_14")]
+    let value = settings.int(key);
+    #[cfg(not(feature = "glib_v0_14"))]
+    let value = settings.get_int(key);
+    value
+}
+
+pub fn get_double(settings: &gio::Settings, key: &str) -> f64 {
+    #[cfg(feature = "glib_v0_14")]
+    let value = settings.double(key);
+    #[cfg(not(feature = "glib_v0_14"))]
+    let value = settings.get_double(key);
+    value
+}
+
+pub fn get_string(settings: &gio::Settings, key: &str) -> Option<String> {
+    #[cfg(feature = "glib_v0_14")]
+    let value = Some(settings.string(key));
+    #[cfg(not(feature = "glib_v0_14"))]
+    let value = settings.get_string(key);
+    value.map(String::from)
+}
+
 fn set_layout(kind: &str, name: &str) {
     let settings = get_settings("org.gnome.desktop.input-sources");
     if let Some(settings) = settings {
@@ -0,0 +1,10 @@
+#pragma once
+
+#include <gtk/gtk.h>
+
+// Defined in Rust
+/// The pressed button's label, magnified above the finger
+struct squeek_preview;
+
+struct squeek_preview *squeek_preview_new(GtkWidget *keyboard);
+void squeek_preview_free(struct squeek_preview *preview);
@@ -0,0 +1,115 @@
+/*! The pressed button's label, magnified above the finger.
+ *
+ * The finger covers the button it presses,
+ * so the label is repeated in a popover, which can extend past the keyboard.
+ * Text which shouldn't be seen by others is never shown this way.
+ */
+
+use gio;
+use gtk;
+
+use crate::layout::ButtonPosition;
+use crate::layout::c::Bounds;
+use crate::popover;
+
+// Traits
+use gtk::prelude::*;
+
+
+/// Turns the preview on or off
+const SETTINGS_KEY: &str = "key-preview";
+
+pub mod c {
+    use super::*;
+
+    use glib::translate::FromGlibPtrNone;
+
+    /// Owned by the keyboard widget
+    #[repr(transparent)]
+    pub struct Preview(*mut super::Preview);
+
+    impl Preview {
+        pub fn get(&self) -> Option<&mut super::Preview> {
+            unsafe { self.0.as_mut() }
+        }
+    }
+
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_preview_new(widget: *mut gtk_sys::GtkWidget) -> Preview {
+        unsafe { gtk::set_initialized() };
+        let widget = unsafe { gtk::Widget::from_glib_none(widget) };
+        Preview(Box::into_raw(Box::new(super::Preview::new(&widget))))
+    }
+
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_preview_free(preview: Preview) {
+        drop(unsafe { Box::from_raw(preview.0) });
+    }
+}
+
+pub struct Preview {
+    popover: gtk::Popover,
+    label: gtk::Label,
+    /// `None` if the schema is not installed, leaving the preview on
+    settings: Option<gio::Settings>,
+    /// The button being shown
+    shown: Option<ButtonPosition>,
+}
+
+impl Preview {
+    fn new(widget: &gtk::Widget) -> Preview {
+        let popover = gtk::Popover::new(Some(widget));
+        // The keyboard must keep receiving touches
+        popover.set_modal(false);
+        popover.set_position(gtk::PositionType::Top);
+        popover.set_constrain_to(gtk::PopoverConstraint::None);
+        #[cfg(feature = "glib_v0_14")]
+        popover.style_context().add_class("preview");
+        #[cfg(not(feature = "glib_v0_14"))]
+        popover.get_style_context().add_class("preview");
+
+        let label = gtk::Label::new(None);
+        popover.add(&label);
+        label.show();
+
+        Preview {
+            popover,
+            label,
+            settings: popover::get_own_settings(),
+            shown: None,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        match &self.settings {
+            Some(settings) => popover::get_boolean(settings, SETTINGS_KEY),
+            None => true,
+        }
+    }
+
+    /// Shows the label above the button, given in widget coordinates
+    pub fn show(&mut self, button: &ButtonPosition, label: &str, bounds: Bounds) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.label.set_text(label);
+        self.popover.set_pointing_to(&gtk::Rectangle {
+            x: bounds.x.ceil() as i32,
+            y: bounds.y.ceil() as i32,
+            width: bounds.width.floor() as i32,
+            height: bounds.height.floor() as i32,
+        });
+        self.popover.show();
+        self.shown = Some(button.clone());
+    }
+
+    /// Hides the preview if it shows the button
+    pub fn hide(&mut self, button: &ButtonPosition) {
+        if self.shown.as_ref() == Some(button) {
+            self.popover.hide();
+            self.shown = None;
+        }
+    }
+}
@@ -32,6 +32,8 @@ static KEYBOARDS: &[(&'static str, &'static str)] = &[
     ("ch", include_str!("../data/keyboards/ch.yaml")),
     ("ch_wide", include_str!("../data/keyboards/ch_wide.yaml")),
 
+    ("cn", include_str!("../data/keyboards/cn.yaml")),
+
     ("de", include_str!("../data/keyboards/de.yaml")),
     ("de_wide", include_str!("../data/keyboards/de_wide.yaml")),
 
@@ -74,6 +76,8 @@ static KEYBOARDS: &[(&'static str, &'static str)] = &[
     ("jp+kana", include_str!("../data/keyboards/jp+kana.yaml")),
     ("jp+kana_wide", include_str!("../data/keyboards/jp+kana_wide.yaml")),
 
+    ("kr", include_str!("../data/keyboards/kr.yaml")),
+
     ("no", include_str!("../data/keyboards/no.yaml")),
 
     ("pl", include_str!("../data/keyboards/pl.yaml")),
@@ -125,6 +129,29 @@ pub fn get_keyboard(needle: &str) -> Option<&'static str> {
     KEYBOARDS.iter().find(|(name, _)| *name == needle).map(|(_, layout)| *layout)
 }
 
+/// Used when the user doesn't provide a dictionary
+pub static PINYIN_DICTIONARY: &str
+    = include_str!("../data/dictionaries/pinyin.txt");
+
+/// Used for gesture typing when the user doesn't provide a word list
+pub static WORDS: &str
+    = include_str!("../data/dictionaries/words.txt");
+
+/// Builtin transliteration tables, by name
+static TRANSLITERATIONS: &[(&'static str, &'static str)] = &[
+    ("bg", include_str!("../data/transliteration/bg.yaml")),
+];
+
+pub fn get_transliteration(needle: &str) -> Option<&'static str> {
+    TRANSLITERATIONS.iter()
+        .find(|(name, _)| *name == needle)
+        .map(|(_, table)| *table)
+}
+
+pub fn get_transliterations() -> Vec<&'static str> {
+    TRANSLITERATIONS.iter().map(|(name, _)| *name).collect()
+}
+
 static OVERLAY_NAMES: &[&'static str] = &[
     "emoji",
     "terminal",
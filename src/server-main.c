@@ -121,9 +121,13 @@ registry_handle_global (void *data,
     } else if (!strcmp (interface, zwp_input_method_manager_v2_interface.name)) {
         wayland->input_method_manager = wl_registry_bind(registry, name,
             &zwp_input_method_manager_v2_interface, 1);
+    } else if (!strcmp (interface, wp_fractional_scale_manager_v1_interface.name)) {
+        wayland->fractional_scale_manager = wl_registry_bind(registry, name,
+            &wp_fractional_scale_manager_v1_interface, 1);
     } else if (!strcmp (interface, "wl_output")) {
+        // Version 4 tells the name of the output
         struct wl_output *output = wl_registry_bind (registry, name,
-            &wl_output_interface, 2);
+            &wl_output_interface, MIN(version, 4));
         squeek_outputs_register(wayland->outputs, output, name);
     } else if (!strcmp(interface, "wl_seat")) {
         wayland->seat = wl_registry_bind(registry, name,
@@ -182,6 +186,10 @@ void init_wayland(struct squeek_wayland *wayland) {
         g_warning("Wayland input method interface not available");
     }
 
+    if (!wayland->fractional_scale_manager) {
+        g_debug("Fractional scaling not available");
+    }
+
     if (wayland->input_method_manager) {
         wayland->input_method = zwp_input_method_manager_v2_get_input_method(
             wayland->input_method_manager,
@@ -451,7 +459,7 @@ main (int argc, char **argv)
         rsobjects.state_manager,
         rsobjects.popover);
 
-    register_ui_loop_handler(rsobjects.receiver, &instance.panel_manager, rsobjects.popover, instance.settings_context, instance.dbus_handler);
+    register_ui_loop_handler(rsobjects.receiver, rsobjects.state_manager, &instance.panel_manager, rsobjects.popover, instance.settings_context, instance.dbus_handler);
 
     session_register();
 
@@ -7,23 +7,64 @@
 
 use crate::actors::external::debug;
 use crate::animation;
+use crate::devices;
 use crate::event_loop;
 use crate::event_loop::ActorState;
 use crate::imservice::{ ContentHint, ContentPurpose };
-use crate::layout::ArrangementKind;
+use crate::layout::{ ArrangementKind, Proportions };
 use crate::main;
 use crate::main::Commands;
 use crate::outputs;
 use crate::outputs::{Millimeter, OutputId, OutputState};
 use crate::panel;
-use crate::panel::PixelSize;
+use crate::panel::{ HeightPreference, PixelSize };
 use crate::popover;
+use crate::suggestions;
 use crate::util::Rational;
 use std::cmp;
 use std::collections::HashMap;
-use std::time::Instant;
+use std::time::{ Duration, Instant };
 
 
+// Based on what works on the L5.
+// Exceeding that probably wastes space. Reducing makes typing harder.
+const IDEAL_TARGET_SIZE: Rational<Millimeter> = Rational {
+    numerator: Millimeter(948),
+    denominator: 100,
+};
+
+/// Keys smaller than that can't be hit
+const MIN_KEY_HEIGHT: f64 = 4.0;
+
+/// The most the gsettings schema can store for key-height
+const MAX_KEY_HEIGHT: f64 = 30.0;
+
+/// Used before the layout gets loaded,
+/// and to pick the arrangement.
+const DEFAULT_ROW_COUNT: u32 = 4;
+
+/// Outputs wider than that get a split keyboard,
+/// because keys spread across the whole width are out of the thumbs' reach.
+const SPLIT_MIN_WIDTH: Millimeter = Millimeter(200);
+
+/// Outputs narrower than that, like watches, get a narrow layout
+const NARROW_MAX_WIDTH: Millimeter = Millimeter(50);
+
+/// Outputs at least that wide get a tablet layout, unless split
+const TABLET_MIN_WIDTH: Millimeter = Millimeter(140);
+
+/// The height relative to the width of a typical wide layout
+const WIDE_HEIGHT: Rational<i32> = Rational {
+    numerator: 172,
+    denominator: 540,
+};
+
+/// The height relative to the width of a typical base layout
+const BASE_HEIGHT: Rational<i32> = Rational {
+    numerator: 210,
+    denominator: 360,
+};
+
 #[derive(Clone, Copy, Debug)]
 pub enum Presence {
     Present,
@@ -76,6 +117,29 @@ pub enum Event {
     LayoutChoice(LayoutChoice),
     OverlayChanged(popover::LayoutId),
     Debug(debug::Event),
+    /// The layout got loaded, revealing how big the panel should be
+    LayoutLoaded {
+        contents: animation::Contents,
+        proportions: Proportions,
+    },
+    /// The user is dragging the top edge of the panel.
+    /// The height is in scaled pixels.
+    HeightDragged {
+        height: u32,
+        /// The drag finished
+        done: bool,
+    },
+    /// The user wants the keyboard on the output with this name or description.
+    /// `None` lets Squeekboard choose.
+    OutputPinned(Option<String>),
+    /// The user changed how long sliding takes, or turned animations off
+    SlideDurationChanged(Duration),
+    /// The compositor gave the panel its size.
+    /// It may differ from the requested one.
+    PanelConfigured {
+        output: OutputId,
+        size: panel::Size,
+    },
     /// Event triggered because a moment in time passed.
     /// Use to animate state transitions.
     /// The value is the ideal arrival time.
@@ -132,6 +196,8 @@ pub mod visibility {
 pub struct Outcome {
     pub panel: animation::Outcome,
     pub im: InputMethod,
+    /// `None` while the user is still adjusting it
+    pub height_preference: Option<HeightPreference>,
 }
 
 impl event_loop::Outcome for Outcome {
@@ -142,10 +208,12 @@ impl event_loop::Outcome for Outcome {
     /// The receivers of the commands bear the burden
     /// of checking if the commands end up being no-ops.
     fn get_commands_to_reach(&self, new_state: &Self) -> Commands {
-// FIXME: handle switching outputs
+        // Switching outputs is up to the panel manager,
+        // which requests a new surface on the other output.
+        // The layout stays unless it doesn't fit the new output.
         let (dbus_visible_set, panel_visibility) = match new_state.panel {
-            animation::Outcome::Visible{output, height, ..}
-                => (Some(true), Some(panel::Command::Show{output, height})),
+            animation::Outcome::Visible{output, height, progress, ..}
+                => (Some(true), Some(panel::Command::Show{output, height, progress})),
             animation::Outcome::Hidden => (Some(false), Some(panel::Command::Hide)),
         };
 
@@ -154,16 +222,22 @@ impl event_loop::Outcome for Outcome {
         use crate::animation::Outcome::*;
         let layout_selection = match &new_state.panel {
             Visible{ contents: new_contents, ..} => {
-                let same
-                    = if let Visible { contents, .. } = &self.panel {
-                        contents == new_contents
-                    } else {
-                        false
-                    };
+                let old_contents = match &self.panel {
+                    Visible { contents, .. } => Some(contents),
+                    Hidden => None,
+                };
 
+                let same = old_contents
+                    .map(|old| old.is_same_loaded(new_contents))
+                    .unwrap_or(false);
                 if !same {
                     Some(main::commands::SetLayout {
-                        description: new_contents.clone()
+                        description: new_contents.clone(),
+                        // After rotating, the user should be able
+                        // to continue where they left off.
+                        keep_view: old_contents
+                            .map(|old| old.is_same_source(new_contents))
+                            .unwrap_or(false),
                     })
                 } else {
                     None
@@ -172,10 +246,16 @@ impl event_loop::Outcome for Outcome {
             animation::Outcome::Hidden => None,
         };        
 
+        let height_preference = match new_state.height_preference {
+            Some(pref) if self.height_preference != Some(pref) => Some(pref),
+            _ => None,
+        };
+
         Commands {
             panel_visibility,
             dbus_visible_set,
             layout_selection,
+            height_preference,
         }
     }
 }
@@ -202,8 +282,13 @@ pub struct Application {
     /// because it's not clear how to derive the output from the rest of the state.
     /// It should probably follow the focused input,
     /// but not sure about being allowed on non-touch displays.
+    /// See `choose_output`.
     pub preferred_output: Option<OutputId>,
     pub outputs: HashMap<OutputId, OutputState>,
+    /// The outputs in the order they appeared
+    pub output_order: Vec<OutputId>,
+    /// Name or description of the output chosen by the user
+    pub pinned_output: Option<String>,
     /// We presume that the system always has some preference,
     /// even though we receive the preference after init,
     /// and we might not receive one at all (gsettings missing).
@@ -211,6 +296,19 @@ pub struct Application {
     pub layout_choice: LayoutChoice,
     /// Manual override of the system layout
     pub overlay_layout: Option<popover::LayoutId>,
+    pub height_preference: HeightPreference,
+    /// The user is dragging the panel edge
+    pub height_dragging: bool,
+    /// Shapes of the layouts loaded so far
+    pub layout_proportions: Vec<(animation::Contents, Proportions)>,
+    /// The size the compositor gave to the panel, in scaled pixels
+    pub allocated: Option<(OutputId, panel::Size)>,
+    /// Adjustments for known screens
+    pub devices: devices::Database,
+    /// Where the panel is going
+    pub slide: animation::Slide,
+    /// How long sliding in or out takes. Zero to just jump.
+    pub slide_duration: Duration,
 }
 
 impl Application {
@@ -228,14 +326,65 @@ impl Application {
             debug_mode_enabled: false,
             preferred_output: None,
             outputs: Default::default(),
+            output_order: Vec::new(),
+            pinned_output: None,
             layout_choice: LayoutChoice {
                 name: String::from("us"),
                 source: LayoutSource::Xkb,
             },
             overlay_layout: None,
+            height_preference: Default::default(),
+            height_dragging: false,
+            layout_proportions: Vec::new(),
+            allocated: None,
+            devices: Default::default(),
+            slide: animation::Slide::hidden(now),
+            slide_duration: Duration::from_millis(0),
         }
     }
 
+    /// Starts with the stored preference
+    pub fn with_height_preference(self, height_preference: HeightPreference) -> Self {
+        Self { height_preference, ..self }
+    }
+
+    /// Starts with the stored output choice
+    pub fn with_pinned_output(self, pinned_output: Option<String>) -> Self {
+        Self { pinned_output, ..self }
+    }
+
+    /// Starts with the loaded device files
+    pub fn with_devices(self, devices: devices::Database) -> Self {
+        Self { devices, ..self }
+    }
+
+    /// Starts with the stored animation preference
+    pub fn with_slide_duration(self, slide_duration: Duration) -> Self {
+        Self { slide_duration, ..self }
+    }
+
+    /// Picks the output for the panel, in order of importance:
+    /// the one chosen by the user,
+    /// a built-in one, because those are likely to have touch input,
+    /// and the one already showing the panel, so that it doesn't jump around.
+    /// Among equals, the one which appeared first wins.
+    fn choose_output(&self) -> Option<OutputId> {
+        self.outputs.iter()
+            .max_by_key(|(id, output)| (
+                self.pinned_output.as_ref()
+                    .map(|name| output.is_called(name))
+                    .unwrap_or(false),
+                output.is_builtin(),
+                self.preferred_output == Some(**id),
+                cmp::Reverse(
+                    self.output_order.iter()
+                        .position(|o| o == *id)
+                        .unwrap_or(usize::MAX)
+                ),
+            ))
+            .map(|(id, _output)| *id)
+    }
+
     pub fn apply_event(self, event: Event, now: Instant) -> Self {
         if self.debug_mode_enabled {
             println!(
@@ -255,6 +404,41 @@ impl Application {
 
             Event::TimeoutReached(_) => self,
 
+            Event::LayoutLoaded { contents, proportions } => {
+                let mut app = self;
+                app.layout_proportions.retain(|(c, _)| !c.is_same_layout(&contents));
+                app.layout_proportions.push((contents, proportions));
+                app
+            },
+
+            Event::HeightDragged { height, done } => {
+                let key_height = self.preferred_output
+                    .and_then(|output| self.outputs.get(&output))
+                    .and_then(|output| Self::get_key_height_for_panel(
+                        output,
+                        height,
+                        self.get_shown_proportions(output),
+                    ));
+                Self {
+                    height_preference: HeightPreference {
+                        key_height: key_height.or(self.height_preference.key_height),
+                        ..self.height_preference
+                    },
+                    height_dragging: !done,
+                    ..self
+                }
+            },
+
+            // The surface may get configured before it has any size
+            Event::PanelConfigured { size: panel::Size { width: 0, .. }, .. }
+            | Event::PanelConfigured { size: panel::Size { height: 0, .. }, .. }
+                => self,
+
+            Event::PanelConfigured { output, size } => Self {
+                allocated: Some((output, size)),
+                ..self
+            },
+
             Event::Visibility(visibility) => Self {
                 visibility_override: match visibility {
                     visibility::Event::ForceHidden => visibility::State::ForcedHidden,
@@ -272,21 +456,52 @@ impl Application {
                 let mut app = self;
                 match change {
                     outputs::ChangeType::Altered(state) => {
-                        app.outputs.insert(output, state);
-                        app.preferred_output = app.preferred_output.or(Some(output));
+                        let old_size = app.outputs.get(&output)
+                            .and_then(|old| old.get_pixel_size());
+                        // After rotating, the old allocation doesn't fit.
+                        // Better to pick the layout as if the panel was new,
+                        // until the compositor sends the new size.
+                        if old_size != state.get_pixel_size() {
+                            if let Some((allocated_output, _)) = app.allocated {
+                                if allocated_output == output {
+                                    app.allocated = None;
+                                }
+                            }
+                        }
+                        // Rotating alters the output too, but the overrides stay
+                        if !app.outputs.contains_key(&output) {
+                            app.devices.report(&state);
+                            app.output_order.push(output);
+                        }
+                        app.outputs.insert(output, app.devices.adjust(state));
                     },
                     outputs::ChangeType::Removed => {
                         app.outputs.remove(&output);
-                        if app.preferred_output == Some(output) {
-                            // There's currently no policy to choose one output over another,
-                            // so just take whichever comes first.
-                            app.preferred_output = app.outputs.keys().next().map(|output| *output);
+                        app.output_order.retain(|o| *o != output);
+                        if let Some((allocated_output, _)) = app.allocated {
+                            if allocated_output == output {
+                                app.allocated = None;
+                            }
                         }
                     },
                 };
+                app.preferred_output = app.choose_output();
                 app
             },
 
+            Event::OutputPinned(pinned_output) => {
+                let app = Self { pinned_output, ..self };
+                Self {
+                    preferred_output: app.choose_output(),
+                    ..app
+                }
+            },
+
+            Event::SlideDurationChanged(slide_duration) => Self {
+                slide_duration,
+                ..self
+            },
+
             Event::InputMethod(new_im)
             => match (self.im.clone(), new_im, self.visibility_override) {
                 (InputMethod::Active(_old), InputMethod::Active(new_im), _)
@@ -339,6 +554,12 @@ impl Application {
             },
         };
 
+        // The panel starts moving as soon as it's wanted somewhere else
+        let state = Self {
+            slide: state.get_slide(now),
+            ..state
+        };
+
         if state.debug_mode_enabled {
             println!(
                 "State is now:
@@ -352,80 +573,357 @@ Outcome:
         state
     }
 
-    fn get_preferred_height_and_arrangement(output: &OutputState)
-        -> Option<(PixelSize, ArrangementKind)>
+    /// Pixels/mm.
+    fn get_density(output: &OutputState, px_size: outputs::PixelSize)
+        -> Rational<i32>
+    {
+        // Assume isotropy.
+        output.get_physical_size()
+            .and_then(|size| size.width)
+            .map(|width| Rational {
+                numerator: px_size.width as i32,
+                denominator: width.0 as u32,
+            })
+            // Whatever the Librem 5 has,
+            // as a good default.
+            .unwrap_or(Rational {
+                numerator: 720,
+                denominator: 65,
+            })
+    }
+
+    /// The key size preferred by the user, or the default
+    fn get_target_size(preference: &HeightPreference) -> Rational<Millimeter> {
+        match preference.key_height {
+            Some(height) => Rational {
+                numerator: Millimeter((height * 100.0).round() as i32),
+                denominator: 100,
+            },
+            None => IDEAL_TARGET_SIZE,
+        }
+    }
+
+    /// The size of a comfortable key on the output
+    fn get_key_size(output: &OutputState, preference: &HeightPreference)
+        -> Option<PixelSize>
     {
         output.get_pixel_size()
             .map(|px_size| {
-                // Assume isotropy.
-                // Pixels/mm.
-                let density = output.get_physical_size()
-                    .and_then(|size| size.width)
-                    .map(|width| Rational {
-                        numerator: px_size.width as i32,
-                        denominator: width.0 as u32,
-                    })
-                    // Whatever the Librem 5 has,
-                    // as a good default.
-                    .unwrap_or(Rational {
-                        numerator: 720,
-                        denominator: 65,
-                    });
-
-                // Based on what works on the L5.
-                // Exceeding that probably wastes space. Reducing makes typing harder.
-                const IDEAL_TARGET_SIZE: Rational<Millimeter> = Rational {
-                    numerator: Millimeter(948),
-                    denominator: 100,
-                };
+                let density = Self::get_density(output, px_size);
+                PixelSize {
+                    scale_factor: output.get_scale(),
+                    pixels: (Self::get_target_size(preference) * density).ceil().0 as u32,
+                }
+            })
+    }
 
-                // TODO: calculate based on selected layout
-                const ROW_COUNT: u32 = 4;
+    /// The key height in millimeters which makes the panel as high as given.
+    /// The height is in scaled pixels, and includes the candidate strip.
+    fn get_key_height_for_panel(
+        output: &OutputState,
+        height: u32,
+        proportions: Option<Proportions>,
+    ) -> Option<f64> {
+        let rows = proportions
+            .map(|proportions| proportions.rows)
+            .unwrap_or(DEFAULT_ROW_COUNT);
+        let height = height.saturating_sub(Self::get_strip_height(proportions));
+        output.get_pixel_size()
+            .map(|px_size| {
+                let density = Self::get_density(output, px_size);
+                let scale = output.get_scale();
+                let pixels = height as f64 * scale.numerator as f64
+                    / scale.denominator as f64;
+                let millimeters = pixels * density.denominator as f64
+                    / density.numerator as f64;
+                (millimeters / rows as f64)
+                    .max(MIN_KEY_HEIGHT)
+                    .min(MAX_KEY_HEIGHT)
+            })
+    }
 
-                let ideal_height = IDEAL_TARGET_SIZE * ROW_COUNT as i32;
+    /// Whether the keyboard should be split in halves
+    fn is_split(output: &OutputState) -> bool {
+        output.get_physical_size()
+            .and_then(|size| size.width)
+            .map(|width| width.0 > SPLIT_MIN_WIDTH.0)
+            .unwrap_or(false)
+    }
+
+    /// Picks the arrangement based on the physical width alone,
+    /// together with the height of a typical layout of that kind,
+    /// relative to its width.
+    /// `None` when it depends on the shape of the panel.
+    /// The arrangement from the device file wins.
+    fn get_physical_arrangement(output: &OutputState, fixed: Option<ArrangementKind>)
+        -> Option<(ArrangementKind, Rational<i32>)>
+    {
+        if let Some(kind) = fixed {
+            return Some((
+                kind,
+                match kind {
+                    ArrangementKind::Base | ArrangementKind::Narrow => BASE_HEIGHT,
+                    ArrangementKind::Wide | ArrangementKind::Tablet => WIDE_HEIGHT,
+                },
+            ));
+        }
+        let width = output.get_physical_size()
+            .and_then(|size| size.width)?;
+        // The halves are made out of the base layout,
+        // and they are never too wide.
+        if Self::is_split(output) {
+            Some((ArrangementKind::Base, WIDE_HEIGHT))
+        } else if width.0 < NARROW_MAX_WIDTH.0 {
+            Some((ArrangementKind::Narrow, BASE_HEIGHT))
+        } else if width.0 >= TABLET_MIN_WIDTH.0 {
+            Some((ArrangementKind::Tablet, WIDE_HEIGHT))
+        } else {
+            None
+        }
+    }
+
+    /// Guesses which arrangement fits the output,
+    /// together with the height of a typical layout of that kind,
+    /// relative to its width.
+    fn get_arrangement(
+        output: &OutputState,
+        preference: &HeightPreference,
+        fixed: Option<ArrangementKind>,
+    ) -> Option<(ArrangementKind, Rational<i32>)> {
+        output.get_pixel_size()
+            .map(|px_size| {
+                let density = Self::get_density(output, px_size);
+
+                // The arrangement doesn't depend on the loaded layout.
+                // Otherwise, loading one could cause another to be picked.
+                let ideal_height = Self::get_target_size(preference)
+                    * DEFAULT_ROW_COUNT as i32;
                 let ideal_height_px = (ideal_height * density).ceil().0 as u32;
 
-                let max_wide_height = Rational {
-                    numerator: 172,
-                    denominator: 540,
-                };
                 let ideal_panel_height = Rational {
                     numerator: ideal_height_px as i32,
                     denominator: px_size.width,
                 };
                 // Reduce height to match what the layout can fill.
-                // For this, we need to guess if normal or wide will be picked.
-                // This must match `eek_gtk_keyboard.c::get_type`.
-                // TODO: query layout database and choose one directly
-                let (arrangement, height_as_widths) = {
-                    if max_wide_height < ideal_panel_height {(
-                        ArrangementKind::Base,
-                        Rational {
-                            numerator: 210,
-                            denominator: 360,
-                        },
-                    )} else {(
-                        ArrangementKind::Wide,
-                        max_wide_height,
-                    )}
+                // When the physical width doesn't decide,
+                // the shape of the panel picks normal or wide.
+                // The height of the loaded layout replaces this guess later,
+                // from its cached `Proportions`.
+                match Self::get_physical_arrangement(output, fixed) {
+                    Some(arrangement) => arrangement,
+                    None => if WIDE_HEIGHT < ideal_panel_height {
+                        (ArrangementKind::Base, BASE_HEIGHT)
+                    } else {
+                        (ArrangementKind::Wide, WIDE_HEIGHT)
+                    },
+                }
+            })
+    }
+
+    /// The proportions are looked up for the chosen arrangement.
+    /// Without them, a typical layout is assumed.
+    fn get_preferred_height_and_arrangement(
+        output: &OutputState,
+        preference: &HeightPreference,
+        fixed: Option<ArrangementKind>,
+        get_proportions: impl Fn(ArrangementKind) -> Option<Proportions>,
+    ) -> Option<(PixelSize, ArrangementKind)> {
+        let (arrangement, height_as_widths)
+            = Self::get_arrangement(output, preference, fixed)?;
+        output.get_pixel_size()
+            .map(|px_size| {
+                let density = Self::get_density(output, px_size);
+                let proportions = get_proportions(arrangement);
+
+                let rows = proportions
+                    .map(|proportions| proportions.rows)
+                    .unwrap_or(DEFAULT_ROW_COUNT);
+                let ideal_height = Self::get_target_size(preference) * rows as i32;
+                let ideal_height_px = (ideal_height * density).ceil().0 as u32;
+
+                let fill_height = match proportions {
+                    Some(proportions) => {
+                        (proportions.aspect * px_size.width as f64).ceil() as u32
+                    },
+                    None => (height_as_widths * px_size.width as i32).ceil() as u32,
                 };
 
-                let height
-                    = cmp::min(
-                        ideal_height_px,
-                        (height_as_widths * px_size.width as i32).ceil() as u32,
-                    );
+                // The layout may not fill the width,
+                // but the size chosen by the user wins.
+                let height = match preference.key_height {
+                    Some(_) => ideal_height_px,
+                    None => cmp::min(ideal_height_px, fill_height),
+                };
+                let scale = output.get_scale();
+                let strip_height = Self::get_strip_height(proportions)
+                    * scale.numerator / scale.denominator;
+                let height = height + strip_height;
+                let max_height = (px_size.height as f64 * preference.max_fraction) as u32;
 
                 (
                     PixelSize {
-                        scale_factor: output.scale as u32,
-                        pixels: cmp::min(height, px_size.height / 2),
+                        scale_factor: output.get_scale(),
+                        pixels: cmp::min(height, max_height),
                     },
                     arrangement,
                 )
             })
     }
-    
+
+    /// Describes the layout to show on the output
+    fn get_contents(&self, output: &OutputState, kind: ArrangementKind)
+        -> animation::Contents
+    {
+        let (name, overlay_name) = self.get_layout_names();
+        animation::Contents {
+            kind,
+            name,
+            overlay_name,
+            purpose: match self.im {
+                InputMethod::Active(InputMethodDetails { purpose, .. }) => purpose,
+                InputMethod::InactiveSince(_) => ContentPurpose::Normal,
+            },
+            key_size: Self::get_key_size(output, &self.get_height_preference(output)),
+            split: Self::is_split(output),
+            safe_area: self.devices.find(output)
+                .map(|device| device.safe_area)
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Picks the arrangement which fits the panel as it is,
+    /// rather than as it was requested.
+    fn get_allocated_arrangement(
+        output: &OutputState,
+        size: &panel::Size,
+        fixed: Option<ArrangementKind>,
+    ) -> ArrangementKind {
+        let panel_height = Rational {
+            numerator: size.height as i32,
+            denominator: size.width,
+        };
+        // Same as in `get_arrangement`
+        match Self::get_physical_arrangement(output, fixed) {
+            Some((kind, _)) => kind,
+            None => if WIDE_HEIGHT < panel_height {
+                ArrangementKind::Base
+            } else {
+                ArrangementKind::Wide
+            },
+        }
+    }
+
+    /// Describes the layout fitting the size given by the compositor.
+    /// The keys are scaled to fill the height.
+    fn get_allocated_contents(&self, output: &OutputState, size: &panel::Size)
+        -> animation::Contents
+    {
+        let contents = self.get_contents(
+            output,
+            Self::get_allocated_arrangement(
+                output,
+                size,
+                self.get_fixed_arrangement(output),
+            ),
+        );
+        let proportions = self.get_proportions(&contents);
+        let rows = proportions
+            .map(|proportions| proportions.rows)
+            .unwrap_or(DEFAULT_ROW_COUNT);
+        let height = size.height.saturating_sub(
+            Self::get_strip_height(proportions) + panel::HANDLE_HEIGHT
+        );
+        animation::Contents {
+            key_size: Some(PixelSize {
+                scale_factor: output.get_scale(),
+                pixels: height * output.get_scale().numerator
+                    / output.get_scale().denominator
+                    / rows,
+            }),
+            ..contents
+        }
+    }
+
+    /// The user's preference, with the key height from the device file
+    /// if the user didn't choose one
+    fn get_height_preference(&self, output: &OutputState) -> HeightPreference {
+        HeightPreference {
+            key_height: self.height_preference.key_height.or_else(|| {
+                self.devices.find(output)
+                    .and_then(|device| device.key_height)
+            }),
+            ..self.height_preference
+        }
+    }
+
+    /// The arrangement set in the device file
+    fn get_fixed_arrangement(&self, output: &OutputState) -> Option<ArrangementKind> {
+        self.devices.find(output)
+            .and_then(|device| device.get_arrangement())
+    }
+
+    /// The shape of the layout shown on the output, if it was loaded before
+    fn get_shown_proportions(&self, output: &OutputState) -> Option<Proportions> {
+        Self::get_arrangement(
+            output,
+            &self.get_height_preference(output),
+            self.get_fixed_arrangement(output),
+        )
+            .and_then(|(kind, _)| {
+                self.get_proportions(&self.get_contents(output, kind))
+            })
+    }
+
+    /// Height of the candidate strip above the layout, in scaled pixels,
+    /// if the layout shows it.
+    /// The handle doesn't make the panel higher:
+    /// it takes its room from the keys instead.
+    fn get_strip_height(proportions: Option<Proportions>) -> u32 {
+        let candidates = proportions
+            .map(|proportions| proportions.candidates)
+            .unwrap_or(false);
+        match candidates {
+            true => suggestions::HEIGHT,
+            false => 0,
+        }
+    }
+
+    /// The shape of the layout, if it was loaded before
+    fn get_proportions(&self, contents: &animation::Contents) -> Option<Proportions> {
+        self.layout_proportions.iter()
+            .find(|(c, _)| c.is_same_layout(contents))
+            .map(|(_, proportions)| *proportions)
+    }
+
+    /// Whether the panel should end up shown
+    fn is_wanted(&self, now: Instant) -> bool {
+        match (self.physical_keyboard, self.visibility_override) {
+            (_, visibility::State::ForcedHidden) => false,
+            (_, visibility::State::ForcedVisible) => true,
+            (Presence::Present, visibility::State::NotForced) => false,
+            (Presence::Missing, visibility::State::NotForced) => match self.im {
+                InputMethod::Active(_) => true,
+                InputMethod::InactiveSince(since) => now < since + animation::HIDING_TIMEOUT,
+            },
+        }
+    }
+
+    /// The movement of the panel, turned around if the panel is wanted elsewhere.
+    /// Events turn it when they arrive,
+    /// but hiding after a timeout starts without an event.
+    fn get_slide(&self, now: Instant) -> animation::Slide {
+        if self.is_wanted(now) == self.slide.showing {
+            return self.slide;
+        }
+        let turned = match self.im {
+            InputMethod::InactiveSince(since)
+                if since + animation::HIDING_TIMEOUT > self.slide.since()
+                => cmp::min(since + animation::HIDING_TIMEOUT, now),
+            _ => now,
+        };
+        self.slide.turn(turned, self.slide_duration)
+    }
+
     /// Returns layout name, overlay name
     fn get_layout_names(&self) -> (String, Option<String>) {
         (
@@ -455,50 +953,53 @@ impl ActorState for Application {
             panel: match self.preferred_output {
                 None => animation::Outcome::Hidden,
                 Some(output) => {
-                    let (height, arrangement) = Self::get_preferred_height_and_arrangement(self.outputs.get(&output).unwrap())
+                    let output_state = self.outputs.get(&output).unwrap();
+                    let (height, arrangement) = Self::get_preferred_height_and_arrangement(
+                        output_state,
+                        &self.get_height_preference(output_state),
+                        self.get_fixed_arrangement(output_state),
+                        |kind| self.get_proportions(&self.get_contents(output_state, kind)),
+                    )
                         .unwrap_or((
-                            PixelSize{pixels: 0, scale_factor: 1},
+                            PixelSize{pixels: 0, scale_factor: 1.into()},
                             ArrangementKind::Base,
                         ));
-                    let (layout_name, overlay) = self.get_layout_names();
         
+                    // The requested height stays as preferred,
+                    // otherwise the panel would never grow back.
+                    let contents = match &self.allocated {
+                        Some((allocated_output, size)) if *allocated_output == output
+                            => self.get_allocated_contents(output_state, size),
+                        _ => self.get_contents(output_state, arrangement),
+                    };
+        
+                    // The panel stays up while it slides out
+                    let slide = self.get_slide(now);
+                    let progress = slide.get_progress(now, self.slide_duration);
                     // TODO: Instead of setting size to 0 when the output is invalid,
                     // simply go invisible.
-                    let visible = animation::Outcome::Visible{
-                        output,
-                        height,
-                        contents: animation::Contents {
-                            kind: arrangement,
-                            name: layout_name,
-                            overlay_name: overlay,
-                            purpose: match self.im {
-                                InputMethod::Active(InputMethodDetails { purpose, .. }) => purpose,
-                                InputMethod::InactiveSince(_) => ContentPurpose::Normal,
-                            },
-                        }
-                    };
-
-                    match (self.physical_keyboard, self.visibility_override) {
-                        (_, visibility::State::ForcedHidden) => animation::Outcome::Hidden,
-                        (_, visibility::State::ForcedVisible) => visible,
-                        (Presence::Present, visibility::State::NotForced) => animation::Outcome::Hidden,
-                        (Presence::Missing, visibility::State::NotForced) => match self.im {
-                            InputMethod::Active(_) => visible,
-                            InputMethod::InactiveSince(since) => {
-                                if now < since + animation::HIDING_TIMEOUT { visible }
-                                else { animation::Outcome::Hidden }
-                            },
+                    match slide.showing || progress > 0.0 {
+                        true => animation::Outcome::Visible{
+                            output,
+                            height,
+                            contents,
+                            progress,
                         },
+                        false => animation::Outcome::Hidden,
                     }
                 }
             },
             im: self.im.clone(),
+            height_preference: match self.height_dragging {
+                true => None,
+                false => Some(self.height_preference),
+            },
         }
     }
 
     /// Returns the next time to update the outcome.
     fn get_next_wake(&self, now: Instant) -> Option<Instant> {
-        match self {
+        let hiding = match self {
             Self {
                 visibility_override: visibility::State::NotForced,
                 im: InputMethod::InactiveSince(since),
@@ -509,6 +1010,16 @@ impl ActorState for Application {
                 else { None }
             }
             _ => None,
+        };
+        // Moves the panel a step
+        let slide_end = self.get_slide(now).get_end(self.slide_duration);
+        let sliding = match now < slide_end {
+            true => Some(cmp::min(now + animation::FRAME_TIME, slide_end)),
+            false => None,
+        };
+        match (hiding, sliding) {
+            (Some(a), Some(b)) => Some(cmp::min(a, b)),
+            (a, b) => a.or(b),
         }
     }
 }
@@ -542,6 +1053,11 @@ pub mod test {
                 current_mode: None,
                 geometry: None,
                 scale: 1,
+                fractional_scale: None,
+                name: None,
+                description: None,
+                make: None,
+                model: None,
             },
         );
         Application {
@@ -743,10 +1259,15 @@ pub mod test {
                     },
                 }),
                 scale: 2,
-            }),
+                fractional_scale: None,
+                name: None,
+                description: None,
+                make: None,
+                model: None,
+            }, &HeightPreference::default(), None, |_| None),
             Some((
                 PixelSize {
-                    scale_factor: 2,
+                    scale_factor: 2.into(),
                     pixels: 420,
                 },
                 ArrangementKind::Base,
@@ -772,14 +1293,860 @@ pub mod test {
                     },
                 }),
                 scale: 1,
-            }),
+                fractional_scale: None,
+                name: None,
+                description: None,
+                make: None,
+                model: None,
+            }, &HeightPreference::default(), None, |_| None),
             Some((
                 PixelSize {
-                    scale_factor: 1,
+                    scale_factor: 1.into(),
                     pixels: 420,
                 },
                 ArrangementKind::Base,
             )),
         );
     }
+
+    /// A watch gets the narrow layout
+    #[test]
+    fn size_watch() {
+        use crate::outputs::{Mode, Geometry, c, Size};
+        let output = OutputState {
+            current_mode: Some(Mode {
+                width: 450,
+                height: 450,
+            }),
+            geometry: Some(Geometry{
+                transform: c::Transform::Normal,
+                phys_size: Size {
+                    width: Some(Millimeter(35)),
+                    height: Some(Millimeter(35)),
+                },
+            }),
+            scale: 1,
+            fractional_scale: None,
+            name: None,
+            description: None,
+            make: None,
+            model: None,
+        };
+        assert_eq!(
+            Application::get_preferred_height_and_arrangement(
+                &output,
+                &HeightPreference::default(),
+                None,
+                |_| None,
+            ).map(|(_, arrangement)| arrangement),
+            Some(ArrangementKind::Narrow),
+        );
+    }
+
+    /// Tablets in portrait are too narrow to split.
+    /// Small ones get the wide layout, bigger ones the tablet layout.
+    #[test]
+    fn size_tablet_portrait() {
+        use crate::outputs::{Mode, Geometry, c, Size};
+        let output = OutputState {
+            current_mode: Some(Mode {
+                width: 1600,
+                height: 2560,
+            }),
+            geometry: Some(Geometry{
+                transform: c::Transform::Normal,
+                phys_size: Size {
+                    width: Some(Millimeter(135)),
+                    height: Some(Millimeter(216)),
+                },
+            }),
+            scale: 2,
+            fractional_scale: None,
+            name: None,
+            description: None,
+            make: None,
+            model: None,
+        };
+        assert!(!Application::is_split(&output));
+        assert_eq!(
+            Application::get_preferred_height_and_arrangement(
+                &output,
+                &HeightPreference::default(),
+                None,
+                |_| None,
+            ).map(|(_, arrangement)| arrangement),
+            Some(ArrangementKind::Wide),
+        );
+        let output = OutputState {
+            geometry: Some(Geometry{
+                transform: c::Transform::Normal,
+                phys_size: Size {
+                    width: Some(Millimeter(160)),
+                    height: Some(Millimeter(256)),
+                },
+            }),
+            ..output
+        };
+        assert_eq!(
+            Application::get_preferred_height_and_arrangement(
+                &output,
+                &HeightPreference::default(),
+                None,
+                |_| None,
+            ).map(|(_, arrangement)| arrangement),
+            Some(ArrangementKind::Tablet),
+        );
+    }
+
+    /// A 13" tablet in landscape gets the base layout, split
+    #[test]
+    fn size_tablet_split() {
+        use crate::outputs::{Mode, Geometry, c, Size};
+        let output = OutputState {
+            current_mode: Some(Mode {
+                width: 2560,
+                height: 1600,
+            }),
+            geometry: Some(Geometry{
+                transform: c::Transform::Normal,
+                phys_size: Size {
+                    width: Some(Millimeter(290)),
+                    height: Some(Millimeter(180)),
+                },
+            }),
+            scale: 2,
+            fractional_scale: None,
+            name: None,
+            description: None,
+            make: None,
+            model: None,
+        };
+        assert!(Application::is_split(&output));
+        assert_eq!(
+            Application::get_preferred_height_and_arrangement(
+                &output,
+                &HeightPreference::default(),
+                None,
+                |_| None,
+            ),
+            Some((
+                PixelSize {
+                    scale_factor: 2.into(),
+                    pixels: 335,
+                },
+                ArrangementKind::Base,
+            )),
+        );
+    }
+
+    fn l5_output() -> OutputState {
+        use crate::outputs::{Mode, Geometry, c, Size};
+        OutputState {
+            current_mode: Some(Mode {
+                width: 720,
+                height: 1440,
+            }),
+            geometry: Some(Geometry{
+                transform: c::Transform::Normal,
+                phys_size: Size {
+                    width: Some(Millimeter(65)),
+                    height: Some(Millimeter(130)),
+                },
+            }),
+            scale: 2,
+            fractional_scale: None,
+            name: None,
+            description: None,
+            make: None,
+            model: None,
+        }
+    }
+
+    /// The panel takes as many pixels with a fractional scale,
+    /// but more scaled pixels
+    #[test]
+    fn size_fractional() {
+        let output = OutputState {
+            fractional_scale: Some(outputs::Scale { numerator: 180, denominator: 120 }),
+            ..l5_output()
+        };
+        let (height, _) = Application::get_preferred_height_and_arrangement(
+            &output,
+            &HeightPreference::default(),
+            None,
+            |_| None,
+        ).unwrap();
+        assert_eq!(
+            height,
+            PixelSize {
+                scale_factor: outputs::Scale { numerator: 3, denominator: 2 },
+                pixels: 420,
+            },
+        );
+        assert_eq!(height.as_scaled_ceiling(), 280);
+    }
+
+    /// The key height chosen by the user wins over the width of the layout
+    #[test]
+    fn size_preference() {
+        let preference = HeightPreference {
+            key_height: Some(12.0),
+            max_fraction: 0.5,
+        };
+        assert_eq!(
+            Application::get_preferred_height_and_arrangement(&l5_output(), &preference, None, |_| None),
+            Some((
+                PixelSize {
+                    scale_factor: 2.into(),
+                    pixels: 532,
+                },
+                ArrangementKind::Base,
+            )),
+        );
+        let preference = HeightPreference {
+            max_fraction: 0.3,
+            ..preference
+        };
+        assert_eq!(
+            Application::get_preferred_height_and_arrangement(&l5_output(), &preference, None, |_| None)
+                .map(|(height, _)| height.pixels),
+            Some(432),
+        );
+    }
+
+    /// A layout with more rows needs a higher panel
+    #[test]
+    fn size_rows() {
+        let preference = HeightPreference {
+            key_height: Some(12.0),
+            max_fraction: 0.5,
+        };
+        let proportions = Proportions { rows: 5, aspect: 1.0, candidates: false };
+        assert_matches!(
+            Application::get_preferred_height_and_arrangement(
+                &l5_output(),
+                &preference,
+                None,
+                |_| Some(proportions),
+            ),
+            Some((PixelSize { pixels: 664..=666, .. }, ArrangementKind::Base))
+        );
+    }
+
+    /// A wide layout doesn't get more height than it can fill
+    #[test]
+    fn size_aspect() {
+        let proportions = Proportions { rows: 4, aspect: 0.25, candidates: false };
+        assert_eq!(
+            Application::get_preferred_height_and_arrangement(
+                &l5_output(),
+                &HeightPreference::default(),
+                None,
+                |_| Some(proportions),
+            )
+                .map(|(height, _)| height.pixels),
+            Some(180),
+        );
+    }
+
+    /// The panel follows the layout once it's loaded
+    #[test]
+    fn layout_loaded() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = Application {
+            preferred_output: Some(id),
+            outputs: {
+                let mut outputs = HashMap::new();
+                outputs.insert(id, l5_output());
+                outputs
+            },
+            im: InputMethod::Active(imdetails_new()),
+            height_preference: HeightPreference {
+                key_height: Some(12.0),
+                max_fraction: 0.5,
+            },
+            ..Application::new(start)
+        };
+        let contents = match state.get_outcome(start).panel {
+            animation::Outcome::Visible { contents, height, .. } => {
+                assert_eq!(height.pixels, 532);
+                contents
+            },
+            other => panic!("Panel not visible: {:?}", other),
+        };
+        let state = state.apply_event(
+            Event::LayoutLoaded {
+                contents,
+                proportions: Proportions { rows: 5, aspect: 1.0, candidates: false },
+            },
+            start,
+        );
+        assert_matches!(
+            state.get_outcome(start).panel,
+            animation::Outcome::Visible {
+                height: PixelSize { pixels: 664..=666, .. },
+                ..
+            }
+        );
+        // Dragging to the same height keeps the key size
+        let state = state.apply_event(
+            Event::HeightDragged { height: 333, done: false },
+            start,
+        );
+        let key_height = state.height_preference.key_height.unwrap();
+        assert!((key_height - 12.0).abs() < 0.05, "{}", key_height);
+        // The size must fit in gsettings
+        let state = state.apply_event(
+            Event::HeightDragged { height: 2000, done: true },
+            start,
+        );
+        assert_eq!(state.height_preference.key_height, Some(MAX_KEY_HEIGHT));
+    }
+
+    /// The candidate strip gets room above the keys,
+    /// which doesn't count towards the key height when dragging
+    #[test]
+    fn layout_chrome() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = Application {
+            preferred_output: Some(id),
+            outputs: {
+                let mut outputs = HashMap::new();
+                outputs.insert(id, l5_output());
+                outputs
+            },
+            im: InputMethod::Active(imdetails_new()),
+            height_preference: HeightPreference {
+                key_height: Some(12.0),
+                max_fraction: 0.5,
+            },
+            ..Application::new(start)
+        };
+        let contents = match state.get_outcome(start).panel {
+            animation::Outcome::Visible { contents, .. } => contents,
+            other => panic!("Panel not visible: {:?}", other),
+        };
+        let state = state.apply_event(
+            Event::LayoutLoaded {
+                contents,
+                proportions: Proportions { rows: 4, aspect: 1.0, candidates: true },
+            },
+            start,
+        );
+        // 4 rows of 12mm, with the strip at scale 2
+        assert_matches!(
+            state.get_outcome(start).panel,
+            animation::Outcome::Visible {
+                height: PixelSize { pixels: 612, .. },
+                ..
+            }
+        );
+        let state = state.apply_event(
+            Event::HeightDragged { height: 306, done: false },
+            start,
+        );
+        let key_height = state.height_preference.key_height.unwrap();
+        assert!((key_height - 12.0).abs() < 0.05, "{}", key_height);
+    }
+
+    /// A lower panel than requested gets a wide layout,
+    /// but the request doesn't change
+    #[test]
+    fn allocated_size() {
+        use crate::event_loop::Outcome as _;
+
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = Application {
+            preferred_output: Some(id),
+            outputs: {
+                let mut outputs = HashMap::new();
+                outputs.insert(id, l5_output());
+                outputs
+            },
+            im: InputMethod::Active(imdetails_new()),
+            ..Application::new(start)
+        };
+        let requested = state.get_outcome(start);
+        let requested_height = match &requested.panel {
+            animation::Outcome::Visible { height, contents, .. } => {
+                assert_eq!(contents.kind, ArrangementKind::Base);
+                *height
+            },
+            other => panic!("Panel not visible: {:?}", other),
+        };
+
+        let state = state.apply_event(
+            Event::PanelConfigured {
+                output: id,
+                size: panel::Size { width: 360, height: 100 },
+            },
+            start,
+        );
+        let allocated = state.get_outcome(start);
+        assert_matches!(
+            &allocated.panel,
+            animation::Outcome::Visible {
+                contents: animation::Contents {
+                    kind: ArrangementKind::Wide,
+                    key_size: Some(PixelSize { pixels: 44, .. }),
+                    ..
+                },
+                ..
+            }
+        );
+        let commands = requested.get_commands_to_reach(&allocated);
+        assert_eq!(
+            commands.panel_visibility,
+            Some(panel::Command::Show {
+                output: id,
+                height: requested_height,
+                progress: 1.0,
+            }),
+        );
+        assert!(commands.layout_selection.is_some());
+
+        // Nothing more to do when the same size comes again
+        let state = state.apply_event(
+            Event::PanelConfigured {
+                output: id,
+                size: panel::Size { width: 360, height: 100 },
+            },
+            start,
+        );
+        assert!(
+            allocated.get_commands_to_reach(&state.get_outcome(start))
+                .layout_selection
+                .is_none()
+        );
+
+        // Nor while the panel gets dragged a little higher
+        let state = state.apply_event(
+            Event::PanelConfigured {
+                output: id,
+                size: panel::Size { width: 360, height: 104 },
+            },
+            start,
+        );
+        let dragged = state.get_outcome(start);
+        assert_matches!(
+            &dragged.panel,
+            animation::Outcome::Visible {
+                contents: animation::Contents {
+                    kind: ArrangementKind::Wide,
+                    key_size: Some(PixelSize { pixels: 46, .. }),
+                    ..
+                },
+                ..
+            }
+        );
+        assert!(
+            allocated.get_commands_to_reach(&dragged)
+                .layout_selection
+                .is_none()
+        );
+    }
+
+    /// Rotating picks another arrangement of the same layout,
+    /// and forgets the size allocated before
+    #[test]
+    fn rotation() {
+        use crate::event_loop::Outcome as _;
+
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = Application {
+            preferred_output: Some(id),
+            outputs: {
+                let mut outputs = HashMap::new();
+                outputs.insert(id, l5_output());
+                outputs
+            },
+            im: InputMethod::Active(imdetails_new()),
+            ..Application::new(start)
+        }
+            .apply_event(
+                Event::PanelConfigured {
+                    output: id,
+                    size: panel::Size { width: 360, height: 210 },
+                },
+                start,
+            );
+        let portrait = state.get_outcome(start);
+
+        let mut rotated = l5_output();
+        if let Some(geometry) = rotated.geometry.as_mut() {
+            geometry.transform = outputs::c::Transform::Rotated90;
+        }
+        let state = state.apply_event(
+            Event::Output(outputs::Event {
+                output: id,
+                change: outputs::ChangeType::Altered(rotated),
+            }),
+            start,
+        );
+        assert!(state.allocated.is_none());
+        let landscape = state.get_outcome(start);
+        assert_matches!(
+            &landscape.panel,
+            animation::Outcome::Visible {
+                contents: animation::Contents { kind: ArrangementKind::Wide, .. },
+                ..
+            }
+        );
+        let commands = portrait.get_commands_to_reach(&landscape);
+        assert_matches!(
+            commands.layout_selection,
+            Some(main::commands::SetLayout { keep_view: true, .. })
+        );
+        let height = |outcome: &Outcome| match outcome.panel {
+            animation::Outcome::Visible { height, .. } => height.pixels,
+            animation::Outcome::Hidden => 0,
+        };
+        assert!(height(&landscape) < height(&portrait));
+        assert_eq!(
+            commands.panel_visibility,
+            Some(panel::Command::Show {
+                output: id,
+                height: PixelSize { pixels: height(&landscape), scale_factor: 2.into() },
+                progress: 1.0,
+            }),
+        );
+
+        // A different layout starts from scratch
+        let state = state.apply_event(
+            Event::LayoutChoice(LayoutChoice {
+                name: "de".into(),
+                source: LayoutSource::Xkb,
+            }),
+            start,
+        );
+        assert_matches!(
+            landscape.get_commands_to_reach(&state.get_outcome(start)).layout_selection,
+            Some(main::commands::SetLayout { keep_view: false, .. })
+        );
+    }
+
+    fn named_output(name: &str, description: &str) -> OutputState {
+        OutputState {
+            name: Some(name.into()),
+            description: Some(description.into()),
+            ..l5_output()
+        }
+    }
+
+    fn output_added(id: OutputId, state: OutputState) -> Event {
+        Event::Output(outputs::Event {
+            output: id,
+            change: outputs::ChangeType::Altered(state),
+        })
+    }
+
+    /// A docked phone keeps the keyboard on its own screen
+    #[test]
+    fn output_builtin() {
+        let start = Instant::now();
+        let external = fake_output_id(1);
+        let builtin = fake_output_id(2);
+        let state = Application::new(start)
+            .apply_event(output_added(external, named_output("HDMI-A-1", "Monitor")), start);
+        assert_eq!(state.preferred_output, Some(external));
+
+        let state = state
+            .apply_event(output_added(builtin, named_output("DSI-1", "Panel")), start);
+        assert_eq!(state.preferred_output, Some(builtin));
+
+        // Changes to the other output don't move the keyboard
+        let state = state
+            .apply_event(output_added(external, named_output("HDMI-A-1", "Monitor")), start);
+        assert_eq!(state.preferred_output, Some(builtin));
+
+        let state = state.apply_event(
+            Event::Output(outputs::Event {
+                output: builtin,
+                change: outputs::ChangeType::Removed,
+            }),
+            start,
+        );
+        assert_eq!(state.preferred_output, Some(external));
+    }
+
+    /// Equal outputs don't get picked at random
+    #[test]
+    fn output_first() {
+        let start = Instant::now();
+        let first = fake_output_id(1);
+        let second = fake_output_id(2);
+        let third = fake_output_id(3);
+        let state = Application::new(start)
+            .apply_event(output_added(first, named_output("HDMI-A-1", "Monitor")), start)
+            .apply_event(output_added(second, named_output("HDMI-A-2", "Monitor")), start)
+            .apply_event(output_added(third, named_output("DP-1", "Monitor")), start);
+        assert_eq!(state.preferred_output, Some(first));
+
+        let state = state.apply_event(
+            Event::Output(outputs::Event {
+                output: first,
+                change: outputs::ChangeType::Removed,
+            }),
+            start,
+        );
+        assert_eq!(state.preferred_output, Some(second));
+    }
+
+    /// The user's choice wins, and it's remembered until the output comes back
+    #[test]
+    fn output_pinned() {
+        let start = Instant::now();
+        let external = fake_output_id(1);
+        let builtin = fake_output_id(2);
+        let state = Application::new(start)
+            .with_pinned_output(Some("Monitor".into()))
+            .apply_event(output_added(builtin, named_output("DSI-1", "Panel")), start);
+        assert_eq!(state.preferred_output, Some(builtin));
+
+        let state = state
+            .apply_event(output_added(external, named_output("HDMI-A-1", "Monitor")), start);
+        assert_eq!(state.preferred_output, Some(external));
+
+        let state = state.apply_event(Event::OutputPinned(Some("DSI-1".into())), start);
+        assert_eq!(state.preferred_output, Some(builtin));
+
+        let state = state.apply_event(Event::OutputPinned(Some("HDMI-A-1".into())), start);
+        assert_eq!(state.preferred_output, Some(external));
+
+        let state = state.apply_event(Event::OutputPinned(None), start);
+        assert_eq!(state.preferred_output, Some(builtin));
+    }
+
+    /// Dragging changes the size live, but it's stored only when done
+    #[test]
+    fn height_drag() {
+        use crate::event_loop::Outcome as _;
+
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let state = Application {
+            preferred_output: Some(id),
+            outputs: {
+                let mut outputs = HashMap::new();
+                outputs.insert(id, l5_output());
+                outputs
+            },
+            im: InputMethod::Active(imdetails_new()),
+            ..Application::new(start)
+        };
+        let initial = state.get_outcome(start);
+
+        let state = state.apply_event(
+            Event::HeightDragged { height: 266, done: false },
+            start,
+        );
+        let key_height = state.height_preference.key_height.unwrap();
+        assert!((key_height - 12.0).abs() < 0.01, "{}", key_height);
+        let dragging = state.get_outcome(start);
+        assert_matches!(
+            dragging.panel,
+            animation::Outcome::Visible {
+                // Rounding to the preference's precision may add a pixel
+                height: PixelSize { pixels: 532..=533, .. },
+                ..
+            }
+        );
+        assert_eq!(
+            initial.get_commands_to_reach(&dragging).height_preference,
+            None,
+        );
+
+        let state = state.apply_event(
+            Event::HeightDragged { height: 266, done: true },
+            start,
+        );
+        let done = state.get_outcome(start);
+        assert_eq!(
+            dragging.get_commands_to_reach(&done).height_preference,
+            Some(state.height_preference),
+        );
+        assert_eq!(done.get_commands_to_reach(&done).height_preference, None);
+    }
+
+    /// Known screens keep the keys away from their rounded corners
+    #[test]
+    fn safe_area() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let device = serde_yaml::from_str("
+match:
+    make: Purism
+    model: Librem 5
+safe_area:
+    bottom: 8
+").unwrap();
+        let state = Application {
+            preferred_output: Some(id),
+            outputs: {
+                let mut outputs = HashMap::new();
+                outputs.insert(id, OutputState {
+                    make: Some("Purism".into()),
+                    model: Some("Librem 5".into()),
+                    ..l5_output()
+                });
+                outputs
+            },
+            im: InputMethod::Active(imdetails_new()),
+            ..Application::new(start)
+        }.with_devices(devices::Database::new(vec![device]));
+        match state.get_outcome(start).panel {
+            animation::Outcome::Visible { contents, .. } => {
+                assert_eq!(contents.safe_area.bottom, 8.0);
+            },
+            other => panic!("Panel not visible: {:?}", other),
+        };
+
+        // Other screens are not affected
+        let state = state.apply_event(
+            output_added(id, l5_output()),
+            start,
+        );
+        match state.get_outcome(start).panel {
+            animation::Outcome::Visible { contents, .. } => {
+                assert_eq!(contents.safe_area, Default::default());
+            },
+            other => panic!("Panel not visible: {:?}", other),
+        };
+    }
+
+    /// The device file picks the arrangement and the key size,
+    /// but the user's choice still wins
+    #[test]
+    fn device_overrides() {
+        let start = Instant::now();
+        let id = fake_output_id(1);
+        let device = serde_yaml::from_str("
+match:
+    name: DSI-1
+arrangement: wide
+key_height: 6
+").unwrap();
+        let state = Application {
+            preferred_output: Some(id),
+            outputs: {
+                let mut outputs = HashMap::new();
+                outputs.insert(id, OutputState {
+                    name: Some("DSI-1".into()),
+                    ..l5_output()
+                });
+                outputs
+            },
+            im: InputMethod::Active(imdetails_new()),
+            ..Application::new(start)
+        }.with_devices(devices::Database::new(vec![device]));
+        assert_matches!(
+            state.get_outcome(start).panel,
+            animation::Outcome::Visible {
+                contents: animation::Contents {
+                    kind: ArrangementKind::Wide,
+                    // 6mm at 720px/65mm
+                    key_size: Some(PixelSize { pixels: 67, .. }),
+                    ..
+                },
+                ..
+            }
+        );
+
+        let state = state.with_height_preference(HeightPreference {
+            key_height: Some(9.48),
+            ..Default::default()
+        });
+        assert_matches!(
+            state.get_outcome(start).panel,
+            animation::Outcome::Visible {
+                contents: animation::Contents {
+                    kind: ArrangementKind::Wide,
+                    key_size: Some(PixelSize { pixels: 106, .. }),
+                    ..
+                },
+                ..
+            }
+        );
+    }
+
+    fn get_progress(outcome: &Outcome) -> Option<f64> {
+        match outcome.panel {
+            animation::Outcome::Visible { progress, .. } => Some(progress),
+            animation::Outcome::Hidden => None,
+        }
+    }
+
+    /// The panel slides in, and slides out after the hiding timeout
+    #[test]
+    fn slide() {
+        use crate::event_loop::ActorState as _;
+
+        let start = Instant::now();
+        let duration = Duration::from_millis(100);
+        let state = Application {
+            im: InputMethod::InactiveSince(start - Duration::from_secs(1)),
+            ..application_with_fake_output(start)
+        }.with_slide_duration(duration);
+        assert_eq!(get_progress(&state.get_outcome(start)), None);
+
+        let state = state.apply_event(Event::InputMethod(InputMethod::Active(imdetails_new())), start);
+        assert_eq!(get_progress(&state.get_outcome(start)), Some(0.0));
+        assert_eq!(state.get_next_wake(start), Some(start + animation::FRAME_TIME));
+        assert_eq!(
+            get_progress(&state.get_outcome(start + duration / 2)),
+            Some(0.5),
+        );
+        let shown = start + duration;
+        assert_eq!(get_progress(&state.get_outcome(shown)), Some(1.0));
+        assert_eq!(state.get_next_wake(shown), None);
+
+        let state = state.apply_event(Event::InputMethod(InputMethod::InactiveSince(shown)), shown);
+        // Stays up for a moment in case another text field takes focus
+        let hiding = shown + animation::HIDING_TIMEOUT;
+        assert_eq!(state.get_next_wake(shown), Some(hiding));
+        assert_eq!(get_progress(&state.get_outcome(hiding)), Some(1.0));
+        // Slides out on its own
+        assert_eq!(
+            get_progress(&state.get_outcome(hiding + duration / 2)),
+            Some(0.5),
+        );
+        assert_eq!(
+            state.get_next_wake(hiding + duration / 2),
+            Some(hiding + duration / 2 + animation::FRAME_TIME),
+        );
+        assert_eq!(get_progress(&state.get_outcome(hiding + duration)), None);
+
+        // Turns back in the middle
+        let state = state.apply_event(
+            Event::InputMethod(InputMethod::Active(imdetails_new())),
+            hiding + duration / 4,
+        );
+        assert_eq!(
+            get_progress(&state.get_outcome(hiding + duration / 2)),
+            Some(1.0),
+        );
+    }
+
+    /// Turning animations off takes effect on the next slide
+    #[test]
+    fn slide_duration_changed() {
+        use crate::event_loop::ActorState as _;
+
+        let start = Instant::now();
+        let state = Application {
+            im: InputMethod::InactiveSince(start - Duration::from_secs(1)),
+            ..application_with_fake_output(start)
+        }.with_slide_duration(Duration::from_millis(100));
+
+        let state = state.apply_event(
+            Event::SlideDurationChanged(Duration::from_millis(0)),
+            start,
+        );
+        let state = state.apply_event(Event::InputMethod(InputMethod::Active(imdetails_new())), start);
+        assert_eq!(get_progress(&state.get_outcome(start)), Some(1.0));
+    }
 }
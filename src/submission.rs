@@ -17,21 +17,31 @@
  * and those events SHOULD NOT cause any lost events.
  * */
 
+use std::cell::RefCell;
 use std::collections::HashSet;
 use std::ffi::CString;
+use std::rc::Rc;
 
 use crate::vkeyboard::c::ZwpVirtualKeyboardV1;
-use crate::action::Modifier;
+use crate::action::{ KeySym, Modifier };
+use crate::composition;
+use crate::composition::{ Composed, Composer, Input, Step };
 use crate::imservice;
 use crate::imservice::IMService;
 use crate::keyboard::{ KeyCode, KeyStateId, Modifiers, PressType };
 use crate::layout;
+use crate::layout::c::Point;
+use crate::logging;
+use crate::swipe;
+use crate::targeting;
 use crate::util::vec_remove;
 use crate::vkeyboard;
 use crate::vkeyboard::VirtualKeyboard;
+use xkbcommon::xkb;
 
 // traits
 use std::iter::FromIterator;
+use crate::logging::Warn;
 
 /// Gathers stuff defined in C or called by C
 pub mod c {
@@ -78,6 +88,22 @@ enum SubmittedAction {
 
 pub struct Submission {
     imservice: Option<Box<IMService>>,
+    /// Text being built out of multiple key presses
+    composer: Composer,
+    /// Kept across layout switches
+    composition_cache: composition::Cache,
+    /// The layout keeps the candidate strip, even when empty
+    shows_candidates: bool,
+    /// Last candidates shown to the user
+    candidates: Option<Vec<String>>,
+    /// Shows candidates to the user
+    candidates_listener: Option<Box<dyn Fn(Option<&[String]>)>>,
+    /// The word typed by the last gesture, still in the preedit
+    swiped: Option<Swiped>,
+    /// The letter of the gesture in progress, in the preedit
+    gesture_start: Option<GestureStart>,
+    /// Loaded on first use
+    words: Option<swipe::WordList>,
     virtual_keyboard: VirtualKeyboard,
     modifiers_active: Vec<(KeyStateId, Modifier)>,
     pressed: Vec<(KeyStateId, SubmittedAction)>,
@@ -85,16 +111,80 @@ pub struct Submission {
     keymap_idx: Option<usize>,
 }
 
+/// Words matching a gesture
+struct Swiped {
+    words: Vec<String>,
+    /// The one shown in the preedit
+    selected: usize,
+}
+
+/// Text to commit, and the new preedit
+type TextUpdate = (String, Option<String>);
+
+/// The letter a gesture started on.
+/// It shows up in the preedit on press, like any other letter,
+/// and gets committed before anything typed after it.
+/// Only a path leaving the start key replaces it.
+#[derive(Clone, Debug, PartialEq)]
+struct GestureStart {
+    letter: String,
+    /// A swiped word came right before, and needs a space after it
+    after_word: bool,
+}
+
+impl GestureStart {
+    /// Commits the swiped word, if any, and shows the letter
+    fn new(letter: &str, swiped: Option<String>) -> (GestureStart, TextUpdate) {
+        let start = GestureStart {
+            letter: letter.into(),
+            after_word: swiped.is_some(),
+        };
+        (start, (swiped.unwrap_or_default(), Some(letter.into())))
+    }
+
+    /// Keeps the letter
+    fn commit(self) -> TextUpdate {
+        (self.letter, Some(String::new()))
+    }
+
+    /// Replaces the letter with a swiped word, left in the preedit
+    fn replace_with_word(self, word: &str) -> TextUpdate {
+        let separator = match self.after_word {
+            true => " ",
+            false => "",
+        };
+        (separator.into(), Some(word.into()))
+    }
+
+    /// Replaces the letter with the one the finger slid to
+    fn retarget(self, letter: &str) -> TextUpdate {
+        (letter.into(), Some(String::new()))
+    }
+}
+
 pub enum SubmitData<'a> {
     Text(&'a CString),
     Erase,
     Keycodes,
+    /// Modifies the next key press
+    Dead {
+        keysym: &'a KeySym,
+        label: &'a str,
+    },
 }
 
 impl Submission {
     pub fn new(vk: ZwpVirtualKeyboardV1, imservice: Option<Box<IMService>>) -> Self {
         Submission {
             imservice,
+            composer: Composer::new(None),
+            composition_cache: Default::default(),
+            shows_candidates: false,
+            candidates: None,
+            candidates_listener: None,
+            swiped: None,
+            gesture_start: None,
+            words: None,
             modifiers_active: Vec::new(),
             virtual_keyboard: VirtualKeyboard(vk),
             pressed: Vec::new(),
@@ -103,6 +193,193 @@ impl Submission {
         }
     }
 
+    /// The listener gets called whenever the candidates change.
+    /// It receives `None` when the layout doesn't use candidates.
+    pub fn set_candidates_listener(
+        &mut self,
+        listener: Box<dyn Fn(Option<&[String]>)>,
+    ) {
+        listener(self.candidates.as_deref());
+        self.candidates_listener = Some(listener);
+    }
+
+    /// Commits the candidate picked by the user.
+    /// Swiped words stay in the preedit, so that they can be picked again.
+    pub fn select_candidate(&mut self, index: usize) {
+        if let Some(imservice) = &mut self.imservice {
+            if imservice.is_active() {
+                match &mut self.swiped {
+                    Some(swiped) => if let Some(word) = swiped.words.get(index) {
+                        swiped.selected = index;
+                        let _ = update_text(imservice, String::new(), Some(word.clone()));
+                    },
+                    None => if let Some(Composed { commit, preedit }) = self.composer.select(index) {
+                        let _ = update_text(imservice, commit, Some(preedit));
+                    },
+                }
+            }
+        }
+        self.update_candidates();
+    }
+
+    fn update_candidates(&mut self) {
+        let candidates = match &self.swiped {
+            Some(swiped) => Some(swiped.words.clone()),
+            None => match self.shows_candidates {
+                true => Some(self.composer.candidates().unwrap_or_default()),
+                false => None,
+            },
+        };
+        if candidates != self.candidates {
+            if let Some(listener) = &self.candidates_listener {
+                listener(candidates.as_deref());
+            }
+            self.candidates = candidates;
+        }
+    }
+
+    /// The text field is gone, and so is anything composed for it.
+    /// The preedit goes away on its own.
+    /// This must not use the input method,
+    /// which is in the middle of handling the event.
+    fn handle_deactivate(&mut self) {
+        self.composer.reset();
+        self.swiped = None;
+        self.gesture_start = None;
+        self.update_candidates();
+    }
+
+    /// The hints of the text field ask not to show what's typed.
+    /// Use `Layout::is_text_hidden`, which also checks the purpose.
+    pub fn is_hinted_hidden(&self) -> bool {
+        self.imservice.as_ref()
+            .filter(|imservice| imservice.is_active())
+            .map(|imservice| imservice.get_content_hint().intersects(
+                imservice::ContentHint::HIDDEN_TEXT
+                    | imservice::ContentHint::SENSITIVE_DATA
+            ))
+            .unwrap_or(false)
+    }
+
+    /// Gestures need text input, and can't type shortcuts
+    pub fn accepts_gestures(&self) -> bool {
+        let active = self.imservice.as_ref()
+            .map(|imservice| imservice.is_active())
+            .unwrap_or(false);
+        active && self.modifiers_active.is_empty()
+    }
+
+    /// Shows the letter the gesture starts on.
+    /// Returns false if the letter must be typed as usual.
+    pub fn start_gesture(&mut self, letter: &str) -> bool {
+        self.commit_gesture_start();
+        let swiped = self.take_swiped();
+        let imservice = match &mut self.imservice {
+            Some(imservice) => imservice,
+            None => return false,
+        };
+        let (start, (commit, preedit)) = GestureStart::new(letter, swiped);
+        match update_text(imservice, commit, preedit) {
+            Ok(()) => {
+                self.gesture_start = Some(start);
+                self.update_candidates();
+                true
+            },
+            Err(imservice::SubmitError::NotActive) => false,
+        }
+    }
+
+    /// Ends the gesture started with `start_gesture`.
+    /// A path leaving the start key types the word best matching it,
+    /// and offers the others as candidates.
+    /// If no word matches, the letter where the path ended gets typed.
+    pub fn finish_gesture(
+        &mut self,
+        keys: &[swipe::Key],
+        path: Option<&[Point]>,
+        end_letter: Option<&str>,
+    ) {
+        let start = match self.take_gesture_start() {
+            Some(start) => start,
+            // Something else got typed in the meantime
+            None => return,
+        };
+        let found = match path {
+            Some(path) => {
+                let words = self.words.get_or_insert_with(swipe::load_word_list);
+                swipe::recognize(words, keys, path)
+            },
+            None => Vec::new(),
+        };
+        let (commit, preedit) = match (found.first(), end_letter) {
+            (Some(word), _) => start.replace_with_word(word),
+            (None, Some(letter)) => start.retarget(letter),
+            (None, None) => start.commit(),
+        };
+        if let Some(imservice) = &mut self.imservice {
+            if update_text(imservice, commit, preedit).is_ok() && !found.is_empty() {
+                self.swiped = Some(Swiped { words: found, selected: 0 });
+            }
+        }
+        self.update_candidates();
+    }
+
+    /// Drops the letter of the gesture without typing anything
+    pub fn cancel_gesture(&mut self) {
+        if self.take_gesture_start().is_some() {
+            if let Some(imservice) = &mut self.imservice {
+                let _ = update_text(imservice, String::new(), Some(String::new()));
+            }
+            self.update_candidates();
+        }
+    }
+
+    /// How likely each letter is to come next in the word being typed.
+    /// `None` when there's no way to tell.
+    pub fn get_prediction(&mut self) -> Option<targeting::Prediction> {
+        if self.composer.is_composing() {
+            return None;
+        }
+        let imservice = self.imservice.as_ref()
+            .filter(|imservice| imservice.is_active())?;
+        // The next letter starts a new word after a gesture
+        let prefix = match self.swiped {
+            Some(_) => "",
+            None => imservice.get_word_before_cursor()?,
+        };
+        let words = self.words.get_or_insert_with(swipe::load_word_list);
+        targeting::Prediction::from_words(words, prefix)
+    }
+
+    /// The letter of the gesture in progress, if it's still in the text field
+    fn take_gesture_start(&mut self) -> Option<GestureStart> {
+        let start = self.gesture_start.take()?;
+        let imservice = self.imservice.as_ref()
+            .filter(|imservice| imservice.is_active())?;
+        Some(start)
+            .filter(|start| imservice.get_preedit_string() == start.letter)
+    }
+
+    /// Types the letter of the gesture in progress before anything else
+    fn commit_gesture_start(&mut self) {
+        if let Some(start) = self.take_gesture_start() {
+            if let Some(imservice) = &mut self.imservice {
+                let (commit, preedit) = start.commit();
+                let _ = update_text(imservice, commit, preedit);
+            }
+        }
+    }
+
+    /// The swiped word, if it's still in the text field
+    fn take_swiped(&mut self) -> Option<String> {
+        let swiped = self.swiped.take()?;
+        let imservice = self.imservice.as_ref()
+            .filter(|imservice| imservice.is_active())?;
+        swiped.words.get(swiped.selected)
+            .filter(|word| imservice.get_preedit_string() == word.as_str())
+            .cloned()
+    }
+
     /// Sends a submit text event if possible;
     /// otherwise sends key press and makes a note of it
     pub fn handle_press(
@@ -112,41 +389,38 @@ impl Submission {
         keycodes: &Vec<KeyCode>,
         time: Timestamp,
     ) {
-        let mods_are_on = !self.modifiers_active.is_empty();
-
-        let was_committed_as_text = match (&mut self.imservice, mods_are_on) {
-            (Some(imservice), false) => {
-                enum Outcome {
-                    Submitted(Result<(), imservice::SubmitError>),
-                    NotSubmitted,
-                }
-
-                let submit_outcome = match data {
-                    SubmitData::Text(text) => {
-                        Outcome::Submitted(imservice.commit_string(text))
-                    },
+        // Another touch point may be in the middle of a gesture
+        self.commit_gesture_start();
+        // A swiped word is done when anything else gets typed
+        if let Some(word) = self.take_swiped() {
+            if let Some(imservice) = &mut self.imservice {
+                match data {
+                    // Erasing drops the whole word
                     SubmitData::Erase => {
-                        /* Delete_surrounding_text takes byte offsets,
-                         * so cannot work without get_surrounding_text.
-                         * This is a bug in the protocol.
-                         */
-                        // imservice.delete_surrounding_text(1, 0),
-                        Outcome::NotSubmitted
+                        let _ = update_text(imservice, String::new(), Some(String::new()));
+                        self.pressed.push((key_id, SubmittedAction::IMService));
+                        self.update_candidates();
+                        return;
                     },
-                    SubmitData::Keycodes => Outcome::NotSubmitted,
-                };
-
-                match submit_outcome {
-                    Outcome::Submitted(result) => {
-                        match result.and_then(|()| imservice.commit()) {
-                            Ok(()) => true,
-                            Err(imservice::SubmitError::NotActive) => false,
-                        }
+                    _ => {
+                        let _ = update_text(imservice, word, Some(String::new()));
                     },
-                    Outcome::NotSubmitted => false,
                 }
+            }
+        }
+
+        let mods_are_on = !self.modifiers_active.is_empty();
+
+        let was_committed_as_text = match (&mut self.imservice, mods_are_on) {
+            (Some(imservice), false) => {
+                submit_to_imservice(imservice, &mut self.composer, data)
             },
-            (_, _) => false,
+            (Some(imservice), true) => {
+                // Shortcuts act on finished text
+                finish_composition(imservice, &mut self.composer);
+                false
+            },
+            (None, _) => false,
         };
 
         let submit_action = match was_committed_as_text {
@@ -185,6 +459,7 @@ impl Submission {
         };
         
         self.pressed.push((key_id, submit_action));
+        self.update_candidates();
     }
     
     pub fn handle_release(&mut self, key_id: KeyStateId, time: Timestamp) {
@@ -304,6 +579,23 @@ impl Submission {
     }
     
     pub fn use_layout(&mut self, layout: &layout::LayoutData, time: Timestamp) {
+        // Text composed on the previous layout is done.
+        self.commit_gesture_start();
+        let swiped = self.take_swiped();
+        if let Some(imservice) = &mut self.imservice {
+            if let Some(word) = swiped {
+                let _ = update_text(imservice, word, Some(String::new()));
+            }
+            finish_composition(imservice, &mut self.composer);
+        }
+        let cache = &mut self.composition_cache;
+        self.composer = Composer::new(
+            layout.composition.clone()
+                .and_then(|kind| composition::create_engine(kind, cache))
+        );
+        self.shows_candidates = layout.shows_candidates();
+        self.update_candidates();
+
         self.keymap_fds = layout.keymaps.iter()
             .map(|keymap_str| vkeyboard::c::KeyMap::from_cstr(
                 keymap_str.as_c_str()
@@ -318,3 +610,184 @@ impl Submission {
         self.select_keymap(0, time);
     }
 }
+
+/// Clears the candidates whenever the text field goes away
+pub fn connect_deactivate(submission: &Rc<RefCell<Submission>>) {
+    let weak = Rc::downgrade(submission);
+    if let Some(imservice) = &mut submission.borrow_mut().imservice {
+        imservice.set_deactivate_listener(Box::new(move || {
+            if let Some(submission) = weak.upgrade() {
+                submission.try_borrow_mut()
+                    .map(|mut submission| submission.handle_deactivate())
+                    .or_print(logging::Problem::Bug, "Submission is busy");
+            }
+        }));
+    }
+}
+
+/// Passes the key press through composition
+/// and submits the resulting text using the input method.
+/// Returns false if the key still needs to be submitted as key presses.
+fn submit_to_imservice(
+    imservice: &mut IMService,
+    composer: &mut Composer,
+    data: SubmitData,
+) -> bool {
+    if !imservice.is_active() {
+        // The text field is gone, and so is anything composed for it
+        composer.reset();
+        return false;
+    }
+    if composer.is_composing() && imservice.get_preedit_string().is_empty() {
+        // The input method got reactivated, which cleared the preedit
+        composer.reset();
+    }
+
+    let step = match &data {
+        SubmitData::Text(text) => {
+            composer.feed(Input::Text(&text.to_string_lossy()))
+        },
+        SubmitData::Erase => composer.feed(Input::Erase),
+        SubmitData::Keycodes => Step::Pass { commit: composer.flush() },
+        SubmitData::Dead { keysym, label } => composer.feed(Input::Dead {
+            keysym: xkb::keysym_from_name(&keysym.0, xkb::KEYSYM_NO_FLAGS),
+            label,
+        }),
+    };
+
+    // The key is not needed any more if it was consumed by composition,
+    // or if it only carried text.
+    let (commit, preedit, key_consumed) = match step {
+        Step::Compose(Composed { commit, preedit }) => {
+            (commit, Some(preedit), true)
+        },
+        Step::Pass { commit } => {
+            // Ending the composition clears the preedit
+            let preedit = commit.as_ref().map(|_| String::new());
+            let commit = commit.unwrap_or_default();
+            match data {
+                SubmitData::Text(text) => {
+                    (commit + &text.to_string_lossy(), preedit, true)
+                },
+                // Delete_surrounding_text takes byte offsets,
+                // so cannot work without get_surrounding_text.
+                // This is a bug in the protocol.
+                // imservice.delete_surrounding_text(1, 0),
+                SubmitData::Erase => (commit, preedit, false),
+                SubmitData::Keycodes => (commit, preedit, false),
+                // Let the application try to compose it
+                SubmitData::Dead { keysym: _, label: _ } => {
+                    (commit, preedit, false)
+                },
+            }
+        },
+    };
+
+    if commit.is_empty() && preedit.is_none() {
+        return key_consumed;
+    }
+
+    match update_text(imservice, commit, preedit) {
+        Ok(()) => key_consumed,
+        Err(imservice::SubmitError::NotActive) => false,
+    }
+}
+
+/// Commits whatever was being composed.
+fn finish_composition(imservice: &mut IMService, composer: &mut Composer) {
+    if let Some(text) = composer.flush() {
+        // If the input method is not active, there's nowhere to put the text.
+        let _ = update_text(imservice, text, Some(String::new()));
+    }
+}
+
+/// Sends the finished text and the new preedit in a single commit.
+fn update_text(
+    imservice: &mut IMService,
+    commit: String,
+    preedit: Option<String>,
+) -> Result<(), imservice::SubmitError> {
+    let committed = match commit.is_empty() {
+        true => Ok(()),
+        false => CString::new(commit)
+            .or_print(logging::Problem::Bug, "Text contains a null byte")
+            .map(|commit| imservice.commit_string(&commit))
+            .unwrap_or(Ok(())),
+    };
+    committed
+        .and_then(|()| match preedit {
+            Some(preedit) => {
+                let cursor = Some((preedit.len(), preedit.len()));
+                imservice.set_preedit_string(preedit, cursor)
+            },
+            None => Ok(()),
+        })
+        .and_then(|()| imservice.commit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// What the text field shows
+    #[derive(Default)]
+    struct Field {
+        text: String,
+        preedit: String,
+    }
+
+    impl Field {
+        fn apply(&mut self, (commit, preedit): TextUpdate) {
+            self.text += &commit;
+            if let Some(preedit) = preedit {
+                self.preedit = preedit;
+            }
+        }
+    }
+
+    /// The second thumb presses a key while the first one is still down
+    #[test]
+    fn gesture_overlapping_touches() {
+        let mut field = Field::default();
+        // The first thumb lands on "a"
+        let (start, update) = GestureStart::new("a", None);
+        field.apply(update);
+        assert_eq!((field.text.as_str(), field.preedit.as_str()), ("", "a"));
+        // The second thumb types "b", which commits "a" first
+        field.apply(start.commit());
+        field.apply(("b".into(), None));
+        // The first thumb lifts, but the letter is gone from the preedit
+        assert_eq!((field.text.as_str(), field.preedit.as_str()), ("ab", ""));
+    }
+
+    /// Swiped words get separated, and a tap after them just follows
+    #[test]
+    fn gesture_after_word() {
+        let mut field = Field::default();
+        let (start, update) = GestureStart::new("h", None);
+        field.apply(update);
+        field.apply(start.replace_with_word("hello"));
+        assert_eq!((field.text.as_str(), field.preedit.as_str()), ("", "hello"));
+
+        let (start, update) = GestureStart::new("w", Some("hello".into()));
+        field.apply(update);
+        assert_eq!((field.text.as_str(), field.preedit.as_str()), ("hello", "w"));
+        field.apply(start.replace_with_word("world"));
+        assert_eq!((field.text.as_str(), field.preedit.as_str()), ("hello ", "world"));
+
+        let (start, update) = GestureStart::new("s", Some("world".into()));
+        field.apply(update);
+        field.apply(start.commit());
+        assert_eq!((field.text.as_str(), field.preedit.as_str()), ("hello worlds", ""));
+    }
+
+    /// Sliding to a letter with no word along the way types only that letter
+    #[test]
+    fn gesture_retarget() {
+        let mut field = Field::default();
+        let (start, update) = GestureStart::new("a", None);
+        field.apply(update);
+        field.apply(start.retarget("s"));
+        assert_eq!((field.text.as_str(), field.preedit.as_str()), ("s", ""));
+    }
+}
@@ -0,0 +1,9 @@
+#pragma once
+
+#include <gtk/gtk.h>
+
+#include "src/submission.h"
+
+// Defined in Rust
+/// Creates the strip showing candidates for the text being composed
+GtkWidget *squeek_suggestions_new(struct submission *submission);
@@ -0,0 +1,95 @@
+/*! The strip above the keyboard showing text candidates.
+ *
+ * Candidates come from the composition engine or from gesture typing,
+ * through `Submission`.
+ * The strip is shown for layouts which may offer candidates,
+ * even while there are none, so that the keys keep their size.
+ * Other layouts keep all the space for buttons.
+ */
+
+use gtk;
+use std::cell::RefCell;
+use std::rc::{ Rc, Weak };
+
+use crate::submission::Submission;
+
+// Traits
+use gtk::prelude::*;
+
+
+/// Enough for a line of text in a button. In scaled pixels
+pub const HEIGHT: u32 = 40;
+
+
+pub mod c {
+    use super::*;
+
+    use glib::translate::ToGlibPtr;
+
+    use crate::submission;
+
+    #[no_mangle]
+    pub extern "C"
+    fn squeek_suggestions_new(submission: submission::c::Submission)
+        -> *mut gtk_sys::GtkWidget
+    {
+        unsafe { gtk::set_initialized() };
+        let strip = create(&submission.clone_ref());
+        strip.to_glib_full()
+    }
+}
+
+fn create(submission: &Rc<RefCell<Submission>>) -> gtk::Widget {
+    let scrolled = gtk::ScrolledWindow::new(
+        None::<&gtk::Adjustment>,
+        None::<&gtk::Adjustment>,
+    );
+    scrolled.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Never);
+    // Visibility follows the layout, not the parent being shown
+    scrolled.set_no_show_all(true);
+    #[cfg(feature = "glib_v0_14")]
+    scrolled.style_context().add_class("suggestions");
+    #[cfg(not(feature = "glib_v0_14"))]
+    scrolled.get_style_context().add_class("suggestions");
+
+    let strip = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+    // The same for all candidates
+    strip.set_size_request(-1, HEIGHT as i32);
+    scrolled.add(&strip);
+    strip.show();
+
+    let weak_submission = Rc::downgrade(submission);
+    let scrolled_inner = scrolled.clone();
+    submission.borrow_mut().set_candidates_listener(Box::new(
+        move |candidates| {
+            fill(&strip, candidates.unwrap_or(&[]), weak_submission.clone());
+            scrolled_inner.set_visible(candidates.is_some());
+        }
+    ));
+    scrolled.upcast()
+}
+
+/// Replaces the buttons with ones for the new candidates
+fn fill(
+    strip: &gtk::Box,
+    candidates: &[String],
+    submission: Weak<RefCell<Submission>>,
+) {
+    strip.foreach(|child| strip.remove(child));
+    for (index, candidate) in candidates.iter().enumerate() {
+        #[cfg(feature = "glib_v0_14")]
+        let button = gtk::Button::with_label(candidate);
+        #[cfg(not(feature = "glib_v0_14"))]
+        let button = gtk::Button::new_with_label(candidate);
+        button.set_relief(gtk::ReliefStyle::None);
+        button.set_can_focus(false);
+        let submission = submission.clone();
+        button.connect_clicked(move |_| {
+            if let Some(submission) = submission.upgrade() {
+                submission.borrow_mut().select_candidate(index);
+            }
+        });
+        strip.add(&button);
+        button.show();
+    }
+}
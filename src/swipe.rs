@@ -0,0 +1,357 @@
+/*! Gesture typing: words out of paths drawn across letter keys.
+ *
+ * The path is compared with the ideal path of each word,
+ * going straight through the centers of its keys.
+ * Both get resampled to the same number of points,
+ * and the average distance between matching points decides the winner.
+ *
+ * This is a library module.
+ */
+
+use std::fs;
+
+use crate::float_ord::FloatOrd;
+use crate::layout::c::Point;
+use crate::logging;
+use crate::popover;
+use crate::resources;
+use crate::xdg;
+
+// Traits
+use crate::logging::Warn;
+
+
+/// Points compared between the drawn and the ideal path
+const SAMPLES: usize = 32;
+
+/// How far from the ideal path the drawn one may be on average,
+/// relative to the distance between neighbouring keys
+const MAX_DISTANCE: f64 = 1.0;
+
+/// Favors frequent words among similar paths
+const RANK_WEIGHT: f64 = 0.05;
+
+/// Only so many alternatives fit on the screen
+pub const MAX_CANDIDATES: usize = 5;
+
+/// Gesture typing is off unless the user turns it on,
+/// because the word list only covers English
+const SETTINGS_KEY: &str = "gesture-typing";
+
+
+/// Words ordered by frequency, most frequent first
+#[derive(Debug, Default)]
+pub struct WordList {
+    words: Vec<String>,
+}
+
+impl WordList {
+    /// One word per line. Lines starting with "#" are comments.
+    pub fn parse(text: &str) -> WordList {
+        WordList {
+            words: text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_lowercase)
+                .collect(),
+        }
+    }
+
+    /// Most frequent first
+    pub fn iter(&self) -> impl Iterator<Item=&str> {
+        self.words.iter().map(String::as_str)
+    }
+}
+
+/// Loads the word list from the user's data directory,
+/// or uses the builtin one.
+pub fn load_word_list() -> WordList {
+    let path = xdg::data_path("squeekboard/words.txt");
+    let text = path.as_ref()
+        .filter(|path| path.exists())
+        .and_then(|path| {
+            fs::read_to_string(path)
+                .map_err(|e| format!("{:?}: {}", path, e))
+                .or_print(
+                    logging::Problem::Warning,
+                    "Can't read the word list",
+                )
+        });
+    match text {
+        Some(text) => WordList::parse(&text),
+        None => WordList::parse(resources::WORDS),
+    }
+}
+
+/// Reads whether the user turned gesture typing on
+pub fn load_enabled() -> bool {
+    popover::get_own_settings()
+        .map(|settings| popover::get_boolean(&settings, SETTINGS_KEY))
+        .unwrap_or(false)
+}
+
+/// A letter key, with its center in layout coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub struct Key {
+    pub letter: char,
+    pub center: Point,
+}
+
+fn distance(a: &Point, b: &Point) -> f64 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+}
+
+/// Spreads `count` points evenly along the path
+fn resample(path: &[Point], count: usize) -> Vec<Point> {
+    let length: f64 = path.windows(2)
+        .map(|pair| distance(&pair[0], &pair[1]))
+        .sum();
+    let first = match path.first() {
+        Some(point) => point.clone(),
+        None => return Vec::new(),
+    };
+    if length == 0.0 {
+        return vec![first; count];
+    }
+
+    let step = length / (count - 1) as f64;
+    let mut out = vec![first];
+    // Distance along the path at the start of the current segment
+    let mut travelled = 0.0;
+    for pair in path.windows(2) {
+        let segment = distance(&pair[0], &pair[1]);
+        while out.len() < count
+            && out.len() as f64 * step <= travelled + segment
+        {
+            let t = match segment {
+                s if s == 0.0 => 0.0,
+                s => (out.len() as f64 * step - travelled) / s,
+            };
+            out.push(Point {
+                x: pair[0].x + t * (pair[1].x - pair[0].x),
+                y: pair[0].y + t * (pair[1].y - pair[0].y),
+            });
+        }
+        travelled += segment;
+    }
+    // Rounding may leave the end short
+    let last = path[path.len() - 1].clone();
+    out.resize(count, last);
+    out
+}
+
+fn find_nearest<'a>(keys: &'a [Key], point: &Point) -> Option<&'a Key> {
+    keys.iter().min_by_key(|key| FloatOrd(distance(&key.center, point)))
+}
+
+/// The distance between the closest keys, for scale
+fn find_key_pitch(keys: &[Key]) -> Option<f64> {
+    keys.iter()
+        .flat_map(|a| keys.iter().map(move |b| distance(&a.center, &b.center)))
+        .filter(|d| *d > 0.0)
+        .min_by_key(|d| FloatOrd(*d))
+}
+
+/// The path going through the centers of the word's keys,
+/// or `None` if some letters are missing from the keys.
+fn word_path(keys: &[Key], word: &str) -> Option<Vec<Point>> {
+    word.chars()
+        .map(|c| {
+            keys.iter()
+                .find(|key| key.letter.to_lowercase().eq(c.to_lowercase()))
+                .map(|key| key.center.clone())
+        })
+        .collect()
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Words matching the path, best first.
+/// The first letter must be the one where the path starts.
+/// If that key shows an uppercase letter, so do the words.
+pub fn recognize(words: &WordList, keys: &[Key], path: &[Point]) -> Vec<String> {
+    let start = match path.first().and_then(|point| find_nearest(keys, point)) {
+        Some(key) => key.letter,
+        None => return Vec::new(),
+    };
+    let pitch = match find_key_pitch(keys) {
+        Some(pitch) => pitch,
+        None => return Vec::new(),
+    };
+    let first: String = start.to_lowercase().collect();
+    let drawn = resample(path, SAMPLES);
+
+    let mut found: Vec<(f64, &str)> = words.words.iter()
+        .enumerate()
+        .filter(|(_rank, word)| word.starts_with(&first))
+        .filter_map(|(rank, word)| {
+            let ideal = resample(&word_path(keys, word)?, SAMPLES);
+            let mean = drawn.iter().zip(ideal.iter())
+                .map(|(a, b)| distance(a, b))
+                .sum::<f64>() / SAMPLES as f64 / pitch;
+            Some((mean, rank, word.as_str()))
+        })
+        .filter(|(mean, _rank, _word)| *mean < MAX_DISTANCE)
+        .map(|(mean, rank, word)| {
+            (mean + RANK_WEIGHT * (1.0 + rank as f64).ln(), word)
+        })
+        .collect();
+    found.sort_by_key(|(score, _word)| FloatOrd(*score));
+
+    let mut out: Vec<String> = Vec::new();
+    for (_score, word) in found {
+        let word = match start.is_uppercase() {
+            true => capitalize(word),
+            false => word.into(),
+        };
+        if !out.contains(&word) {
+            out.push(word);
+        }
+        if out.len() == MAX_CANDIDATES {
+            break;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A part of the QWERTY layout, with keys 1 unit apart
+    fn keys(rows: &[&str]) -> Vec<Key> {
+        rows.iter().enumerate()
+            .flat_map(|(y, row)| {
+                row.chars().enumerate().map(move |(x, letter)| Key {
+                    letter,
+                    center: Point {
+                        // Staggered like on a real keyboard
+                        x: x as f64 + y as f64 * 0.5,
+                        y: y as f64,
+                    },
+                })
+            })
+            .collect()
+    }
+
+    fn qwerty() -> Vec<Key> {
+        keys(&["qwertyuiop", "asdfghjkl", "zxcvbnm"])
+    }
+
+    /// Goes through the letters' keys, with some wobble
+    fn swipe(keys: &[Key], letters: &str) -> Vec<Point> {
+        word_path(keys, letters).unwrap()
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| Point {
+                x: p.x + if i % 2 == 0 { 0.2 } else { -0.2 },
+                y: p.y + 0.1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resample_line() {
+        let path = resample(
+            &[Point { x: 0.0, y: 0.0 }, Point { x: 3.0, y: 0.0 }],
+            4,
+        );
+        assert_eq!(
+            path,
+            (0..4).map(|x| Point { x: x as f64, y: 0.0 }).collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn resample_point() {
+        let point = Point { x: 1.0, y: 1.0 };
+        assert_eq!(resample(&[point.clone()], 3), vec![point; 3]);
+        assert_eq!(resample(&[], 3), vec![]);
+    }
+
+    #[test]
+    fn word() {
+        let keys = qwerty();
+        let words = WordList::parse("the\nthey\ntry\nhello\n");
+        assert_eq!(
+            recognize(&words, &keys, &swipe(&keys, "the")).first(),
+            Some(&"the".to_string()),
+        );
+        assert_eq!(
+            recognize(&words, &keys, &swipe(&keys, "helo")),
+            vec!["hello".to_string()],
+        );
+    }
+
+    /// Words not starting where the path starts don't match
+    #[test]
+    fn first_letter() {
+        let keys = qwerty();
+        let words = WordList::parse("try\n");
+        assert_eq!(
+            recognize(&words, &keys, &swipe(&keys, "ery")),
+            Vec::<String>::new(),
+        );
+    }
+
+    /// Paths too far from any word don't match
+    #[test]
+    fn no_match() {
+        let keys = qwerty();
+        let words = WordList::parse("the\n");
+        assert_eq!(
+            recognize(&words, &keys, &swipe(&keys, "tmp")),
+            Vec::<String>::new(),
+        );
+    }
+
+    /// The more frequent word wins when both fit equally
+    #[test]
+    fn frequency() {
+        let keys = qwerty();
+        let words = WordList::parse("tot\ntit\n");
+        let path = vec![
+            Point { x: 4.0, y: 0.0 },
+            Point { x: 7.5, y: 0.0 },
+            Point { x: 4.0, y: 0.0 },
+        ];
+        assert_eq!(
+            recognize(&words, &keys, &path),
+            vec!["tot".to_string(), "tit".to_string()],
+        );
+    }
+
+    #[test]
+    fn uppercase() {
+        let keys = keys(&["QWERTYUIOP", "ASDFGHJKL", "ZXCVBNM"]);
+        let words = WordList::parse("the\n");
+        assert_eq!(
+            recognize(&words, &keys, &swipe(&keys, "THE")),
+            vec!["The".to_string()],
+        );
+    }
+
+    /// Words with letters missing from the keys can't be typed
+    #[test]
+    fn missing_letters() {
+        let keys = qwerty();
+        let words = WordList::parse("tße\n");
+        assert_eq!(
+            recognize(&words, &keys, &swipe(&keys, "the")),
+            Vec::<String>::new(),
+        );
+    }
+
+    /// The shipped word list must be usable
+    #[test]
+    fn builtin() {
+        let words = WordList::parse(resources::WORDS);
+        assert!(words.words.iter().any(|word| word == "the"));
+    }
+}
@@ -0,0 +1,272 @@
+/*! Finding the button the user aimed at.
+ *
+ * Strict hit-testing picks whichever button's rectangle covers the touch.
+ * Fingers are imprecise, so near the edges that's often the neighbour.
+ * Instead, each nearby button gets a score:
+ * how close the touch is to its center, relative to the button size,
+ * and how likely its letter is to come next in the word.
+ *
+ * This is a library module.
+ */
+
+use std::collections::HashMap;
+
+use crate::float_ord::FloatOrd;
+use crate::layout::c::{ Bounds, Point };
+use crate::swipe::WordList;
+
+
+/// How far touches aimed at the center spread,
+/// relative to the button size
+const SPREAD: f64 = 0.3;
+
+/// Even the least likely letter keeps this much of the most likely one's chance,
+/// so that taps close to a button's center always hit it.
+const MIN_LIKELIHOOD: f64 = 0.1;
+
+/// A moving touch stays on the pressed button up to this far outside of it,
+/// relative to the button size
+const HOLD_MARGIN: f64 = 0.25;
+
+/// Settings of the model, coming from the layout
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    /// How far below the aimed at point fingers land, in layout units
+    pub touch_offset: f64,
+}
+
+/// A button which may be chosen
+#[derive(Clone, Debug)]
+pub struct Key<T> {
+    pub id: T,
+    pub bounds: Bounds,
+    /// The letter the button types, if any
+    pub letter: Option<char>,
+}
+
+/// How likely each letter is to come next, relative to the most likely one
+#[derive(Clone, Debug, PartialEq)]
+pub struct Prediction(HashMap<char, f64>);
+
+impl Prediction {
+    /// Guesses the letter following the beginning of a word.
+    /// Words are weighed by their rank in the frequency-ordered list.
+    /// Returns `None` if no word starts that way.
+    pub fn from_words(words: &WordList, prefix: &str) -> Option<Prediction> {
+        let prefix = prefix.to_lowercase();
+        let mut weights: HashMap<char, f64> = HashMap::new();
+        for (rank, word) in words.iter().enumerate() {
+            let next = word.strip_prefix(prefix.as_str())
+                .and_then(|rest| rest.chars().next());
+            if let Some(next) = next {
+                *weights.entry(next).or_insert(0.0) += 1.0 / (1.0 + rank as f64);
+            }
+        }
+        let max = weights.values().cloned().fold(0.0, f64::max);
+        if max == 0.0 {
+            return None;
+        }
+        Some(Prediction(
+            weights.into_iter()
+                .map(|(letter, weight)| (letter, weight / max))
+                .collect()
+        ))
+    }
+
+    fn get(&self, letter: char) -> f64 {
+        let likelihood = letter.to_lowercase().next()
+            .and_then(|letter| self.0.get(&letter))
+            .cloned()
+            .unwrap_or(0.0);
+        MIN_LIKELIHOOD + (1.0 - MIN_LIKELIHOOD) * likelihood
+    }
+}
+
+impl Model {
+    /// Returns the button most likely aimed at.
+    /// Only buttons less than their size away from the touch are considered.
+    pub fn choose<'a, T>(
+        &self,
+        keys: &'a [Key<T>],
+        point: &Point,
+        prediction: Option<&Prediction>,
+    ) -> Option<&'a T> {
+        let aimed = Point { x: point.x, y: point.y - self.touch_offset };
+        keys.iter()
+            .filter_map(|key| {
+                let bounds = &key.bounds;
+                // Nothing can be aimed at an empty button
+                if bounds.width <= 0.0 || bounds.height <= 0.0 {
+                    return None;
+                }
+                let dx = (aimed.x - bounds.x) / bounds.width - 0.5;
+                let dy = (aimed.y - bounds.y) / bounds.height - 0.5;
+                if dx.abs() >= 1.0 || dy.abs() >= 1.0 {
+                    return None;
+                }
+                // Logarithm of a normal distribution around the center
+                let spatial = -(dx * dx + dy * dy) / (2.0 * SPREAD * SPREAD);
+                // Only letters compete on likelihood.
+                // Other buttons don't get penalized.
+                let language = match (prediction, key.letter) {
+                    (Some(prediction), Some(letter)) => prediction.get(letter).ln(),
+                    _ => 0.0,
+                };
+                Some((spatial + language, &key.id))
+            })
+            .max_by_key(|(score, _)| FloatOrd(*score))
+            .map(|(_score, id)| id)
+    }
+
+    /// Whether a touch which moved keeps aiming at the pressed button.
+    /// Gaps and the edges of the neighbours stay on it,
+    /// so that a trembling finger doesn't switch buttons.
+    pub fn holds(&self, bounds: &Bounds, point: &Point) -> bool {
+        let aimed = Point { x: point.x, y: point.y - self.touch_offset };
+        let dx = (aimed.x - bounds.x) / bounds.width - 0.5;
+        let dy = (aimed.y - bounds.y) / bounds.height - 0.5;
+        dx.abs() < 0.5 + HOLD_MARGIN && dy.abs() < 0.5 + HOLD_MARGIN
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A row of square keys, 10 units wide
+    fn row(letters: &str) -> Vec<Key<char>> {
+        letters.chars().enumerate()
+            .map(|(i, letter)| Key {
+                id: letter,
+                bounds: Bounds {
+                    x: i as f64 * 10.0,
+                    y: 0.0,
+                    width: 10.0,
+                    height: 10.0,
+                },
+                letter: Some(letter),
+            })
+            .collect()
+    }
+
+    fn no_offset() -> Model {
+        Model { touch_offset: 0.0 }
+    }
+
+    #[test]
+    fn nearest() {
+        let keys = row("qwe");
+        let model = no_offset();
+        assert_eq!(
+            model.choose(&keys, &Point { x: 14.0, y: 5.0 }, None),
+            Some(&'w'),
+        );
+        assert_eq!(
+            model.choose(&keys, &Point { x: 9.9, y: 5.0 }, None),
+            Some(&'q'),
+        );
+    }
+
+    /// Touches far from any key don't hit anything
+    #[test]
+    fn outside() {
+        let keys = row("qwe");
+        assert_eq!(
+            no_offset().choose(&keys, &Point { x: 15.0, y: 25.0 }, None),
+            None,
+        );
+    }
+
+    /// Fingers land below the target
+    #[test]
+    fn offset() {
+        let mut keys = row("q");
+        keys.push(Key {
+            id: 'a',
+            bounds: Bounds { x: 0.0, y: 10.0, width: 10.0, height: 10.0 },
+            letter: Some('a'),
+        });
+        let point = Point { x: 5.0, y: 11.0 };
+        assert_eq!(no_offset().choose(&keys, &point, None), Some(&'a'));
+        assert_eq!(
+            Model { touch_offset: 3.0 }.choose(&keys, &point, None),
+            Some(&'q'),
+        );
+    }
+
+    /// Near the edge, the likely letter wins
+    #[test]
+    fn likely_edge() {
+        let keys = row("qwe");
+        let words = WordList::parse("the\nthe\nthe\ntwo\n");
+        let prediction = Prediction::from_words(&words, "th").unwrap();
+        let point = Point { x: 19.0, y: 5.0 };
+        assert_eq!(no_offset().choose(&keys, &point, None), Some(&'w'));
+        assert_eq!(
+            no_offset().choose(&keys, &point, Some(&prediction)),
+            Some(&'e'),
+        );
+    }
+
+    /// Close to the center, the unlikely letter still wins
+    #[test]
+    fn unlikely_center() {
+        let keys = row("qwe");
+        let words = WordList::parse("the\n");
+        let prediction = Prediction::from_words(&words, "th").unwrap();
+        assert_eq!(
+            no_offset().choose(&keys, &Point { x: 16.0, y: 5.0 }, Some(&prediction)),
+            Some(&'w'),
+        );
+    }
+
+    /// Buttons typing no letters are not penalized
+    #[test]
+    fn not_letters() {
+        let mut keys = row("q");
+        keys[0].letter = None;
+        keys.extend(row("qw").into_iter().skip(1));
+        let words = WordList::parse("w\n");
+        let prediction = Prediction::from_words(&words, "").unwrap();
+        assert_eq!(
+            no_offset().choose(&keys, &Point { x: 9.0, y: 5.0 }, Some(&prediction)),
+            Some(&'q'),
+        );
+    }
+
+    #[test]
+    fn prediction() {
+        let words = WordList::parse("the\nto\ntwo\n");
+        let prediction = Prediction::from_words(&words, "T").unwrap();
+        assert_eq!(prediction.get('h'), 1.0);
+        assert!(prediction.get('o') > prediction.get('w'));
+        assert_eq!(prediction.get('x'), MIN_LIKELIHOOD);
+        assert_eq!(Prediction::from_words(&words, "x"), None);
+    }
+
+    /// Buttons without a size don't get in the way
+    #[test]
+    fn empty() {
+        let mut keys = row("qw");
+        keys[0].bounds.width = 0.0;
+        assert_eq!(
+            no_offset().choose(&keys, &Point { x: 0.0, y: 5.0 }, None),
+            None,
+        );
+        assert_eq!(
+            no_offset().choose(&keys, &Point { x: 12.0, y: 5.0 }, None),
+            Some(&'w'),
+        );
+    }
+
+    /// The pressed key keeps the touch a little beyond its edge
+    #[test]
+    fn hold() {
+        let keys = row("qw");
+        let model = no_offset();
+        let point = Point { x: 11.0, y: 5.0 };
+        assert_eq!(model.choose(&keys, &point, None), Some(&'w'));
+        assert!(model.holds(&keys[0].bounds, &point));
+        assert!(!model.holds(&keys[0].bounds, &Point { x: 13.0, y: 5.0 }));
+    }
+}
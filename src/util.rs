@@ -252,6 +252,24 @@ impl PartialEq for Rational<i32> {
 
 impl Eq for Rational<i32> {}
 
+impl PartialEq for Rational<u32> {
+    fn eq(&self, other: &Self) -> bool {
+        self.denominator as u64 * other.numerator as u64
+            == other.denominator as u64 * self.numerator as u64
+    }
+}
+
+impl Eq for Rational<u32> {}
+
+impl From<u32> for Rational<u32> {
+    fn from(v: u32) -> Self {
+        Self {
+            numerator: v,
+            denominator: 1,
+        }
+    }
+}
+
 impl Ord for Rational<i32> {
     fn cmp(&self, other: &Self) -> Ordering {
         // Using 64-bit values to make overflows unlikely.
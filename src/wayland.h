@@ -6,6 +6,7 @@
 #include "wlr-layer-shell-unstable-v1-client-protocol.h"
 #include "virtual-keyboard-unstable-v1-client-protocol.h"
 #include "input-method-unstable-v2-client-protocol.h"
+#include "fractional-scale-v1-client-protocol.h"
 
 #include "outputs.h"
 
@@ -14,6 +15,8 @@ struct squeek_wayland {
     struct zwlr_layer_shell_v1 *layer_shell;
     struct zwp_virtual_keyboard_manager_v1 *virtual_keyboard_manager;
     struct zwp_input_method_manager_v2 *input_method_manager;
+    // optional
+    struct wp_fractional_scale_manager_v1 *fractional_scale_manager;
     struct squeek_outputs *outputs;
     struct wl_seat *seat;
     // objects